@@ -4,13 +4,8 @@
 
 extern crate alloc;
 
-#[cfg(feature = "nvs")]
-use embassy_sync::blocking_mutex::Mutex;
 use embassy_time::Duration;
 
-#[cfg(feature = "nvs")]
-use embedded_svc::storage::Storage;
-
 use esp_idf_hal::adc::*;
 use esp_idf_hal::gpio::*;
 use esp_idf_hal::reset::WakeupReason;
@@ -22,9 +17,15 @@ use esp_idf_svc::nvs::EspDefaultNvsPartition;
 
 use esp_idf_sys::esp;
 
-use ruwm::button;
+use ruwm::battery::BatteryConfig;
+use ruwm::button::{self, ButtonConfig};
+use ruwm::emergency::EmergencyPolicy;
+use ruwm::leak_sensor::{LeakSensorConfig, LeakSensorInput};
+use ruwm::screen::PowerConfig;
+use ruwm::sleep::SleepPolicy;
 use ruwm::spawn;
-use ruwm::wm::WaterMeterState;
+use ruwm::valve::ValveConfig;
+use ruwm::wm::MeterConfig;
 
 use crate::errors::*;
 use crate::peripherals::{ButtonsPeripherals, PulseCounterPeripherals};
@@ -38,7 +39,14 @@ mod ulp_pulse_counter;
 #[cfg(all(feature = "ulp", not(any(esp32, esp32s2, esp32s3))))]
 compile_error!("Feature `ulp` is supported only on esp32, esp32s2 and esp32s3");
 
-const SLEEP_TIME: Duration = Duration::from_secs(30);
+#[cfg(all(feature = "ulp", feature = "quadrature"))]
+compile_error!("Features `ulp` and `quadrature` are mutually exclusive");
+
+const SLEEP_POLICY: SleepPolicy = SleepPolicy::new();
+const LEAK_SENSORS: &[LeakSensorConfig] = &[LeakSensorConfig {
+    name: "Probe",
+    input: LeakSensorInput::Probe(button::PressedLevel::Low),
+}];
 const MQTT_MAX_TOPIC_LEN: usize = 64;
 
 // Make sure that the firmware will contain
@@ -57,7 +65,7 @@ fn main() -> Result<(), InitError> {
 
     run(wakeup_reason)?;
 
-    sleep()?;
+    sleep(ruwm::sleep::plan(&SLEEP_POLICY).wake_interval)?;
 
     unreachable!()
 }
@@ -79,16 +87,16 @@ fn init() -> Result<(), InitError> {
     Ok(())
 }
 
-fn sleep() -> Result<(), InitError> {
+fn sleep(wake_interval: Duration) -> Result<(), InitError> {
     unsafe {
         #[cfg(feature = "ulp")]
         esp!(esp_idf_sys::esp_sleep_enable_ulp_wakeup())?;
 
         esp!(esp_idf_sys::esp_sleep_enable_timer_wakeup(
-            SLEEP_TIME.as_micros() as u64
+            wake_interval.as_micros() as u64
         ))?;
 
-        log::info!("Going to sleep");
+        log::info!("Going to sleep for {}s", wake_interval.as_secs());
 
         esp_idf_sys::esp_deep_sleep_start();
     }
@@ -97,11 +105,6 @@ fn sleep() -> Result<(), InitError> {
 fn run(wakeup_reason: WakeupReason) -> Result<(), InitError> {
     let peripherals = peripherals::SystemPeripherals::take();
 
-    // Valve pins
-
-    let (valve_power_pin, valve_open_pin, valve_close_pin) =
-        services::valve_pins(peripherals.valve, wakeup_reason)?;
-
     // Deep sleep wakeup init
 
     mark_wakeup_pins(&peripherals.pulse_counter, &peripherals.buttons)?;
@@ -113,32 +116,22 @@ fn run(wakeup_reason: WakeupReason) -> Result<(), InitError> {
 
     // Storage
 
-    #[cfg(feature = "nvs")]
-    let (wm_state, storage) = {
-        let storage = services::storage(nvs_default_partition.clone())?;
+    let storage = services::storage(nvs_default_partition.clone())?;
 
-        if let Some(wm_state) = storage
-            .lock(|storage| storage.borrow().get::<WaterMeterState>("wm-state"))
-            .unwrap()
-        {
-            (wm_state, storage)
-        } else {
-            log::warn!("No WM edge count found in NVS, assuming new device");
+    ruwm::wm::restore(storage);
+    ruwm::wm_stats::restore(storage);
+    ruwm::clock::restore(storage);
+    ruwm::schedule::restore(storage);
+    ruwm::budget::restore(storage);
+    ruwm::i18n::restore(storage);
+    ruwm::settings::restore(storage);
+    ruwm::battery::restore(storage);
+    ruwm::power::restore(storage);
 
-            (Default::default(), storage)
-        }
-    };
+    // Valve pins, after the settings as an emergency close needs the valve turn ticks
 
-    #[cfg(not(feature = "nvs"))]
-    let wm_state: WaterMeterState = Default::default();
-
-    unsafe {
-        services::RTC_MEMORY.wm = wm_state;
-
-        ruwm::valve::STATE.set(services::RTC_MEMORY.valve);
-        ruwm::wm::STATE.set(services::RTC_MEMORY.wm);
-        ruwm::wm_stats::STATE.set(services::RTC_MEMORY.wm_stats);
-    }
+    let (valve_power_pin, valve_open_pin, valve_close_pin) =
+        services::valve_pins(peripherals.valve, wakeup_reason)?;
 
     // Pulse counter
 
@@ -150,49 +143,74 @@ fn run(wakeup_reason: WakeupReason) -> Result<(), InitError> {
 
     // Wifi
 
-    let (wifi, wifi_notif) = services::wifi(
+    let (wifi, wifi_info, wifi_notif) = services::wifi(
         peripherals.modem,
         sysloop.clone(),
         Some(nvs_default_partition.clone()),
     )?;
 
+    // Clock
+
+    let clock = services::clock()?;
+
     // Httpd
 
     let (_httpd, ws_acceptor) = services::httpd()?;
 
     // Mqtt
 
-    let (mqtt_topic_prefix, mqtt_client, mqtt_conn) = services::mqtt()?;
+    let (mqtt_topic_prefix, mqtt_broker, mqtt_client, mqtt_conn) = services::mqtt()?;
+
+    // Firmware update
+
+    let ota = services::ota();
 
     // High-prio tasks
 
-    let mut high_prio_executor = EspExecutor::<16, _>::new();
-    let mut high_prio_tasks = heapless::Vec::<_, 16>::new();
+    let mut high_prio_executor = EspExecutor::<24, _>::new();
+    let mut high_prio_tasks = heapless::Vec::<_, 24>::new();
 
     spawn::high_prio(
         &mut high_prio_executor,
         &mut high_prio_tasks,
-        valve_power_pin,
-        valve_open_pin,
-        valve_close_pin,
-        |state| unsafe {
-            services::RTC_MEMORY.valve = state;
-        },
-        pulse_counter,
-        pulse_wakeup,
-        |state| unsafe {
-            services::RTC_MEMORY.wm = state;
-        },
-        |state| unsafe {
-            services::RTC_MEMORY.wm_stats = state;
-        },
+        [(
+            valve_power_pin,
+            valve_open_pin,
+            valve_close_pin,
+            ValveConfig::new(),
+        )],
+        EmergencyPolicy::new(),
+        storage,
+        [(pulse_counter, pulse_wakeup, MeterConfig::new())],
         AdcDriver::new(peripherals.battery.adc, &AdcConfig::new().calibration(true))?,
         AdcChannelDriver::<_, Atten0dB<_>>::new(peripherals.battery.voltage)?,
         PinDriver::input(peripherals.battery.power)?,
+        BatteryConfig::new(),
         false,
         services::button(peripherals.buttons.button1, &button::BUTTON1_PIN_EDGE)?,
         services::button(peripherals.buttons.button2, &button::BUTTON2_PIN_EDGE)?,
         services::button(peripherals.buttons.button3, &button::BUTTON3_PIN_EDGE)?,
+        ButtonConfig::new(),
+        SLEEP_POLICY,
+    )?;
+
+    // The probe is the first of the leak sensors
+    let leak_probe = peripherals
+        .leak_probe
+        .map(|pin| services::leak_probe(pin, 0))
+        .transpose()?;
+
+    let leak_sensors: &[LeakSensorConfig] = if leak_probe.is_some() {
+        LEAK_SENSORS
+    } else {
+        &[]
+    };
+
+    spawn::leak_sensors(
+        &mut high_prio_executor,
+        &mut high_prio_tasks,
+        leak_sensors,
+        leak_probe.map(|pin| (0, pin)),
     )?;
 
     // Mid-prio tasks
@@ -208,21 +226,22 @@ fn run(wakeup_reason: WakeupReason) -> Result<(), InitError> {
 
     let display_peripherals = peripherals.display;
 
-    let mid_prio_execution = services::schedule::<8, _>(50000, move || {
+    let mid_prio_execution = services::schedule::<12, _>(50000, move || {
         let mut executor = EspExecutor::new();
         let mut tasks = heapless::Vec::new();
 
+        let (display, backlight) = services::display(display_peripherals).unwrap();
+
         spawn::mid_prio(
             &mut executor,
             &mut tasks,
-            services::display(display_peripherals).unwrap(),
-            move |_new_state| {
-                #[cfg(feature = "nvs")]
-                flash_wm_state(storage, _new_state);
-            },
+            display,
+            backlight,
+            PowerConfig::new(),
+            clock,
         )?;
 
-        spawn::wifi(&mut executor, &mut tasks, wifi, wifi_notif)?;
+        spawn::wifi(&mut executor, &mut tasks, wifi, wifi_info, wifi_notif)?;
 
         spawn::mqtt_receive(&mut executor, &mut tasks, mqtt_conn)?;
 
@@ -248,11 +267,15 @@ fn run(wakeup_reason: WakeupReason) -> Result<(), InitError> {
             &mut executor,
             &mut tasks,
             mqtt_topic_prefix,
+            mqtt_broker,
             mqtt_client,
         )?;
 
         spawn::ws(&mut executor, &mut tasks, ws_acceptor)?;
 
+        // The update is blocking, so it is the low-prio tasks which are stalled while downloading
+        spawn::update(&mut executor, &mut tasks, ota)?;
+
         Ok((executor, tasks))
     });
 
@@ -274,26 +297,6 @@ fn run(wakeup_reason: WakeupReason) -> Result<(), InitError> {
     Ok(())
 }
 
-#[cfg(feature = "nvs")]
-fn flash_wm_state<S>(
-    storage: &'static Mutex<
-        impl embassy_sync::blocking_mutex::raw::RawMutex,
-        core::cell::RefCell<S>,
-    >,
-    new_state: WaterMeterState,
-) where
-    S: Storage,
-{
-    ruwm::log_err!(storage.lock(|storage| {
-        let old_state = storage.borrow().get("wm-state")?;
-        if old_state != Some(new_state) {
-            storage.borrow_mut().set("wm-state", &new_state)?;
-        }
-
-        Ok::<_, S::Error>(())
-    }));
-}
-
 fn mark_wakeup_pins(
     pulse_counter_peripherals: &PulseCounterPeripherals<impl RTCPin + InputPin>,
     buttons_peripherals: &ButtonsPeripherals<
@@ -1,5 +1,6 @@
 use esp_idf_hal::adc::*;
 use esp_idf_hal::gpio::*;
+use esp_idf_hal::ledc::{CHANNEL0, TIMER0};
 use esp_idf_hal::modem::Modem;
 use esp_idf_hal::peripherals::Peripherals;
 use esp_idf_hal::spi::*;
@@ -9,6 +10,8 @@ pub struct SystemPeripherals<P, ADC, V, B1, B2, B3, SPI> {
     pub valve: ValvePeripherals,
     pub battery: BatteryPeripherals<ADC, V>,
     pub buttons: ButtonsPeripherals<B1, B2, B3>,
+    /// Reads low when wet
+    pub leak_probe: Option<AnyIOPin>,
     pub display: DisplaySpiPeripherals<SPI>,
     pub modem: Modem,
 }
@@ -21,6 +24,8 @@ impl SystemPeripherals<Gpio33, ADC1, Gpio36, Gpio2, Gpio4, Gpio32, SPI2> {
         SystemPeripherals {
             pulse_counter: PulseCounterPeripherals {
                 pulse: peripherals.pins.gpio33,
+                #[cfg(feature = "quadrature")]
+                pulse_b: peripherals.pins.gpio21.into(),
                 #[cfg(feature = "ulp")]
                 ulp: peripherals.ulp,
             },
@@ -39,9 +44,12 @@ impl SystemPeripherals<Gpio33, ADC1, Gpio36, Gpio2, Gpio4, Gpio32, SPI2> {
                 button2: peripherals.pins.gpio4,
                 button3: peripherals.pins.gpio32,
             },
+            leak_probe: Some(peripherals.pins.gpio22.into()),
             display: DisplaySpiPeripherals {
                 control: DisplayControlPeripherals {
                     backlight: Some(peripherals.pins.gpio15.into()),
+                    backlight_timer: peripherals.ledc.timer0,
+                    backlight_channel: peripherals.ledc.channel0,
                     dc: peripherals.pins.gpio18.into(),
                     rst: peripherals.pins.gpio19.into(),
                 },
@@ -63,6 +71,8 @@ impl SystemPeripherals<Gpio1, ADC1, Gpio9, Gpio2, Gpio4, Gpio12, SPI2> {
         SystemPeripherals {
             pulse_counter: PulseCounterPeripherals {
                 pulse: peripherals.pins.gpio1,
+                #[cfg(feature = "quadrature")]
+                pulse_b: peripherals.pins.gpio10.into(),
                 #[cfg(feature = "ulp")]
                 ulp: peripherals.ulp,
             },
@@ -81,9 +91,12 @@ impl SystemPeripherals<Gpio1, ADC1, Gpio9, Gpio2, Gpio4, Gpio12, SPI2> {
                 button2: peripherals.pins.gpio4,
                 button3: peripherals.pins.gpio12,
             },
+            leak_probe: Some(peripherals.pins.gpio11.into()),
             display: DisplaySpiPeripherals {
                 control: DisplayControlPeripherals {
                     backlight: Some(peripherals.pins.gpio15.into()),
+                    backlight_timer: peripherals.ledc.timer0,
+                    backlight_channel: peripherals.ledc.channel0,
                     dc: peripherals.pins.gpio18.into(),
                     rst: peripherals.pins.gpio19.into(),
                 },
@@ -105,6 +118,8 @@ impl SystemPeripherals<Gpio1, ADC1, Gpio0, Gpio2, Gpio3, Gpio4, SPI2> {
         SystemPeripherals {
             pulse_counter: PulseCounterPeripherals {
                 pulse: peripherals.pins.gpio1,
+                #[cfg(feature = "quadrature")]
+                pulse_b: peripherals.pins.gpio19.into(),
             },
             valve: ValvePeripherals {
                 power: peripherals.pins.gpio6.into(),
@@ -121,9 +136,13 @@ impl SystemPeripherals<Gpio1, ADC1, Gpio0, Gpio2, Gpio3, Gpio4, SPI2> {
                 button2: peripherals.pins.gpio3,
                 button3: peripherals.pins.gpio4,
             },
+            // All other pins are taken by the flash or the console
+            leak_probe: None,
             display: DisplaySpiPeripherals {
                 control: DisplayControlPeripherals {
                     backlight: Some(peripherals.pins.gpio9.into()),
+                    backlight_timer: peripherals.ledc.timer0,
+                    backlight_channel: peripherals.ledc.channel0,
                     dc: peripherals.pins.gpio10.into(),
                     rst: peripherals.pins.gpio18.into(),
                 },
@@ -139,6 +158,9 @@ impl SystemPeripherals<Gpio1, ADC1, Gpio0, Gpio2, Gpio3, Gpio4, SPI2> {
 
 pub struct PulseCounterPeripherals<P> {
     pub pulse: P,
+    /// The second sensor of a quadrature meter; only the first one wakes the device up
+    #[cfg(feature = "quadrature")]
+    pub pulse_b: AnyIOPin,
     #[cfg(feature = "ulp")]
     pub ulp: esp_idf_hal::ulp::ULP,
 }
@@ -163,6 +185,8 @@ pub struct ButtonsPeripherals<B1, B2, B3> {
 
 pub struct DisplayControlPeripherals {
     pub backlight: Option<AnyOutputPin>,
+    pub backlight_timer: TIMER0,
+    pub backlight_channel: CHANNEL0,
     pub dc: AnyOutputPin,
     pub rst: AnyOutputPin,
 }
@@ -1,18 +1,22 @@
 use core::cell::RefCell;
-use core::fmt::Debug;
-use core::mem;
+use core::fmt::{Debug, Write as _};
 
 extern crate alloc;
 
 use edge_frame::assets::serve::AssetMetadata;
 
+use embassy_sync::blocking_mutex::raw::RawMutex;
 use embassy_sync::blocking_mutex::Mutex;
 use embassy_time::Duration;
 
 use embedded_hal::digital::v2::OutputPin as EHOutputPin;
 
+use embedded_svc::http::client::Client as HttpClient;
 use embedded_svc::http::server::Method;
+use embedded_svc::http::{Headers, Status};
+use embedded_svc::io::{Read, Write};
 use embedded_svc::mqtt::client::asynch::{Client, Connection, Publish};
+use embedded_svc::storage::{RawStorage, StorageBase};
 use embedded_svc::utils::asyncify::Asyncify;
 use embedded_svc::wifi::{AuthMethod, ClientConfiguration, Configuration, Wifi};
 use embedded_svc::ws::asynch::server::Acceptor;
@@ -20,6 +24,8 @@ use embedded_svc::ws::asynch::server::Acceptor;
 use esp_idf_hal::delay;
 use esp_idf_hal::delay::FreeRtos;
 use esp_idf_hal::gpio::*;
+use esp_idf_hal::ledc::config::TimerConfig;
+use esp_idf_hal::ledc::{LedcDriver, LedcTimerDriver};
 use esp_idf_hal::modem::WifiModemPeripheral;
 use esp_idf_hal::peripheral::Peripheral;
 use esp_idf_hal::prelude::*;
@@ -27,14 +33,18 @@ use esp_idf_hal::reset::WakeupReason;
 use esp_idf_hal::spi::*;
 use esp_idf_hal::task::embassy_sync::EspRawMutex;
 
+use esp_idf_svc::errors::EspIOError;
 use esp_idf_svc::eventloop::EspSystemEventLoop;
+use esp_idf_svc::http::client::EspHttpConnection;
 use esp_idf_svc::http::server::ws::EspHttpWsProcessor;
 use esp_idf_svc::http::server::EspHttpServer;
 use esp_idf_svc::mqtt::client::{EspMqttClient, MqttClientConfiguration};
-use esp_idf_svc::nvs::EspDefaultNvsPartition;
+use esp_idf_svc::nvs::{EspDefaultNvs, EspDefaultNvsPartition, EspNvs};
+use esp_idf_svc::ota::EspOta;
+use esp_idf_svc::sntp::EspSntp;
 use esp_idf_svc::wifi::{EspWifi, WifiEvent, WifiWait};
 
-use esp_idf_sys::EspError;
+use esp_idf_sys::{esp, EspError};
 
 use gfx_xtra::draw_target::{Flushable, OwnedDrawTargetExt};
 
@@ -45,13 +55,16 @@ use edge_executor::*;
 use channel_bridge::{asynch::pubsub, asynch::*, notification::Notification};
 
 use ruwm::button::PressedLevel;
+use ruwm::clock::{Clock, SystemClock};
+use ruwm::leak_sensor::{self, LeakSensorId};
 use ruwm::mqtt::{MessageParser, MqttCommand};
 use ruwm::pulse_counter::PulseCounter;
 use ruwm::pulse_counter::PulseWakeup;
-use ruwm::screen::Color;
-use ruwm::valve::{self, ValveState};
-use ruwm::wm::WaterMeterState;
-use ruwm::wm_stats::WaterMeterStatsState;
+use ruwm::screen::{Backlight, Color};
+use ruwm::storage::Storage;
+use ruwm::update::{Ota, VERSION_MAX_LEN};
+use ruwm::valve;
+use ruwm::wifi::WifiInfo;
 use ruwm::ws;
 
 use crate::errors::*;
@@ -60,27 +73,21 @@ use crate::peripherals::{DisplaySpiPeripherals, PulseCounterPeripherals, ValvePe
 const SSID: &str = env!("RUWM_WIFI_SSID");
 const PASS: &str = env!("RUWM_WIFI_PASS");
 
+/// The location of the `version` and `firmware.bin` files; updates are disabled when not set
+const OTA_URL: Option<&str> = option_env!("RUWM_OTA_URL");
+
 const ASSETS: assets::serve::Assets = edge_frame::assets!("RUWM_WEB");
 
-#[derive(Default)]
-pub struct RtcMemory {
-    pub valve: Option<ValveState>,
-    pub wm: WaterMeterState,
-    pub wm_stats: WaterMeterStatsState,
-}
+/// The address of the device on its own access point, which is the ESP-IDF default
+const PROVISIONING_IP: [u8; 4] = [192, 168, 71, 1];
 
-impl RtcMemory {
-    pub const fn new() -> Self {
-        Self {
-            valve: None,
-            wm: WaterMeterState::new(),
-            wm_stats: WaterMeterStatsState::new(),
-        }
-    }
-}
-
-#[cfg_attr(feature = "rtc-mem", link_section = ".rtc.data.rtc_memory")]
-pub static mut RTC_MEMORY: RtcMemory = RtcMemory::new();
+const PROVISIONING_FORM: &str = "<!DOCTYPE html><html><head><title>Water Meter</title>\
+    <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\"></head><body>\
+    <form method=\"post\" action=\"/provision\">\
+    <p><label>SSID <input name=\"ssid\" maxlength=\"32\" required></label></p>\
+    <p><label>Password <input name=\"password\" type=\"password\" maxlength=\"64\"></label></p>\
+    <p><input type=\"submit\" value=\"Connect\"></p>\
+    </form></body></html>";
 
 pub fn valve_pins(
     peripherals: ValvePeripherals,
@@ -108,62 +115,38 @@ pub fn valve_pins(
     Ok((power, open, close))
 }
 
-#[cfg(feature = "nvs")]
 pub fn storage(
     partition: EspDefaultNvsPartition,
-) -> Result<
-    &'static Mutex<
-        impl embassy_sync::blocking_mutex::raw::RawMutex,
-        RefCell<impl embedded_svc::storage::Storage>,
-    >,
-    InitError,
-> {
-    const POSTCARD_BUF_SIZE: usize = 500;
+) -> Result<&'static Mutex<impl RawMutex, RefCell<impl Storage>>, InitError> {
+    struct NvsStorage(EspDefaultNvs);
 
-    struct PostcardSerDe;
+    impl Storage for NvsStorage {
+        type Error = EspError;
 
-    impl embedded_svc::storage::SerDe for PostcardSerDe {
-        type Error = postcard::Error;
+        fn load<'a>(&self, key: &str, buf: &'a mut [u8]) -> Result<Option<&'a [u8]>, Self::Error> {
+            self.0.get_raw(key, buf)
+        }
 
-        fn serialize<'a, T>(&self, slice: &'a mut [u8], value: &T) -> Result<&'a [u8], Self::Error>
-        where
-            T: serde::Serialize,
-        {
-            postcard::to_slice(value, slice).map(|r| &*r)
+        fn store(&mut self, key: &str, data: &[u8]) -> Result<(), Self::Error> {
+            self.0.set_raw(key, data).map(|_| ())
         }
 
-        fn deserialize<T>(&self, slice: &[u8]) -> Result<T, Self::Error>
-        where
-            T: serde::de::DeserializeOwned,
-        {
-            postcard::from_bytes(slice)
+        fn remove(&mut self, key: &str) -> Result<(), Self::Error> {
+            StorageBase::remove(&mut self.0, key).map(|_| ())
         }
     }
 
-    static STORAGE: static_cell::StaticCell<
-        Mutex<
-            EspRawMutex,
-            RefCell<
-                embedded_svc::storage::StorageImpl<
-                    { POSTCARD_BUF_SIZE },
-                    esp_idf_svc::nvs::EspDefaultNvs,
-                    PostcardSerDe,
-                >,
-            >,
-        >,
-    > = static_cell::StaticCell::new();
-
-    let storage = &*STORAGE.init(Mutex::new(RefCell::new(
-        embedded_svc::storage::StorageImpl::new(
-            esp_idf_svc::nvs::EspNvs::new(partition, "WM", true)?,
-            PostcardSerDe,
-        ),
-    )));
+    static STORAGE: static_cell::StaticCell<Mutex<EspRawMutex, RefCell<NvsStorage>>> =
+        static_cell::StaticCell::new();
+
+    let storage = &*STORAGE.init(Mutex::new(RefCell::new(NvsStorage(EspNvs::new(
+        partition, "WM", true,
+    )?))));
 
     Ok(storage)
 }
 
-#[cfg(not(feature = "ulp"))]
+#[cfg(not(any(feature = "ulp", feature = "quadrature")))]
 pub fn pulse(
     peripherals: PulseCounterPeripherals<impl RTCPin + InputPin + OutputPin>,
 ) -> Result<(impl PulseCounter, impl PulseWakeup), InitError> {
@@ -179,6 +162,26 @@ pub fn pulse(
     Ok((pulse_counter, ()))
 }
 
+#[cfg(feature = "quadrature")]
+pub fn pulse(
+    peripherals: PulseCounterPeripherals<impl RTCPin + InputPin + OutputPin>,
+) -> Result<(impl PulseCounter, impl PulseWakeup), InitError> {
+    static PULSE_SIGNAL: Notification = Notification::new();
+
+    let pulse_counter = ruwm::pulse_counter::QuadraturePulseCounter::new(
+        subscribe_pin_edges(peripherals.pulse, InterruptType::AnyEdge, || {
+            PULSE_SIGNAL.notify()
+        })?,
+        subscribe_pin_edges(peripherals.pulse_b, InterruptType::AnyEdge, || {
+            PULSE_SIGNAL.notify()
+        })?,
+        PressedLevel::Low,
+        &PULSE_SIGNAL,
+    );
+
+    Ok((pulse_counter, ()))
+}
+
 #[cfg(feature = "ulp")]
 pub fn pulse(
     peripherals: PulseCounterPeripherals<impl RTCPin + InputPin + OutputPin>,
@@ -202,18 +205,56 @@ pub fn button<'d, P: InputPin + OutputPin>(
     subscribe_pin(pin, move || notification.notify())
 }
 
+pub fn leak_probe<'d, P: InputPin + OutputPin>(
+    pin: impl Peripheral<P = P> + 'd,
+    sensor: LeakSensorId,
+) -> Result<impl embedded_hal::digital::v2::InputPin<Error = impl Debug + 'd> + 'd, InitError> {
+    // Both getting wet and drying up matter
+    subscribe_pin_edges(pin, InterruptType::AnyEdge, move || {
+        leak_sensor::PIN_EDGES[sensor as usize].notify()
+    })
+}
+
 pub fn display(
     peripherals: DisplaySpiPeripherals<impl Peripheral<P = impl SpiAnyPins + 'static> + 'static>,
-) -> Result<impl Flushable<Color = Color, Error = impl Debug + 'static> + 'static, InitError> {
-    if let Some(backlight) = peripherals.control.backlight {
-        let mut backlight = PinDriver::output(backlight)?;
+) -> Result<
+    (
+        impl Flushable<Color = Color, Error = impl Debug + 'static> + 'static,
+        impl Backlight + 'static,
+    ),
+    InitError,
+> {
+    struct PwmBacklight(Option<LedcDriver<'static>>);
+
+    impl Backlight for PwmBacklight {
+        type Error = EspError;
 
-        backlight.set_drive_strength(DriveStrength::I40mA)?;
-        backlight.set_high()?;
+        fn set_brightness(&mut self, percentage: u8) -> Result<(), Self::Error> {
+            if let Some(driver) = self.0.as_mut() {
+                let max_duty = driver.get_max_duty();
 
-        mem::forget(backlight); // TODO: For now
+                driver.set_duty(max_duty * percentage as u32 / 100)?;
+            }
+
+            Ok(())
+        }
     }
 
+    let backlight = if let Some(backlight) = peripherals.control.backlight {
+        let timer = LedcTimerDriver::new(
+            peripherals.control.backlight_timer,
+            &TimerConfig::new().frequency(25.kHz().into()),
+        )?;
+
+        Some(LedcDriver::new(
+            peripherals.control.backlight_channel,
+            timer,
+            backlight,
+        )?)
+    } else {
+        None
+    };
+
     let baudrate = 26.MHz().into();
     //let baudrate = 40.MHz().into();
 
@@ -278,29 +319,71 @@ pub fn display(
 
     let display = display.owned_noop_flushing().owned_color_converted();
 
-    Ok(display)
+    Ok((display, PwmBacklight(backlight)))
 }
 
 pub fn wifi<'d>(
     modem: impl Peripheral<P = impl WifiModemPeripheral + 'd> + 'd,
     mut sysloop: EspSystemEventLoop,
     partition: Option<EspDefaultNvsPartition>,
-) -> Result<(impl Wifi + 'd, impl Receiver<Data = WifiEvent>), InitError> {
+) -> Result<
+    (
+        impl Wifi + 'd,
+        impl WifiInfo,
+        impl Receiver<Data = WifiEvent>,
+    ),
+    InitError,
+> {
+    struct StaInfo;
+
+    impl WifiInfo for StaInfo {
+        fn rssi(&self) -> Option<i8> {
+            let mut ap_info: esp_idf_sys::wifi_ap_record_t = Default::default();
+
+            esp!(unsafe { esp_idf_sys::esp_wifi_sta_get_ap_info(&mut ap_info) })
+                .ok()
+                .map(|_| ap_info.rssi)
+        }
+
+        fn ip(&self) -> Option<[u8; 4]> {
+            let netif = unsafe {
+                esp_idf_sys::esp_netif_get_handle_from_ifkey(b"WIFI_STA_DEF\0".as_ptr() as _)
+            };
+
+            if netif.is_null() {
+                return None;
+            }
+
+            let mut ip_info: esp_idf_sys::esp_netif_ip_info_t = Default::default();
+
+            esp!(unsafe { esp_idf_sys::esp_netif_get_ip_info(netif, &mut ip_info) })
+                .ok()
+                .map(|_| ip_info.ip.addr.to_le_bytes())
+                .filter(|ip| *ip != [0; 4])
+        }
+    }
+
     let mut wifi = EspWifi::new(modem, sysloop.clone(), partition)?;
 
-    if PASS.is_empty() {
-        wifi.set_configuration(&Configuration::Client(ClientConfiguration {
+    // The driver keeps the credentials entered while provisioning in NVS,
+    // so the ones the firmware was built with are only a fallback
+    let conf = match wifi.get_configuration()? {
+        Configuration::Client(conf) | Configuration::Mixed(conf, _) if !conf.ssid.is_empty() => {
+            conf
+        }
+        _ if PASS.is_empty() => ClientConfiguration {
             ssid: SSID.into(),
             auth_method: AuthMethod::None,
             ..Default::default()
-        }))?;
-    } else {
-        wifi.set_configuration(&Configuration::Client(ClientConfiguration {
+        },
+        _ => ClientConfiguration {
             ssid: SSID.into(),
             password: PASS.into(),
             ..Default::default()
-        }))?;
-    }
+        },
+    };
+
+    wifi.set_configuration(&Configuration::Client(conf))?;
 
     let wait = WifiWait::new(&sysloop)?;
 
@@ -316,10 +399,29 @@ pub fn wifi<'d>(
 
     Ok((
         wifi,
+        StaInfo,
         pubsub::SvcReceiver::new(sysloop.as_async().subscribe()?),
     ))
 }
 
+pub fn clock() -> Result<impl Clock, InitError> {
+    struct SntpClock {
+        _sntp: EspSntp,
+    }
+
+    impl Clock for SntpClock {
+        fn now(&self) -> Option<u64> {
+            // SNTP sets the system time, and the RTC keeps it across deep sleep,
+            // so it is valid even before the SNTP sync of this wakeup cycle has completed
+            SystemClock.now()
+        }
+    }
+
+    Ok(SntpClock {
+        _sntp: EspSntp::new_default()?,
+    })
+}
+
 pub fn httpd() -> Result<(EspHttpServer, impl Acceptor), InitError> {
     let (ws_processor, ws_acceptor) =
         EspHttpWsProcessor::<{ ws::WS_MAX_CONNECTIONS }, { ws::WS_MAX_FRAME_LEN }>::new(());
@@ -349,11 +451,148 @@ pub fn httpd() -> Result<(EspHttpServer, impl Acceptor), InitError> {
         ws_processor.lock(|ws_processor| ws_processor.borrow_mut().process(connection))
     })?;
 
+    captive_portal(&mut httpd)?;
+
     Ok((httpd, ws_acceptor))
 }
 
+fn captive_portal(httpd: &mut EspHttpServer) -> Result<(), InitError> {
+    // The URIs the various OSes probe to detect a captive portal
+    for uri in [
+        "/generate_204",
+        "/hotspot-detect.html",
+        "/connecttest.txt",
+        "/ncsi.txt",
+    ] {
+        httpd.fn_handler(uri, Method::Get, |req| {
+            req.into_response(302, Some("Found"), &[("Location", "/provision")])?;
+
+            Ok(())
+        })?;
+    }
+
+    httpd
+        .fn_handler("/provision", Method::Get, |req| {
+            req.into_ok_response()?
+                .write_all(PROVISIONING_FORM.as_bytes())?;
+
+            Ok(())
+        })?
+        .fn_handler("/provision", Method::Post, |mut req| {
+            let mut buf = [0_u8; 256];
+            let mut len = 0;
+
+            while len < buf.len() {
+                let read = req.read(&mut buf[len..])?;
+                if read == 0 {
+                    break;
+                }
+
+                len += read;
+            }
+
+            let mut ssid = None;
+            let mut password = Some(heapless::String::<64>::new());
+
+            for field in core::str::from_utf8(&buf[..len])?.split('&') {
+                let (name, value) = field.split_once('=').unwrap_or((field, ""));
+
+                match name {
+                    "ssid" => ssid = url_decode::<32>(value).filter(|ssid| !ssid.is_empty()),
+                    "password" => password = url_decode(value),
+                    _ => (),
+                }
+            }
+
+            if let (Some(ssid), Some(password)) = (ssid, password) {
+                req.into_ok_response()?
+                    .write_all("Connecting...".as_bytes())?;
+
+                ruwm::wifi::provision(&ssid, &password);
+            } else {
+                req.into_status_response(400)?
+                    .write_all("Invalid SSID or password".as_bytes())?;
+            }
+
+            Ok(())
+        })?;
+
+    // Resolve every name to the device itself while its provisioning access point is up
+    std::thread::Builder::new()
+        .stack_size(6144)
+        .spawn(|| loop {
+            if let Err(err) = captive_dns() {
+                log::warn!("Captive portal DNS failed: {}", err);
+            }
+
+            std::thread::sleep(core::time::Duration::from_secs(1));
+        })
+        .unwrap();
+
+    Ok(())
+}
+
+fn captive_dns() -> std::io::Result<()> {
+    let socket = std::net::UdpSocket::bind("0.0.0.0:53")?;
+
+    let mut buf = [0_u8; 512];
+
+    loop {
+        let (len, addr) = socket.recv_from(&mut buf)?;
+
+        // Only standard queries with a single question are answered
+        if len < 12 || buf[2] & 0xf8 != 0 || buf[4..6] != [0, 1] {
+            continue;
+        }
+
+        // Skip the question name and type/class, dropping any additional records following them
+        let mut end = 12;
+        while end < len && buf[end] != 0 {
+            end += buf[end] as usize + 1;
+        }
+        end += 5;
+
+        if end > len || end + 16 > buf.len() {
+            continue;
+        }
+
+        buf[2] = 0x84 | (buf[2] & 0x01); // Response, authoritative, keep the recursion desired flag
+        buf[3] = 0x80; // Recursion available, no error
+        buf[6..12].copy_from_slice(&[0, 1, 0, 0, 0, 0]);
+
+        // A pointer to the question name, type A, class IN, TTL of 60s and the address
+        buf[end..end + 12].copy_from_slice(&[0xc0, 0x0c, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4]);
+        buf[end + 12..end + 16].copy_from_slice(&PROVISIONING_IP);
+
+        socket.send_to(&buf[..end + 16], addr)?;
+    }
+}
+
+fn url_decode<const N: usize>(value: &str) -> Option<heapless::String<N>> {
+    let mut bytes = heapless::Vec::<u8, N>::new();
+
+    let mut chars = value.bytes();
+
+    while let Some(ch) = chars.next() {
+        let byte = match ch {
+            b'+' => b' ',
+            b'%' => {
+                let hex = [chars.next()?, chars.next()?];
+
+                u8::from_str_radix(core::str::from_utf8(&hex).ok()?, 16).ok()?
+            }
+            ch => ch,
+        };
+
+        bytes.push(byte).ok()?;
+    }
+
+    heapless::String::from_utf8(bytes).ok()
+}
+
 pub fn mqtt() -> Result<
     (
+        &'static str,
         &'static str,
         impl Client + Publish,
         impl Connection<Message = Option<MqttCommand>>,
@@ -361,10 +600,11 @@ pub fn mqtt() -> Result<
     InitError,
 > {
     let client_id = "water-meter-demo";
+    let broker = "mqtt://broker.emqx.io:1883";
     let mut mqtt_parser = MessageParser::new();
 
     let (mqtt_client, mqtt_conn) = EspMqttClient::new_with_converting_async_conn(
-        "mqtt://broker.emqx.io:1883",
+        broker,
         &MqttClientConfiguration {
             client_id: Some(client_id),
             ..Default::default()
@@ -374,16 +614,124 @@ pub fn mqtt() -> Result<
 
     let mqtt_client = mqtt_client.into_async();
 
-    Ok((client_id, mqtt_client, mqtt_conn))
+    Ok((client_id, broker, mqtt_client, mqtt_conn))
+}
+
+pub fn ota() -> impl Ota {
+    struct HttpOta(Option<&'static str>);
+
+    impl HttpOta {
+        fn get(
+            &self,
+            file: &str,
+        ) -> Result<(HttpClient<EspHttpConnection>, heapless::String<256>), EspIOError> {
+            let mut uri = heapless::String::new();
+
+            if let Some(url) = self.0 {
+                write!(&mut uri, "{}/{}", url.trim_end_matches('/'), file).unwrap();
+            }
+
+            Ok((
+                HttpClient::wrap(EspHttpConnection::new(&Default::default())?),
+                uri,
+            ))
+        }
+    }
+
+    impl Ota for HttpOta {
+        type Error = EspIOError;
+
+        fn check(&mut self) -> Result<Option<heapless::String<VERSION_MAX_LEN>>, Self::Error> {
+            if self.0.is_none() {
+                return Ok(None);
+            }
+
+            let (mut client, uri) = self.get("version")?;
+            let mut response = client.get(&uri)?.submit()?;
+
+            if response.status() != 200 {
+                return Err(EspError::from(esp_idf_sys::ESP_ERR_NOT_FOUND as _)
+                    .unwrap()
+                    .into());
+            }
+
+            let mut buf = [0_u8; VERSION_MAX_LEN];
+            let len = response.read(&mut buf)?;
+
+            let version = core::str::from_utf8(&buf[..len]).unwrap_or("").trim();
+
+            let newer = !version.is_empty() && version != env!("CARGO_PKG_VERSION");
+
+            Ok(newer.then(|| version.into()))
+        }
+
+        fn update(&mut self, progress: &mut dyn FnMut(u8)) -> Result<(), Self::Error> {
+            let (mut client, uri) = self.get("firmware.bin")?;
+            let mut response = client.get(&uri)?.submit()?;
+
+            if response.status() != 200 {
+                return Err(EspError::from(esp_idf_sys::ESP_ERR_NOT_FOUND as _)
+                    .unwrap()
+                    .into());
+            }
+
+            let total = response.content_len().unwrap_or(0);
+
+            let mut ota = EspOta::new()?;
+            let mut update = ota.initiate_update()?;
+
+            let mut buf = [0_u8; 1024];
+            let mut written = 0;
+
+            let result = loop {
+                let len = match response.read(&mut buf) {
+                    Ok(0) => break Ok(()),
+                    Ok(len) => len,
+                    Err(err) => break Err(err),
+                };
+
+                if let Err(err) = update.write_all(&buf[..len]) {
+                    break Err(err);
+                }
+
+                written += len as u64;
+
+                if total > 0 {
+                    progress((written * 100 / total).min(100) as u8);
+                }
+            };
+
+            match result {
+                Ok(()) => update.complete()?,
+                Err(err) => {
+                    update.abort()?;
+
+                    return Err(err);
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    HttpOta(OTA_URL)
 }
 
 fn subscribe_pin<'d, P: InputPin + OutputPin>(
     pin: impl Peripheral<P = P> + 'd,
     notify: impl Fn() + Send + 'static,
+) -> Result<impl embedded_hal::digital::v2::InputPin<Error = impl Debug + 'd> + 'd, InitError> {
+    subscribe_pin_edges(pin, InterruptType::NegEdge, notify)
+}
+
+fn subscribe_pin_edges<'d, P: InputPin + OutputPin>(
+    pin: impl Peripheral<P = P> + 'd,
+    interrupt_type: InterruptType,
+    notify: impl Fn() + Send + 'static,
 ) -> Result<impl embedded_hal::digital::v2::InputPin<Error = impl Debug + 'd> + 'd, InitError> {
     let mut pin = PinDriver::input(pin)?;
 
-    pin.set_interrupt_type(InterruptType::NegEdge)?;
+    pin.set_interrupt_type(interrupt_type)?;
 
     unsafe {
         pin.subscribe(notify)?;
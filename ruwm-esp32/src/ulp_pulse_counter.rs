@@ -93,7 +93,7 @@ where
 {
     type Error = EspError;
 
-    type TakePulsesFuture<'a> = impl Future<Output = Result<u64, Self::Error>> where Self: 'a;
+    type TakePulsesFuture<'a> = impl Future<Output = Result<i64, Self::Error>> where Self: 'a;
 
     fn take_pulses(&mut self) -> Self::TakePulsesFuture<'_> {
         async move {
@@ -9,12 +9,32 @@ use static_cell::StaticCell;
 
 use yew::prelude::*;
 
-use ruwm::{button, spawn};
+use ruwm::battery::BatteryConfig;
+use ruwm::button::{self, ButtonConfig, PressedLevel};
+use ruwm::emergency::EmergencyPolicy;
+use ruwm::leak_sensor::{LeakSensorConfig, LeakSensorInput};
+use ruwm::screen::PowerConfig;
+use ruwm::sleep::SleepPolicy;
+use ruwm::spawn;
+use ruwm::valve::ValveConfig;
+use ruwm::wm::MeterConfig;
 
 mod peripherals;
 mod services;
 
 //const SLEEP_TIME: Duration = Duration::from_secs(30);
+
+/// The probe is driven by the simulator; the MQTT sensor only reports once MQTT is simulated as well
+const LEAK_SENSORS: &[LeakSensorConfig] = &[
+    LeakSensorConfig {
+        name: "Probe",
+        input: LeakSensorInput::Probe(PressedLevel::High),
+    },
+    LeakSensorConfig {
+        name: "Kitchen",
+        input: LeakSensorInput::Mqtt("zigbee2mqtt/kitchen_leak"),
+    },
+];
 //const MQTT_MAX_TOPIC_LEN: usize = 64;
 
 #[function_component(App)]
@@ -36,7 +56,7 @@ fn main() -> Result<(), SpawnError> {
     Ok(())
 }
 
-static EXECUTOR: StaticCell<Executor<32, WasmMonitor, Local>> = StaticCell::new();
+static EXECUTOR: StaticCell<Executor<40, WasmMonitor, Local>> = StaticCell::new();
 
 fn start() -> Result<(), SpawnError> {
     info!("Initializing services & peripherals");
@@ -50,37 +70,26 @@ fn start() -> Result<(), SpawnError> {
 
     // Storage
 
-    // #[cfg(feature = "nvs")]
-    // let (wm_state, storage) = {
-    //     let storage = services::storage(nvs_default_partition.clone())?;
-
-    //     if let Some(wm_state) = storage
-    //         .lock(|storage| storage.borrow().get::<WaterMeterState>("wm-state"))
-    //         .unwrap()
-    //     {
-    //         (wm_state, storage)
-    //     } else {
-    //         log::warn!("No WM edge count found in NVS, assuming new device");
-
-    //         (Default::default(), storage)
-    //     }
-    // };
-
-    // #[cfg(not(feature = "nvs"))]
-    // let wm_state: WaterMeterState = Default::default();
+    let storage = services::storage();
 
-    // unsafe {
-    //     services::RTC_MEMORY.wm = wm_state;
-
-    //     ruwm::valve::STATE.set(services::RTC_MEMORY.valve);
-    //     ruwm::wm::STATE.set(services::RTC_MEMORY.wm);
-    //     ruwm::wm_stats::STATE.set(services::RTC_MEMORY.wm_stats);
-    // }
+    ruwm::wm::restore(storage);
+    ruwm::wm_stats::restore(storage);
+    ruwm::clock::restore(storage);
+    ruwm::schedule::restore(storage);
+    ruwm::budget::restore(storage);
+    ruwm::i18n::restore(storage);
+    ruwm::settings::restore(storage);
+    ruwm::battery::restore(storage);
+    ruwm::power::restore(storage);
 
     // Pulse counter
 
+    #[cfg(not(feature = "quadrature"))]
     let (pulse_counter, pulse_wakeup) = services::pulse(peripherals.pulse);
 
+    #[cfg(feature = "quadrature")]
+    let (pulse_counter, pulse_wakeup) = services::pulse(peripherals.pulse, peripherals.pulse_b);
+
     // TODO
     // Mqtt
 
@@ -88,35 +97,40 @@ fn start() -> Result<(), SpawnError> {
 
     // Executor
 
-    let executor = EXECUTOR.init(Executor::<32, WasmMonitor, _>::new());
-    let mut tasks = heapless::Vec::<_, 32>::new();
+    let executor = EXECUTOR.init(Executor::<40, WasmMonitor, _>::new());
+    let mut tasks = heapless::Vec::<_, 40>::new();
 
     // High-prio tasks
 
     spawn::high_prio(
         executor,
         &mut tasks,
-        valve_power_pin,
-        valve_open_pin,
-        valve_close_pin,
-        |state| unsafe {
-            services::RTC_MEMORY.valve = state;
-        },
-        pulse_counter,
-        pulse_wakeup,
-        |state| unsafe {
-            services::RTC_MEMORY.wm = state;
-        },
-        |state| unsafe {
-            services::RTC_MEMORY.wm_stats = state;
-        },
+        [(
+            valve_power_pin,
+            valve_open_pin,
+            valve_close_pin,
+            ValveConfig::new(),
+        )],
+        EmergencyPolicy::new(),
+        storage,
+        [(pulse_counter, pulse_wakeup, MeterConfig::new())],
         peripherals.battery.adc,
         peripherals.battery.voltage,
         peripherals.battery.power,
+        BatteryConfig::new(),
         false,
         services::button(peripherals.buttons.button1, &button::BUTTON1_PIN_EDGE),
         services::button(peripherals.buttons.button2, &button::BUTTON2_PIN_EDGE),
         services::button(peripherals.buttons.button3, &button::BUTTON3_PIN_EDGE),
+        ButtonConfig::new(),
+        SleepPolicy::new(),
+    )?;
+
+    spawn::leak_sensors(
+        executor,
+        &mut tasks,
+        LEAK_SENSORS,
+        [(0, services::leak_probe(peripherals.leak_probe, 0))],
     )?;
 
     // Mid-prio tasks
@@ -127,10 +141,9 @@ fn start() -> Result<(), SpawnError> {
         executor,
         &mut tasks,
         services::display(display),
-        move |_new_state| {
-            #[cfg(feature = "nvs")]
-            flash_wm_state(storage, _new_state);
-        },
+        (),
+        PowerConfig::new(),
+        services::clock(),
     )?;
 
     // Low-prio tasks
@@ -144,6 +157,9 @@ fn start() -> Result<(), SpawnError> {
     //     mqtt_client,
     // )?;
 
+    // Firmware update
+    spawn::update(executor, &mut tasks, ())?;
+
     // Web
     spawn::web(
         executor,
@@ -174,23 +190,3 @@ fn start() -> Result<(), SpawnError> {
 
     Ok(())
 }
-
-#[cfg(feature = "nvs")]
-fn flash_wm_state<S>(
-    storage: &'static Mutex<
-        impl embassy_sync::blocking_mutex::raw::RawMutex,
-        core::cell::RefCell<S>,
-    >,
-    new_state: WaterMeterState,
-) where
-    S: Storage,
-{
-    ruwm::log_err!(storage.lock(|storage| {
-        let old_state = storage.borrow().get("wm-state")?;
-        if old_state != Some(new_state) {
-            storage.borrow_mut().set("wm-state", &new_state)?;
-        }
-
-        Ok::<_, S::Error>(())
-    }));
-}
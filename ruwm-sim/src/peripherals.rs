@@ -15,9 +15,13 @@ pub const DISPLAY_SIZE: Size = Size::new(128, 128);
 pub struct SystemPeripherals {
     pub shared: SharedPeripherals,
     pub pulse: Pin<Input>,
+    #[cfg(feature = "quadrature")]
+    pub pulse_b: Pin<Input>,
     pub valve: ValvePeripherals,
     pub battery: BatteryPeripherals,
     pub buttons: ButtonsPeripherals,
+    /// Reads high when wet
+    pub leak_probe: Pin<Input>,
     pub display: Display<Rgb888>,
 }
 
@@ -27,9 +31,15 @@ impl SystemPeripherals {
 
         SystemPeripherals {
             shared: peripherals.shared(),
+            #[cfg(not(feature = "quadrature"))]
             pulse: peripherals
                 .pins
                 .input_click("Pulse", "Pulse Counter", false),
+            // Toggled one after the other, A then B for forward flow and B then A for backflow
+            #[cfg(feature = "quadrature")]
+            pulse: peripherals.pins.input("Pulse A", "Pulse Counter", false),
+            #[cfg(feature = "quadrature")]
+            pulse_b: peripherals.pins.input("Pulse B", "Pulse Counter", false),
             valve: ValvePeripherals {
                 power: peripherals.pins.output("Power", "Valve", false),
                 open: peripherals.pins.output("Open", "Valve", false),
@@ -51,6 +61,7 @@ impl SystemPeripherals {
                 button2: peripherals.pins.input_click("Next", "Display", false),
                 button3: peripherals.pins.input_click("Action", "Display", false),
             },
+            leak_probe: peripherals.pins.input("Wet", "Leak Probe", false),
             display: peripherals.displays.display(
                 "Display",
                 DISPLAY_SIZE.width as _,
@@ -1,6 +1,11 @@
+use core::cell::RefCell;
 use core::fmt::Debug;
 
-use embassy_time::Duration;
+use std::collections::BTreeMap;
+
+use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, RawMutex};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::{Duration, Instant};
 
 use embedded_graphics_core::pixelcolor::Rgb888;
 
@@ -16,34 +21,15 @@ use hal_sim::display::Display;
 use hal_sim::gpio::{Input, Pin};
 
 use ruwm::button::PressedLevel;
+use ruwm::clock::{Clock, MIN_VALID_TIME_SECS};
+use ruwm::leak_sensor::{self, LeakSensorId};
 use ruwm::pulse_counter::PulseCounter;
 use ruwm::pulse_counter::PulseWakeup;
 use ruwm::screen::Color;
-use ruwm::valve::ValveState;
-use ruwm::wm::WaterMeterState;
-use ruwm::wm_stats::WaterMeterStatsState;
+use ruwm::storage::Storage;
 
 use crate::peripherals::ValvePeripherals;
 
-pub static mut RTC_MEMORY: RtcMemory = RtcMemory::new();
-
-#[derive(Default)]
-pub struct RtcMemory {
-    pub valve: Option<ValveState>,
-    pub wm: WaterMeterState,
-    pub wm_stats: WaterMeterStatsState,
-}
-
-impl RtcMemory {
-    pub const fn new() -> Self {
-        Self {
-            valve: None,
-            wm: WaterMeterState::new(),
-            wm_stats: WaterMeterStatsState::new(),
-        }
-    }
-}
-
 pub fn valve_pins(
     peripherals: ValvePeripherals,
 ) -> (
@@ -58,61 +44,61 @@ pub fn valve_pins(
     (power, open, close)
 }
 
-#[cfg(feature = "nvs")]
-pub fn storage(
-    partition: EspDefaultNvsPartition,
-) -> Result<
-    &'static Mutex<
-        impl embassy_sync::blocking_mutex::raw::RawMutex,
-        RefCell<impl embedded_svc::storage::Storage>,
-    >,
-    InitError,
-> {
-    const POSTCARD_BUF_SIZE: usize = 500;
-
-    struct PostcardSerDe;
-
-    impl embedded_svc::storage::SerDe for PostcardSerDe {
-        type Error = postcard::Error;
-
-        fn serialize<'a, T>(&self, slice: &'a mut [u8], value: &T) -> Result<&'a [u8], Self::Error>
-        where
-            T: serde::Serialize,
-        {
-            postcard::to_slice(value, slice).map(|r| &*r)
+pub fn storage() -> &'static Mutex<impl RawMutex, RefCell<impl Storage>> {
+    #[derive(Default)]
+    struct MemoryStorage(BTreeMap<String, Vec<u8>>);
+
+    /// The stored record does not fit in the buffer of the caller
+    #[derive(Debug)]
+    struct RecordTooLarge;
+
+    impl Storage for MemoryStorage {
+        type Error = RecordTooLarge;
+
+        fn load<'a>(&self, key: &str, buf: &'a mut [u8]) -> Result<Option<&'a [u8]>, Self::Error> {
+            match self.0.get(key) {
+                Some(data) if data.len() > buf.len() => Err(RecordTooLarge),
+                Some(data) => {
+                    buf[..data.len()].copy_from_slice(data);
+
+                    Ok(Some(&buf[..data.len()]))
+                }
+                None => Ok(None),
+            }
+        }
+
+        fn store(&mut self, key: &str, data: &[u8]) -> Result<(), Self::Error> {
+            self.0.insert(key.to_owned(), data.to_vec());
+
+            Ok(())
+        }
+
+        fn remove(&mut self, key: &str) -> Result<(), Self::Error> {
+            self.0.remove(key);
+
+            Ok(())
         }
+    }
+
+    static STORAGE: StaticCell<Mutex<CriticalSectionRawMutex, RefCell<MemoryStorage>>> =
+        StaticCell::new();
+
+    STORAGE.init(Mutex::new(RefCell::new(Default::default())))
+}
 
-        fn deserialize<T>(&self, slice: &[u8]) -> Result<T, Self::Error>
-        where
-            T: serde::de::DeserializeOwned,
-        {
-            postcard::from_bytes(slice)
+pub fn clock() -> impl Clock {
+    struct SimClock;
+
+    impl Clock for SimClock {
+        fn now(&self) -> Option<u64> {
+            Some(MIN_VALID_TIME_SECS + Instant::now().as_secs())
         }
     }
 
-    static STORAGE: static_cell::StaticCell<
-        Mutex<
-            CriticalSectionRawMutex,
-            RefCell<
-                embedded_svc::storage::StorageImpl<
-                    { POSTCARD_BUF_SIZE },
-                    esp_idf_svc::nvs::EspDefaultNvs,
-                    PostcardSerDe,
-                >,
-            >,
-        >,
-    > = static_cell::StaticCell::new();
-
-    let storage = &*STORAGE.init(Mutex::new(RefCell::new(
-        embedded_svc::storage::StorageImpl::new(
-            esp_idf_svc::nvs::EspNvs::new(partition, "WM", true)?,
-            PostcardSerDe,
-        ),
-    )));
-
-    Ok(storage)
+    SimClock
 }
 
+#[cfg(not(feature = "quadrature"))]
 pub fn pulse(pulse: Pin<Input>) -> (impl PulseCounter, impl PulseWakeup) {
     static PULSE_SIGNAL: Notification = Notification::new();
 
@@ -126,6 +112,20 @@ pub fn pulse(pulse: Pin<Input>) -> (impl PulseCounter, impl PulseWakeup) {
     (pulse_counter, ())
 }
 
+#[cfg(feature = "quadrature")]
+pub fn pulse(pulse_a: Pin<Input>, pulse_b: Pin<Input>) -> (impl PulseCounter, impl PulseWakeup) {
+    static PULSE_SIGNAL: Notification = Notification::new();
+
+    let pulse_counter = ruwm::pulse_counter::QuadraturePulseCounter::new(
+        subscribe_pin(pulse_a, || PULSE_SIGNAL.notify()),
+        subscribe_pin(pulse_b, || PULSE_SIGNAL.notify()),
+        PressedLevel::Low,
+        &PULSE_SIGNAL,
+    );
+
+    (pulse_counter, ())
+}
+
 pub fn button(
     pin: Pin<Input>,
     notification: &'static Notification,
@@ -133,6 +133,12 @@ pub fn button(
     subscribe_pin(pin, move || notification.notify())
 }
 
+pub fn leak_probe(pin: Pin<Input>, sensor: LeakSensorId) -> impl InputPin<Error = impl Debug> {
+    subscribe_pin(pin, move || {
+        leak_sensor::PIN_EDGES[sensor as usize].notify()
+    })
+}
+
 pub fn display(
     display: Display<Rgb888>,
 ) -> impl Flushable<Color = Color, Error = impl Debug> + 'static {
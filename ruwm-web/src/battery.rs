@@ -4,6 +4,9 @@ use yew::prelude::*;
 use yewdux_middleware::*;
 
 use ruwm::dto::battery::BatteryState;
+use ruwm::dto::i18n::Message;
+
+use crate::i18n::LanguageStore;
 
 #[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
 pub struct BatteryStore(pub BatteryState);
@@ -24,8 +27,34 @@ impl Reducer<BatteryStore> for BatteryMsg {
 #[function_component(Battery)]
 pub fn battery() -> Html {
     let battery_store = use_store_value::<BatteryStore>();
+    let language = use_store_value::<LanguageStore>().0;
+
+    let powered = if battery_store.0.powered.unwrap_or(false) {
+        Message::Yes
+    } else {
+        Message::No
+    };
+
+    let voltage = battery_store
+        .0
+        .voltage
+        .map(|voltage| format!("{} mV", voltage))
+        .unwrap_or_else(|| Message::Unknown.text(language).to_owned());
+
+    let charge = battery_store
+        .0
+        .charge
+        .map(|charge| format!(" ({}%)", charge))
+        .unwrap_or_default();
 
     html! {
-        {format!("Battery Powered: {}, mV: {:?}", battery_store.0.powered.unwrap_or(false), battery_store.0.voltage)}
+        {format!(
+            "{}: {}, {}: {}{}",
+            Message::Powered.text(language),
+            powered.text(language),
+            Message::Voltage.text(language),
+            voltage,
+            charge,
+        )}
     }
 }
@@ -0,0 +1,47 @@
+use std::rc::Rc;
+
+use yew::prelude::*;
+use yewdux_middleware::*;
+
+use ruwm::dto::i18n::Language;
+use ruwm::dto::web::WebRequest;
+
+#[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
+pub struct LanguageStore(pub Language);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LanguageMsg(pub Language);
+
+impl Reducer<LanguageStore> for LanguageMsg {
+    fn apply(&self, mut store: Rc<LanguageStore>) -> Rc<LanguageStore> {
+        let state = Rc::make_mut(&mut store);
+
+        state.0 = self.0;
+
+        store
+    }
+}
+
+#[function_component(LanguageSelect)]
+pub fn language_select() -> Html {
+    let language_store = use_store_value::<LanguageStore>();
+
+    html! {
+        <div class="buttons">
+            {
+                for Language::ALL.iter().map(|language| {
+                    let language = *language;
+
+                    html! {
+                        <button
+                            class={classes!("button", (language == language_store.0).then(|| "is-primary"))}
+                            onclick={Callback::from(move |_| dispatch::invoke(WebRequest::Language(language)))}
+                        >
+                            {language.name()}
+                        </button>
+                    }
+                })
+            }
+        </div>
+    }
+}
@@ -6,6 +6,7 @@ use std::rc::Rc;
 
 use edge_frame::middleware;
 use log::Level;
+use ruwm::dto::valve::MAIN_VALVE;
 use ruwm::dto::web::WebEvent;
 use ruwm::dto::web::WebRequest;
 use yew::prelude::*;
@@ -18,9 +19,13 @@ use edge_frame::role::*;
 use edge_frame::wifi::*;
 
 use crate::battery::*;
+use crate::i18n::*;
+use crate::schedule::*;
 use crate::valve::*;
 
 mod battery;
+mod i18n;
+mod schedule;
 mod valve;
 
 #[cfg(all(feature = "middleware-ws", feature = "middleware-local"))]
@@ -92,6 +97,8 @@ fn render(route: &Routes) -> Html {
                             <Role role={RoleDto::User} auth=true>
                                 <Valve/>
                                 <Battery/>
+                                <Schedule/>
+                                <LanguageSelect/>
                             </Role>
                         },
                         Routes::AuthState => html! {
@@ -120,7 +127,7 @@ fn init_middleware(_endpoint: String) {
     // Dispatch WebRequest messages => send to backend
     dispatch::register(middleware::send::<WebRequest>(sender));
 
-    // Dispatch WebEvent messages => redispatch as BatteryMsg, ValveMsg, RoleState or WifiConf messages
+    // Dispatch WebEvent messages => redispatch as BatteryMsg, ValveMsg, ScheduleMsg, LanguageMsg, RoleState or WifiConf messages
     dispatch::register::<WebEvent, _>(|event| {
         match event {
             WebEvent::NoPermissions => unreachable!(),
@@ -131,9 +138,18 @@ fn init_middleware(_endpoint: String) {
                 }))
             } // TODO
             WebEvent::RoleState(role) => dispatch::invoke(RoleState::Role(role)),
-            WebEvent::ValveState(valve) => dispatch::invoke(ValveMsg(valve)),
+            WebEvent::ValveState(MAIN_VALVE, valve) => dispatch::invoke(ValveMsg(valve)),
+            WebEvent::ValveState(..) => (), // TODO
             WebEvent::BatteryState(battery) => dispatch::invoke(BatteryMsg(battery)),
-            WebEvent::WaterMeterState(_) => (), // TODO
+            WebEvent::BatteryHistory(_) => (),   // TODO
+            WebEvent::PowerState(_) => (),       // TODO
+            WebEvent::WaterMeterState(..) => (), // TODO
+            WebEvent::SensorHealth(..) => (),    // TODO
+            WebEvent::LeakSensorState(..) => (), // TODO
+            WebEvent::ScheduleState(schedule) => dispatch::invoke(ScheduleMsg(schedule)),
+            WebEvent::BudgetState(_) => (), // TODO
+            WebEvent::AlertsState(_) => (), // TODO
+            WebEvent::LanguageState(language) => dispatch::invoke(LanguageMsg(language)),
         }
     });
 
@@ -143,6 +159,8 @@ fn init_middleware(_endpoint: String) {
     dispatch::register(log::<WifiConfStore, WifiConfState>(dispatch::store));
     dispatch::register(log::<BatteryStore, BatteryMsg>(dispatch::store));
     dispatch::register(log::<ValveStore, ValveMsg>(dispatch::store));
+    dispatch::register(log::<ScheduleStore, ScheduleMsg>(dispatch::store));
+    dispatch::register(log::<LanguageStore, LanguageMsg>(dispatch::store));
 
     // Receive from backend => dispatch WebEvent messages
     middleware::receive::<WebEvent>(receiver);
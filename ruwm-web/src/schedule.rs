@@ -0,0 +1,78 @@
+use std::rc::Rc;
+
+use yew::prelude::*;
+use yewdux_middleware::*;
+
+use ruwm::dto::i18n::Message;
+use ruwm::dto::schedule::{RuleSource, ScheduleAction, ScheduleCommand, ScheduleState};
+use ruwm::dto::web::WebRequest;
+
+use crate::i18n::LanguageStore;
+
+#[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
+pub struct ScheduleStore(pub ScheduleState);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ScheduleMsg(pub ScheduleState);
+
+impl Reducer<ScheduleStore> for ScheduleMsg {
+    fn apply(&self, mut store: Rc<ScheduleStore>) -> Rc<ScheduleStore> {
+        let state = Rc::make_mut(&mut store);
+
+        state.0 = self.0.clone();
+
+        store
+    }
+}
+
+#[function_component(Schedule)]
+pub fn schedule() -> Html {
+    let schedule_store = use_store_value::<ScheduleStore>();
+    let language = use_store_value::<LanguageStore>().0;
+
+    let enabled = schedule_store.0.enabled;
+
+    html! {
+        <div>
+            <button
+                class={classes!("button", enabled.then(|| "is-primary"))}
+                onclick={Callback::from(move |_| dispatch::invoke(WebRequest::ScheduleCommand(ScheduleCommand::Enable(!enabled))))}
+            >
+                {format!(
+                    "{}: {}",
+                    Message::Schedule.text(language),
+                    if enabled { Message::Yes } else { Message::No }.text(language),
+                )}
+            </button>
+            <ul>
+                {
+                    for schedule_store.0.rules.iter().enumerate().map(|(index, rule)| {
+                        let index = index as u8;
+
+                        let action = match rule.action {
+                            ScheduleAction::CloseValve => Message::CloseValve,
+                            ScheduleAction::ArmMeter => Message::Arm,
+                        };
+
+                        let source = match rule.source {
+                            RuleSource::User => String::new(),
+                            RuleSource::Vacation => format!(" ({})", Message::Vacation.text(language)),
+                        };
+
+                        html! {
+                            <li>
+                                {format!("{}: {}{} ", action.text(language), rule.window, source)}
+                                <button
+                                    class="button is-small"
+                                    onclick={Callback::from(move |_| dispatch::invoke(WebRequest::ScheduleCommand(ScheduleCommand::RemoveRule(index))))}
+                                >
+                                    {Message::Remove.text(language)}
+                                </button>
+                            </li>
+                        }
+                    })
+                }
+            </ul>
+        </div>
+    }
+}
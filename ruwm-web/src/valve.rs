@@ -3,8 +3,11 @@ use std::rc::Rc;
 use yew::prelude::*;
 use yewdux_middleware::*;
 
+use ruwm::dto::i18n::Message;
 use ruwm::dto::valve::ValveState;
 
+use crate::i18n::LanguageStore;
+
 #[derive(Default, Clone, Debug, Eq, PartialEq, Store)]
 pub struct ValveStore(pub Option<ValveState>);
 
@@ -24,8 +27,17 @@ impl Reducer<ValveStore> for ValveMsg {
 #[function_component(Valve)]
 pub fn valve() -> Html {
     let valve_store = use_store_value::<ValveStore>();
+    let language = use_store_value::<LanguageStore>().0;
+
+    let state = match valve_store.0 {
+        Some(ValveState::Open) => Message::ValveOpen,
+        Some(ValveState::Opening(_)) => Message::ValveOpening,
+        Some(ValveState::Closed) => Message::ValveClosed,
+        Some(ValveState::Closing(_)) => Message::ValveClosing,
+        None => Message::Unknown,
+    };
 
     html! {
-        {format!("Valve State: {:?}", valve_store.0.as_ref())}
+        {format!("{}: {}", Message::Valve.text(language), state.text(language))}
     }
 }
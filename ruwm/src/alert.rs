@@ -0,0 +1,123 @@
+use embassy_futures::select::{select, select_array, Either};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+
+use channel_bridge::notification::Notification;
+
+use crate::battery::{self, BatteryLevel};
+use crate::state::State;
+use crate::valve::MAX_VALVES;
+use crate::{clock, leak_sensor, valve, wifi, wm};
+
+pub use crate::dto::alert::*;
+
+pub static STATE: State<AlertsState> = State::new(
+    "ALERTS",
+    AlertsState::new(),
+    &[
+        &crate::keepalive::NOTIF,
+        &crate::screen::ALERTS_STATE_NOTIF,
+        &crate::mqtt::ALERTS_STATE_NOTIF,
+        &crate::web::ALERTS_STATE_NOTIF,
+    ],
+);
+
+pub(crate) static VALVE_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static WM_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static BATTERY_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static WIFI_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static LEAK_SENSOR_STATE_NOTIF: Notification = Notification::new();
+
+pub(crate) static COMMAND: Signal<CriticalSectionRawMutex, AlertCommand> = Signal::new();
+
+pub async fn process() {
+    // Only losing a known valve position or an established connection is a fault;
+    // not knowing them yet after a wakeup is not
+    let mut valves_known = [false; MAX_VALVES];
+    let mut wifi_connected = false;
+
+    loop {
+        let command = match select(
+            COMMAND.wait(),
+            select_array([
+                VALVE_STATE_NOTIF.wait(),
+                WM_STATE_NOTIF.wait(),
+                BATTERY_STATE_NOTIF.wait(),
+                WIFI_STATE_NOTIF.wait(),
+                LEAK_SENSOR_STATE_NOTIF.wait(),
+            ]),
+        )
+        .await
+        {
+            Either::First(command) => Some(command),
+            Either::Second(_) => None,
+        };
+
+        let mut valve_fault = false;
+
+        for (valve_state, valve_known) in valve::STATES.iter().zip(valves_known.iter_mut()) {
+            let valve_state = valve_state.get();
+
+            valve_fault |= *valve_known && valve_state.is_none();
+            *valve_known = valve_state.is_some();
+        }
+
+        // A lost valve keeps the fault raised until its position is known again
+        let valve_known = valve::configured().all(|valve| valves_known[valve as usize]);
+
+        let connected = wifi::STATE.get().connected;
+        let wifi_lost = wifi_connected && connected == Some(false);
+        wifi_connected = connected.unwrap_or(false) || wifi_lost;
+
+        let battery = battery::STATE.get();
+        let battery_low = battery
+            .level
+            .map(|level| level != BatteryLevel::Normal)
+            .unwrap_or(false)
+            && !battery.powered.unwrap_or(false);
+
+        // Raised if any of the meters raises it
+        let leaking = wm::STATES.iter().any(|state| state.get().leaking);
+        let backflow = wm::STATES.iter().any(|state| state.get().backflow);
+        let sensor_fault = wm::HEALTH.iter().any(|health| health.get().fault.is_some());
+
+        let flooded = leak_sensor::wet_sensor();
+
+        let now = clock::now();
+
+        if matches!(
+            command,
+            Some(AlertCommand::Acknowledge(AlertKind::Backflow) | AlertCommand::AcknowledgeAll)
+        ) {
+            wm::acknowledge_backflow();
+        }
+
+        STATE.update_with(|mut state| {
+            for (kind, active) in [
+                (AlertKind::Leak, leaking),
+                (AlertKind::Flood, flooded.is_some()),
+                (AlertKind::ValveFault, valve_fault),
+                (AlertKind::Backflow, backflow),
+                (AlertKind::SensorFault, sensor_fault),
+                (AlertKind::LowBattery, battery_low),
+                (AlertKind::WifiLost, wifi_lost),
+            ] {
+                if active {
+                    let sensor = flooded.filter(|_| kind == AlertKind::Flood);
+
+                    state.raise(kind, sensor, now);
+                } else if kind != AlertKind::ValveFault || valve_known {
+                    state.clear(kind);
+                }
+            }
+
+            match command {
+                Some(AlertCommand::Acknowledge(kind)) => state.acknowledge(Some(kind)),
+                Some(AlertCommand::AcknowledgeAll) => state.acknowledge(None),
+                None => (),
+            }
+
+            state
+        });
+    }
+}
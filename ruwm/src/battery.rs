@@ -1,43 +1,230 @@
-use embassy_time::{Duration, Timer};
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::{Duration, Instant, Timer};
 
 use embedded_hal::adc;
 use embedded_hal::digital::v2::InputPin;
 
+use channel_bridge::notification::Notification;
+
 use crate::state::State;
+use crate::storage::{self, Storage, Versioned};
+use crate::{clock, settings};
 
 pub use crate::dto::battery::*;
 
+const HISTORY_STORAGE_KEY: &str = "bat-hist";
+
+pub const MAX_FILTER_WINDOW: usize = 16;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BatteryFilter {
+    Average,
+    /// Ignores single spikes, e.g. while the valve motor is running
+    Median,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BatteryConfig {
+    pub filter: BatteryFilter,
+    /// How many of the last samples are filtered, up to `MAX_FILTER_WINDOW`
+    pub window: usize,
+    /// In mV; smaller changes of the filtered voltage are not reported
+    pub change_threshold: u16,
+}
+
+impl BatteryConfig {
+    pub const fn new() -> Self {
+        Self {
+            filter: BatteryFilter::Median,
+            window: 5,
+            change_threshold: 20,
+        }
+    }
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub static STATE: State<BatteryState> = State::new(
     "BATTERY",
     BatteryState::new(),
     &[
         &crate::keepalive::NOTIF,
         &crate::emergency::BATTERY_STATE_NOTIF,
+        &crate::alert::BATTERY_STATE_NOTIF,
         &crate::screen::BATTERY_STATE_NOTIF,
         &crate::mqtt::BATTERY_STATE_NOTIF,
         &crate::web::BATTERY_STATE_NOTIF,
+        &crate::power::BATTERY_STATE_NOTIF,
     ],
 );
 
+pub static HISTORY: State<VoltageHistory> = State::new(
+    "BATTERY HISTORY",
+    VoltageHistory::new(),
+    &[
+        &crate::screen::BATTERY_STATE_NOTIF,
+        &crate::web::BATTERY_HISTORY_STATE_NOTIF,
+        &HISTORY_PERSIST_NOTIFY,
+    ],
+);
+
+static HISTORY_PERSIST_NOTIFY: Notification = Notification::new();
+
 pub async fn process<ADC, BP>(
     mut one_shot: impl adc::OneShot<ADC, u16, BP>,
     mut battery_pin: BP,
     power_pin: impl InputPin,
+    config: BatteryConfig,
 ) where
     BP: adc::Channel<ADC>,
 {
-    const ROUND_UP: u16 = 50; // TODO: Make it smaller once ADC is connected
+    let window = config.window.clamp(1, MAX_FILTER_WINDOW);
+
+    // The discharge since the anchor is at least that long before it is trusted,
+    // and the anchor is moved after the window so that the estimate follows the recent load
+    const TREND_MIN: Duration = Duration::from_secs(10 * 60);
+    const TREND_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+    let mut trend_anchor: Option<(Instant, u16)> = None;
+    let mut samples = heapless::Vec::<u16, MAX_FILTER_WINDOW>::new();
 
     loop {
         Timer::after(Duration::from_secs(2)).await;
 
-        let voltage = one_shot
+        let profile = settings::STATE.get().battery_profile();
+
+        let sample = one_shot
             .read(&mut battery_pin)
             .ok()
-            .map(|voltage| voltage / ROUND_UP * ROUND_UP);
+            .map(|voltage| profile.voltage(voltage));
 
         let powered = Some(power_pin.is_high().unwrap_or(false));
 
-        STATE.update(BatteryState { voltage, powered });
+        let prev_state = STATE.get();
+
+        let voltage = if let Some(sample) = sample {
+            if samples.len() >= window {
+                samples = samples
+                    .iter()
+                    .skip(samples.len() + 1 - window)
+                    .copied()
+                    .collect();
+            }
+
+            let _ = samples.push(sample);
+
+            let filtered = filter(&samples, config.filter);
+
+            match prev_state.voltage {
+                Some(prev_voltage) if diff(prev_voltage, filtered) < config.change_threshold => {
+                    Some(prev_voltage)
+                }
+                _ => Some(filtered),
+            }
+        } else {
+            samples.clear();
+
+            None
+        };
+
+        let mut time_to_empty = prev_state.time_to_empty;
+
+        // Only the reported voltages are recorded, to spare the flash
+        if let Some((voltage, now)) = voltage.zip(clock::now()) {
+            let day = clock::TIME_ZONE.get().to_local(now) / clock::SECS_PER_DAY;
+
+            HISTORY.update_with(|mut history| {
+                history.record(day, voltage);
+
+                history
+            });
+        }
+
+        match voltage {
+            Some(voltage) if powered == Some(false) => {
+                let now = Instant::now();
+                let (since, anchor_voltage) = *trend_anchor.get_or_insert((now, voltage));
+
+                if voltage > anchor_voltage {
+                    // Recovered, e.g. after a load spike
+                    trend_anchor = Some((now, voltage));
+                } else if Some(voltage) != prev_state.voltage {
+                    let elapsed = now - since;
+
+                    if elapsed >= TREND_MIN && voltage < anchor_voltage {
+                        let remaining = voltage.saturating_sub(profile.critical_voltage) as u64;
+                        let dropped = (anchor_voltage - voltage) as u64;
+
+                        time_to_empty = Some((remaining * elapsed.as_secs() / dropped) as u32);
+                    }
+
+                    if elapsed >= TREND_WINDOW {
+                        trend_anchor = Some((now, voltage));
+                    }
+                }
+            }
+            _ => {
+                trend_anchor = None;
+                time_to_empty = None;
+            }
+        }
+
+        STATE.update(BatteryState {
+            voltage,
+            powered,
+            charge: voltage.map(|voltage| profile.percentage(voltage)),
+            level: voltage.map(|voltage| profile.level(voltage, prev_state.level)),
+            time_to_empty,
+        });
+    }
+}
+
+fn filter(samples: &[u16], filter: BatteryFilter) -> u16 {
+    match filter {
+        BatteryFilter::Average => {
+            (samples.iter().map(|sample| *sample as u32).sum::<u32>() / samples.len() as u32) as u16
+        }
+        BatteryFilter::Median => {
+            let mut sorted = [0; MAX_FILTER_WINDOW];
+            let sorted = &mut sorted[..samples.len()];
+
+            sorted.copy_from_slice(samples);
+            sorted.sort_unstable();
+
+            sorted[sorted.len() / 2]
+        }
+    }
+}
+
+fn diff(a: u16, b: u16) -> u16 {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
+
+impl Versioned for VoltageHistory {
+    const VERSION: u8 = 1;
+}
+
+pub fn restore(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>) {
+    if let Some(history) = storage::restore(storage, HISTORY_STORAGE_KEY) {
+        HISTORY.set(history);
+    }
+}
+
+pub async fn persist(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>) {
+    loop {
+        HISTORY_PERSIST_NOTIFY.wait().await;
+
+        storage::persist(storage, HISTORY_STORAGE_KEY, &HISTORY.get());
     }
 }
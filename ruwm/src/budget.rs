@@ -0,0 +1,101 @@
+use core::cell::RefCell;
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, RawMutex};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::signal::Signal;
+
+use channel_bridge::notification::Notification;
+
+use crate::state::State;
+use crate::storage::{self, Storage, Versioned};
+use crate::wm::{self, MAX_METERS};
+use crate::wm_stats;
+
+pub use crate::dto::budget::*;
+
+const STORAGE_KEY: &str = "budget";
+
+pub static STATE: State<BudgetState> = State::new(
+    "BUDGET",
+    BudgetState::new(),
+    &[
+        &crate::keepalive::NOTIF,
+        &crate::emergency::BUDGET_STATE_NOTIF,
+        &crate::screen::BUDGET_STATE_NOTIF,
+        &crate::mqtt::BUDGET_STATE_NOTIF,
+        &crate::web::BUDGET_STATE_NOTIF,
+        &STATE_PERSIST_NOTIFY,
+    ],
+);
+
+pub(crate) static WM_STATS_STATE_NOTIF: Notification = Notification::new();
+
+static STATE_PERSIST_NOTIFY: Notification = Notification::new();
+
+pub(crate) static COMMAND: Signal<CriticalSectionRawMutex, BudgetConfig> = Signal::new();
+
+pub async fn process() {
+    loop {
+        let config = match select(COMMAND.wait(), WM_STATS_STATE_NOTIF.wait()).await {
+            Either::First(config) => Some(config),
+            Either::Second(_) => None,
+        };
+
+        // The meters can count different volumes per edge, so the budget is kept in litres
+        let stats = wm::configured()
+            .filter_map(|meter| {
+                wm::config(meter).map(|config| {
+                    (
+                        wm_stats::STATES[meter as usize].get(),
+                        config.edge_volume_ml,
+                    )
+                })
+            })
+            .collect::<heapless::Vec<_, MAX_METERS>>();
+
+        STATE.update_with(|mut state| {
+            if let Some(config) = config {
+                state.config = config;
+            }
+
+            state.update(
+                stats
+                    .iter()
+                    .map(|(stats, edge_volume_ml)| (stats, *edge_volume_ml)),
+            );
+
+            state
+        });
+    }
+}
+
+impl Versioned for BudgetConfig {
+    const VERSION: u8 = 1;
+}
+
+pub fn restore(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>) {
+    if let Some(config) = storage::restore(storage, STORAGE_KEY) {
+        STATE.set(BudgetState {
+            config,
+            ..BudgetState::new()
+        });
+    }
+}
+
+pub async fn persist(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>) {
+    let mut persisted = STATE.get().config;
+
+    loop {
+        STATE_PERSIST_NOTIFY.wait().await;
+
+        // The state changes with every pulse, but only the configuration is worth persisting
+        let config = STATE.get().config;
+
+        if persisted != config {
+            storage::persist(storage, STORAGE_KEY, &config);
+
+            persisted = config;
+        }
+    }
+}
@@ -1,7 +1,11 @@
+use core::cell::Cell;
 use core::fmt::Debug;
 use core::future::pending;
 
-use embassy_time::{Duration, Timer};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
 use serde::{Deserialize, Serialize};
 
 use embassy_futures::select::{select, select3, Either, Either3};
@@ -10,12 +14,63 @@ use embedded_hal::digital::v2::InputPin;
 
 use channel_bridge::notification::Notification;
 
+use crate::sleep::{self, Activity};
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum PressedLevel {
     Low,
     High,
 }
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Button {
+    Button1 = 0,
+    Button2 = 1,
+    Button3 = 2,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Gesture {
+    /// A second press within `ButtonConfig::double_press` of the first one
+    DoublePress(Button),
+    /// The button is still held `ButtonConfig::hold` after being pressed
+    Hold(Button),
+    /// The button is still held `ButtonConfig::long_hold` after being pressed
+    LongHold(Button),
+    /// Button1 and Button2 are held together
+    Combo,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ButtonConfig {
+    pub debounce: Duration,
+    pub double_press: Duration,
+    pub hold: Duration,
+    pub long_hold: Duration,
+    /// Roller detents closer to each other than this count as more than one step
+    pub roller_acceleration: Duration,
+    pub roller_max_steps: u32,
+}
+
+impl ButtonConfig {
+    pub const fn new() -> Self {
+        Self {
+            debounce: Duration::from_millis(50),
+            double_press: Duration::from_millis(400),
+            hold: Duration::from_secs(3),
+            long_hold: Duration::from_secs(10),
+            roller_acceleration: Duration::from_millis(200),
+            roller_max_steps: 20,
+        }
+    }
+}
+
+impl Default for ButtonConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub static BUTTON1_PIN_EDGE: Notification = Notification::new();
 pub static BUTTON2_PIN_EDGE: Notification = Notification::new();
 pub static BUTTON3_PIN_EDGE: Notification = Notification::new();
@@ -24,33 +79,66 @@ static BUTTON1_NOTIFY: &[&Notification] = &[&crate::screen::BUTTON1_PRESSED_NOTI
 static BUTTON2_NOTIFY: &[&Notification] = &[&crate::screen::BUTTON2_PRESSED_NOTIF];
 static BUTTON3_NOTIFY: &[&Notification] = &[&crate::screen::BUTTON3_PRESSED_NOTIF];
 
-pub async fn button1_process(pin: impl InputPin, pressed_level: PressedLevel) {
+// Which buttons are currently held, and whether they made a combo, for detecting combos
+static HELD: Mutex<CriticalSectionRawMutex, Cell<([bool; 3], bool)>> =
+    Mutex::new(Cell::new(([false; 3], false)));
+
+// The steps rolled since the last `take_rolled_steps` call, with acceleration applied
+static ROLLED_STEPS: Mutex<CriticalSectionRawMutex, Cell<u32>> = Mutex::new(Cell::new(0));
+
+/// Returns the number of steps the roller moved since the last call.
+///
+/// Each detent is still notified on its own, but fast spins count for more than one step,
+/// so that numeric values can be edited quickly. Always zero when the buttons are not a roller.
+pub fn take_rolled_steps() -> u32 {
+    ROLLED_STEPS.lock(|steps| steps.replace(0))
+}
+
+pub async fn button1_process(
+    pin: impl InputPin,
+    pressed_level: PressedLevel,
+    config: ButtonConfig,
+) {
     button_process(
         pin,
         pressed_level,
+        Button::Button1,
         &BUTTON1_PIN_EDGE,
+        config,
         "BUTTON1 STATE",
         BUTTON1_NOTIFY,
     )
     .await;
 }
 
-pub async fn button2_process(pin: impl InputPin, pressed_level: PressedLevel) {
+pub async fn button2_process(
+    pin: impl InputPin,
+    pressed_level: PressedLevel,
+    config: ButtonConfig,
+) {
     button_process(
         pin,
         pressed_level,
+        Button::Button2,
         &BUTTON2_PIN_EDGE,
+        config,
         "BUTTON2 STATE",
         BUTTON2_NOTIFY,
     )
     .await;
 }
 
-pub async fn button3_process(pin: impl InputPin, pressed_level: PressedLevel) {
+pub async fn button3_process(
+    pin: impl InputPin,
+    pressed_level: PressedLevel,
+    config: ButtonConfig,
+) {
     button_process(
         pin,
         pressed_level,
+        Button::Button3,
         &BUTTON3_PIN_EDGE,
+        config,
         "BUTTON3 STATE",
         BUTTON3_NOTIFY,
     )
@@ -60,17 +148,21 @@ pub async fn button3_process(pin: impl InputPin, pressed_level: PressedLevel) {
 async fn button_process<'a>(
     pin: impl InputPin,
     pressed_level: PressedLevel,
+    button: Button,
     pin_edge: &'a Notification,
+    config: ButtonConfig,
     pressed_sink_msg: &'a str,
     pressed_sink: &'a [&'a Notification],
 ) {
-    process(
+    gesture_process(
         pin,
         pressed_level,
+        button,
         pin_edge,
-        Some(Duration::from_millis(50)),
+        config,
         pressed_sink_msg,
         pressed_sink,
+        &crate::screen::GESTURE,
     )
     .await;
 }
@@ -88,17 +180,244 @@ pub async fn process<'a>(
 
         log::info!("[{}]", pressed_sink_msg);
 
+        sleep::mark(Activity::User);
+
         for notification in pressed_sink {
             notification.notify();
         }
     }
 }
 
+/// Like `process`, but also reports the gestures of the button to `gesture_sink`.
+///
+/// The press is only reported on release, and not at all when a hold or a combo came with it,
+/// so that holding a button does not act on the page as well.
+/// A double press is still preceded by the notification of the first press.
+#[allow(clippy::too_many_arguments)]
+pub async fn gesture_process<'a>(
+    pin: impl InputPin,
+    pressed_level: PressedLevel,
+    button: Button,
+    pin_edge: &'a Notification,
+    config: ButtonConfig,
+    pressed_sink_msg: &'a str,
+    pressed_sink: &'a [&'a Notification],
+    gesture_sink: &'a Signal<CriticalSectionRawMutex, Gesture>,
+) {
+    let gesture = |gesture| {
+        log::info!("[{}]: {:?}", pressed_sink_msg, gesture);
+
+        gesture_sink.signal(gesture);
+    };
+
+    let mut tracker = GestureTracker::new(button, config);
+
+    loop {
+        let timer = if let Some(deadline) = tracker.deadline() {
+            futures::future::Either::Left(Timer::at(deadline))
+        } else {
+            futures::future::Either::Right(pending())
+        };
+
+        if let Either::First(_) = select(pin_edge.wait(), timer).await {
+            tracker.edge(Instant::now(), is_pressed(&pin, pressed_level));
+        }
+
+        while let Some(event) = tracker.expire(Instant::now()) {
+            match event {
+                ButtonEvent::Pressed { double_press } => {
+                    sleep::mark(Activity::User);
+
+                    if double_press {
+                        gesture(Gesture::DoublePress(button));
+                    }
+
+                    if hold_down(button) {
+                        gesture(Gesture::Combo);
+                    }
+                }
+                ButtonEvent::Gesture(hold) => gesture(hold),
+                ButtonEvent::Released { press } => {
+                    let combo = let_go(button);
+
+                    if press && !combo {
+                        log::info!("[{}]", pressed_sink_msg);
+
+                        for notification in pressed_sink {
+                            notification.notify();
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// What `GestureTracker` tells from the edges of a button
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ButtonEvent {
+    Pressed {
+        double_press: bool,
+    },
+    /// A hold or a long hold, while the button is still pressed
+    Gesture(Gesture),
+    Released {
+        /// Whether it counts as a press, i.e. no hold came with it
+        press: bool,
+    },
+}
+
+/// Tells the presses and holds of a button from its debounced edges.
+///
+/// It has no timers of its own: `expire` is to be called whenever `deadline` is reached,
+/// so that it can be fed with made up times as well.
+#[derive(Copy, Clone, Debug)]
+pub struct GestureTracker {
+    button: Button,
+    config: ButtonConfig,
+    /// The level of the pin as of its last edge, and when it is considered settled
+    level: bool,
+    settles_at: Option<Instant>,
+    /// When the button was pressed and the holds told since then, while it is pressed
+    pressed: Option<(Instant, usize)>,
+    last_press: Option<Instant>,
+}
+
+impl GestureTracker {
+    pub const fn new(button: Button, config: ButtonConfig) -> Self {
+        Self {
+            button,
+            config,
+            level: false,
+            settles_at: None,
+            pressed: None,
+            last_press: None,
+        }
+    }
+
+    /// Records an edge of the pin, with the level it changed to; bounces restart the debounce
+    pub fn edge(&mut self, at: Instant, pressed: bool) {
+        self.level = pressed;
+        self.settles_at = Some(at + self.config.debounce);
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        let hold = self
+            .pressed
+            .and_then(|(at, holds)| self.holds().get(holds).map(|(after, _)| at + *after));
+
+        match (self.settles_at, hold) {
+            (Some(settles_at), Some(hold)) => Some(settles_at.min(hold)),
+            (settles_at, hold) => settles_at.or(hold),
+        }
+    }
+
+    /// Returns the next event due by `now`, if any
+    pub fn expire(&mut self, now: Instant) -> Option<ButtonEvent> {
+        if let Some(settled_at) = self.settles_at.filter(|at| now >= *at) {
+            self.settles_at = None;
+
+            match (self.level, self.pressed) {
+                (true, None) => {
+                    let double_press = self
+                        .last_press
+                        .map(|last_press| settled_at - last_press <= self.config.double_press)
+                        .unwrap_or(false);
+
+                    // A third press starts over
+                    self.last_press = if double_press { None } else { Some(settled_at) };
+                    self.pressed = Some((settled_at, 0));
+
+                    return Some(ButtonEvent::Pressed { double_press });
+                }
+                (false, Some((_, holds))) => {
+                    self.pressed = None;
+
+                    return Some(ButtonEvent::Released { press: holds == 0 });
+                }
+                _ => (),
+            }
+        }
+
+        if let Some((at, holds)) = self.pressed {
+            if let Some((after, hold)) = self.holds().get(holds) {
+                if now >= at + *after {
+                    self.pressed = Some((at, holds + 1));
+
+                    return Some(ButtonEvent::Gesture(*hold));
+                }
+            }
+        }
+
+        None
+    }
+
+    fn holds(&self) -> [(Duration, Gesture); 2] {
+        [
+            (self.config.hold, Gesture::Hold(self.button)),
+            (self.config.long_hold, Gesture::LongHold(self.button)),
+        ]
+    }
+}
+
+/// Marks the button as held, returning whether Button1 and Button2 are now held together
+fn hold_down(button: Button) -> bool {
+    HELD.lock(|held| {
+        let (mut buttons, combo) = held.get();
+        buttons[button as usize] = true;
+
+        let new_combo = button != Button::Button3
+            && buttons[Button::Button1 as usize]
+            && buttons[Button::Button2 as usize];
+
+        held.set((buttons, combo || new_combo));
+
+        new_combo
+    })
+}
+
+/// Marks the button as released, returning whether it was part of a combo, which is no press
+fn let_go(button: Button) -> bool {
+    HELD.lock(|held| {
+        let (mut buttons, combo) = held.get();
+        buttons[button as usize] = false;
+
+        let in_combo = combo && button != Button::Button3;
+        let combo_held = buttons[Button::Button1 as usize] || buttons[Button::Button2 as usize];
+
+        held.set((buttons, combo && combo_held));
+
+        in_combo
+    })
+}
+
 pub async fn wait_press<'a>(
     pin: &mut impl InputPin,
     pressed_level: PressedLevel,
     pin_edge: &'a Notification,
     debounce_duration: Option<Duration>,
+) {
+    wait_level(pin, pressed_level, true, pin_edge, debounce_duration).await
+}
+
+/// Returns immediately if the button is not pressed
+pub async fn wait_release<'a>(
+    pin: &mut impl InputPin,
+    pressed_level: PressedLevel,
+    pin_edge: &'a Notification,
+    debounce_duration: Option<Duration>,
+) {
+    if is_pressed(&*pin, pressed_level) {
+        wait_level(pin, pressed_level, false, pin_edge, debounce_duration).await
+    }
+}
+
+async fn wait_level<'a>(
+    pin: &mut impl InputPin,
+    pressed_level: PressedLevel,
+    pressed: bool,
+    pin_edge: &'a Notification,
+    debounce_duration: Option<Duration>,
 ) {
     let mut debounce = false;
 
@@ -135,44 +454,64 @@ pub async fn wait_press<'a>(
             }
         };
 
-        if check {
-            let pressed = pin.is_high().unwrap_or(pressed_level != PressedLevel::High)
-                == (pressed_level == PressedLevel::High);
-
-            if pressed {
-                return;
-            }
+        if check && is_pressed(&*pin, pressed_level) == pressed {
+            return;
         }
     }
 }
 
+pub(crate) fn is_pressed(pin: &impl InputPin, pressed_level: PressedLevel) -> bool {
+    pin.is_high().unwrap_or(pressed_level != PressedLevel::High)
+        == (pressed_level == PressedLevel::High)
+}
+
 pub async fn button1_button2_roller_process<'a>(
     pin_1: impl InputPin<Error = impl Debug>,
     pin_2: impl InputPin<Error = impl Debug>,
+    push_pin: impl InputPin,
+    pressed_level: PressedLevel,
+    config: ButtonConfig,
 ) {
-    roller_process(
-        pin_1,
-        pin_2,
-        &BUTTON1_PIN_EDGE,
-        &BUTTON2_PIN_EDGE,
-        Some(Duration::from_millis(50)),
-        "ROLLER",
-        BUTTON1_NOTIFY,
-        BUTTON2_NOTIFY,
+    // The push switch of the encoder acts as button3
+    select(
+        roller_process(
+            pin_1,
+            pin_2,
+            &BUTTON1_PIN_EDGE,
+            &BUTTON2_PIN_EDGE,
+            Some(config.debounce),
+            Some((config.roller_acceleration, config.roller_max_steps)),
+            "ROLLER",
+            BUTTON1_NOTIFY,
+            BUTTON2_NOTIFY,
+        ),
+        button_process(
+            push_pin,
+            pressed_level,
+            Button::Button3,
+            &BUTTON3_PIN_EDGE,
+            config,
+            "ROLLER PUSH",
+            BUTTON3_NOTIFY,
+        ),
     )
     .await;
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn roller_process<'a>(
     mut pin_1: impl InputPin<Error = impl Debug>,
     mut pin_2: impl InputPin<Error = impl Debug>,
     pin_1_edge: &'a Notification,
     pin_2_edge: &'a Notification,
     debounce_duration: Option<Duration>,
+    acceleration: Option<(Duration, u32)>,
     rolled_sink_msg: &'a str,
     rolled_clockwise_sink: &'a [&'a Notification],
     rolled_counter_clockwise_sink: &'a [&'a Notification],
 ) {
+    let mut last_rolled: Option<(Instant, bool)> = None;
+
     loop {
         let clockwise = wait_roller(
             &mut pin_1,
@@ -183,7 +522,26 @@ pub async fn roller_process<'a>(
         )
         .await;
 
-        log::info!("[{}]: {}", rolled_sink_msg, clockwise);
+        let now = Instant::now();
+
+        let steps = match (acceleration, last_rolled) {
+            (Some((window, max_steps)), Some((last, last_clockwise)))
+                if last_clockwise == clockwise && now < last + window =>
+            {
+                let interval = (now - last).as_millis().max(1);
+
+                ((window.as_millis() / interval) as u32).clamp(1, max_steps.max(1))
+            }
+            _ => 1,
+        };
+
+        last_rolled = Some((now, clockwise));
+
+        ROLLED_STEPS.lock(|rolled| rolled.set(rolled.get().saturating_add(steps)));
+
+        log::info!("[{}]: {} x{}", rolled_sink_msg, clockwise, steps);
+
+        sleep::mark(Activity::User);
 
         let sink = if clockwise {
             rolled_clockwise_sink
@@ -271,3 +629,180 @@ pub async fn wait_roller<'a>(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Mutex as StdMutex, PoisonError};
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// The combos share `HELD`, so the tests must not run concurrently
+    static SERIAL: StdMutex<()> = StdMutex::new(());
+
+    /// The default timings, scaled down
+    const CONFIG: ButtonConfig = ButtonConfig {
+        debounce: Duration::from_millis(5),
+        double_press: Duration::from_millis(100),
+        hold: Duration::from_millis(300),
+        long_hold: Duration::from_millis(1000),
+        ..ButtonConfig::new()
+    };
+
+    const fn pressed(double_press: bool) -> ButtonEvent {
+        ButtonEvent::Pressed { double_press }
+    }
+
+    const fn released(press: bool) -> ButtonEvent {
+        ButtonEvent::Released { press }
+    }
+
+    /// The events of Button1, pressed (`true`) and released at the given milliseconds
+    fn events(edges: &[(u64, bool)]) -> Vec<ButtonEvent> {
+        let mut tracker = GestureTracker::new(Button::Button1, CONFIG);
+        let mut events = Vec::new();
+
+        let mut expire = |tracker: &mut GestureTracker, until: Instant| {
+            while let Some(deadline) = tracker.deadline().filter(|deadline| *deadline <= until) {
+                events.extend(tracker.expire(deadline));
+            }
+        };
+
+        for (at, pressed) in edges {
+            let at = Instant::from_millis(*at);
+
+            expire(&mut tracker, at);
+            tracker.edge(at, *pressed);
+        }
+
+        // Let the gestures settle
+        expire(&mut tracker, Instant::from_secs(60));
+
+        events
+    }
+
+    #[test]
+    fn short_press() {
+        assert_eq!(
+            events(&[(0, true), (100, false)]),
+            [pressed(false), released(true)]
+        );
+    }
+
+    #[test]
+    fn hold() {
+        assert_eq!(
+            events(&[(0, true), (500, false)]),
+            [
+                pressed(false),
+                ButtonEvent::Gesture(Gesture::Hold(Button::Button1)),
+                released(false)
+            ]
+        );
+    }
+
+    #[test]
+    fn long_hold() {
+        assert_eq!(
+            events(&[(0, true), (1200, false)]),
+            [
+                pressed(false),
+                ButtonEvent::Gesture(Gesture::Hold(Button::Button1)),
+                ButtonEvent::Gesture(Gesture::LongHold(Button::Button1)),
+                released(false)
+            ]
+        );
+    }
+
+    #[test]
+    fn double_press() {
+        assert_eq!(
+            events(&[(0, true), (30, false), (60, true), (90, false)]),
+            [
+                pressed(false),
+                released(true),
+                pressed(true),
+                released(true)
+            ]
+        );
+    }
+
+    #[test]
+    fn third_press_starts_over() {
+        assert_eq!(
+            events(&[
+                (0, true),
+                (30, false),
+                (60, true),
+                (90, false),
+                (120, true),
+                (150, false),
+            ]),
+            [
+                pressed(false),
+                released(true),
+                pressed(true),
+                released(true),
+                pressed(false),
+                released(true)
+            ]
+        );
+    }
+
+    #[test]
+    fn slow_presses() {
+        assert_eq!(
+            events(&[(0, true), (30, false), (300, true), (330, false)]),
+            [
+                pressed(false),
+                released(true),
+                pressed(false),
+                released(true)
+            ]
+        );
+    }
+
+    #[test]
+    fn combo() {
+        let _serial = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+
+        assert!(!hold_down(Button::Button1));
+        assert!(!hold_down(Button::Button3));
+        assert!(hold_down(Button::Button2));
+
+        // Neither of the buttons of the combo is a press of its own, whichever is released first
+        assert!(!let_go(Button::Button3));
+        assert!(let_go(Button::Button2));
+        assert!(let_go(Button::Button1));
+
+        assert!(!hold_down(Button::Button1));
+        assert!(!let_go(Button::Button1));
+    }
+
+    #[test]
+    fn bounces_count_as_one_press() {
+        assert_eq!(
+            events(&[
+                (0, true),
+                (1, false),
+                (2, true),
+                (50, false),
+                (51, true),
+                (52, false),
+            ]),
+            [pressed(false), released(true)]
+        );
+    }
+
+    #[test]
+    fn glitches_are_no_press() {
+        // Shorter than the debounce
+        assert!(events(&[(10, true), (12, false)]).is_empty());
+
+        // Longer than the debounce
+        assert_eq!(
+            events(&[(10, true), (30, false)]),
+            [pressed(false), released(true)]
+        );
+    }
+}
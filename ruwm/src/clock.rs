@@ -0,0 +1,273 @@
+use core::cell::{Cell, RefCell};
+
+use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, RawMutex};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::{Duration, Instant, Timer};
+
+use channel_bridge::notification::Notification;
+
+use crate::state::State;
+use crate::storage::{self, Storage, Versioned};
+
+pub use crate::dto::time::*;
+
+/// Any wall clock time before that (2023-01-01) is considered not set
+pub const MIN_VALID_TIME_SECS: u64 = 1_672_531_200;
+
+const SYNCED_POLL: Duration = Duration::from_secs(60 * 10);
+const UNSYNCED_POLL: Duration = Duration::from_secs(2);
+
+const STORAGE_KEY: &str = "tz";
+
+/// A source of the wall clock time, in seconds since the Unix epoch (UTC).
+pub trait Clock {
+    fn now(&self) -> Option<u64>;
+}
+
+impl<T> Clock for &T
+where
+    T: Clock,
+{
+    fn now(&self) -> Option<u64> {
+        (*self).now()
+    }
+}
+
+/// A clock which is only set explicitly
+pub struct FakeClock(Mutex<CriticalSectionRawMutex, Cell<Option<u64>>>);
+
+impl FakeClock {
+    pub const fn new(now: Option<u64>) -> Self {
+        Self(Mutex::new(Cell::new(now)))
+    }
+
+    pub fn set(&self, now: Option<u64>) {
+        self.0.lock(|cell| cell.set(now));
+    }
+
+    pub fn advance(&self, secs: u64) {
+        self.0
+            .lock(|cell| cell.set(cell.get().map(|now| now + secs)));
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> Option<u64> {
+        self.0.lock(Cell::get)
+    }
+}
+
+/// A clock backed by the OS time, which - on ESP-IDF - is kept by the RTC across deep sleep
+#[cfg(feature = "std")]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> Option<u64> {
+        extern crate std;
+
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()
+            .map(|duration| duration.as_secs())
+            .filter(|secs| *secs >= MIN_VALID_TIME_SECS)
+    }
+}
+
+pub static STATE: State<bool> = State::new(
+    "CLOCK SYNCED",
+    false,
+    &[
+        &crate::keepalive::NOTIF,
+        &crate::wm_stats::CLOCK_STATE_NOTIF,
+        &crate::schedule::CLOCK_STATE_NOTIF,
+    ],
+);
+
+pub static TIME_ZONE: State<TimeZone> = State::new(
+    "TIME ZONE",
+    TimeZone::UTC,
+    &[
+        &crate::wm_stats::CLOCK_STATE_NOTIF,
+        &crate::schedule::CLOCK_STATE_NOTIF,
+        &TIME_ZONE_PERSIST_NOTIFY,
+    ],
+);
+
+static TIME_ZONE_PERSIST_NOTIFY: Notification = Notification::new();
+
+// Wall clock seconds at `Instant` zero
+static EPOCH_OFFSET: Mutex<CriticalSectionRawMutex, Cell<Option<u64>>> =
+    Mutex::new(Cell::new(None));
+
+/// The current wall clock time in UTC seconds, if the clock is synchronized
+pub fn now() -> Option<u64> {
+    EPOCH_OFFSET
+        .lock(Cell::get)
+        .map(|offset| offset + Instant::now().as_secs())
+}
+
+/// The current local time, if the clock is synchronized
+pub fn local_now() -> Option<DateTime> {
+    now().map(|now| TIME_ZONE.get().local(now))
+}
+
+pub async fn process(clock: impl Clock) {
+    loop {
+        let synced = if let Some(now) = clock.now() {
+            EPOCH_OFFSET.lock(|offset| offset.set(Some(now - Instant::now().as_secs())));
+
+            true
+        } else {
+            false
+        };
+
+        STATE.update(synced);
+
+        Timer::after(if synced { SYNCED_POLL } else { UNSYNCED_POLL }).await;
+    }
+}
+
+impl Versioned for TimeZone {
+    const VERSION: u8 = 1;
+}
+
+pub fn restore(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>) {
+    if let Some(time_zone) = storage::restore(storage, STORAGE_KEY) {
+        TIME_ZONE.set(time_zone);
+    }
+}
+
+pub async fn persist(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>) {
+    loop {
+        TIME_ZONE_PERSIST_NOTIFY.wait().await;
+
+        storage::persist(storage, STORAGE_KEY, &TIME_ZONE.get());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn at(year: u16, month: u8, day: u8, hour: u8, minute: u8, weekday: u8) -> DateTime {
+        DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second: 0,
+            weekday,
+        }
+    }
+
+    fn local(clock: &FakeClock, time_zone: TimeZone) -> DateTime {
+        let now = clock.now().unwrap();
+        let local = time_zone.local(now);
+
+        assert_eq!(
+            DateTime::from_secs(time_zone.to_local(now)).to_secs(),
+            time_zone.to_local(now)
+        );
+
+        local
+    }
+
+    #[test]
+    fn springs_forward() {
+        // 2023-03-26 00:59 UTC, a minute before the summer time starts
+        let clock = FakeClock::new(Some(1_679_792_340));
+
+        assert_eq!(
+            local(&clock, TimeZone::CENTRAL_EUROPE),
+            at(2023, 3, 26, 1, 59, 6)
+        );
+        assert_eq!(
+            local(&clock, TimeZone::EASTERN_EUROPE),
+            at(2023, 3, 26, 2, 59, 6)
+        );
+
+        clock.advance(SECS_PER_MINUTE);
+
+        assert_eq!(
+            local(&clock, TimeZone::CENTRAL_EUROPE),
+            at(2023, 3, 26, 3, 0, 6)
+        );
+        assert_eq!(
+            local(&clock, TimeZone::EASTERN_EUROPE),
+            at(2023, 3, 26, 4, 0, 6)
+        );
+    }
+
+    #[test]
+    fn falls_back() {
+        // 2023-10-29 00:59 UTC, a minute before the summer time ends
+        let clock = FakeClock::new(Some(1_698_541_140));
+
+        assert_eq!(
+            local(&clock, TimeZone::CENTRAL_EUROPE),
+            at(2023, 10, 29, 2, 59, 6)
+        );
+
+        // The hour from 02:00 to 03:00 comes twice
+        clock.advance(SECS_PER_MINUTE);
+
+        assert_eq!(
+            local(&clock, TimeZone::CENTRAL_EUROPE),
+            at(2023, 10, 29, 2, 0, 6)
+        );
+
+        clock.advance(SECS_PER_HOUR);
+
+        assert_eq!(
+            local(&clock, TimeZone::CENTRAL_EUROPE),
+            at(2023, 10, 29, 3, 0, 6)
+        );
+    }
+
+    #[test]
+    fn counts_leap_days() {
+        // 2024-02-28 23:30 UTC
+        let clock = FakeClock::new(Some(1_709_163_000));
+
+        assert_eq!(local(&clock, TimeZone::UTC), at(2024, 2, 28, 23, 30, 2));
+
+        clock.advance(SECS_PER_HOUR);
+        assert_eq!(local(&clock, TimeZone::UTC), at(2024, 2, 29, 0, 30, 3));
+
+        clock.advance(SECS_PER_DAY);
+        assert_eq!(local(&clock, TimeZone::UTC), at(2024, 3, 1, 0, 30, 4));
+
+        // 2023-02-28 12:00 UTC
+        clock.set(Some(1_677_585_600));
+        clock.advance(SECS_PER_DAY);
+        assert_eq!(local(&clock, TimeZone::UTC), at(2023, 3, 1, 12, 0, 2));
+
+        // Centuries are leap years only every 400 years
+        clock.set(Some(4_107_499_200));
+        clock.advance(SECS_PER_DAY);
+        assert_eq!(local(&clock, TimeZone::UTC), at(2100, 3, 1, 12, 0, 0));
+
+        clock.set(Some(951_739_200));
+        clock.advance(SECS_PER_DAY);
+        assert_eq!(local(&clock, TimeZone::UTC), at(2000, 2, 29, 12, 0, 1));
+    }
+
+    #[test]
+    fn crosses_years() {
+        // 2023-12-31 23:30 UTC, already the new year in central Europe
+        let clock = FakeClock::new(Some(1_704_065_400));
+
+        assert_eq!(local(&clock, TimeZone::UTC), at(2023, 12, 31, 23, 30, 6));
+        assert_eq!(
+            local(&clock, TimeZone::CENTRAL_EUROPE),
+            at(2024, 1, 1, 0, 30, 0)
+        );
+
+        assert_eq!(
+            local(&clock, TimeZone::CENTRAL_EUROPE).month_index(),
+            local(&clock, TimeZone::UTC).month_index() + 1
+        );
+    }
+}
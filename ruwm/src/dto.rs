@@ -1,4 +1,12 @@
+pub mod alert;
 pub mod battery;
+pub mod budget;
+pub mod i18n;
+pub mod leak_sensor;
+pub mod power;
+pub mod schedule;
+pub mod settings;
+pub mod time;
 pub mod valve;
 pub mod water_meter;
 pub mod water_meter_stats;
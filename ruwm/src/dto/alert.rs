@@ -0,0 +1,113 @@
+use core::fmt::Debug;
+
+use serde::{Deserialize, Serialize};
+
+use super::i18n::Message;
+use super::leak_sensor::LeakSensorId;
+
+pub const MAX_ALERTS: usize = 7;
+
+/// The kinds of alerts, most important first
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum AlertKind {
+    Leak,
+    Flood,
+    ValveFault,
+    Backflow,
+    SensorFault,
+    LowBattery,
+    WifiLost,
+}
+
+impl AlertKind {
+    pub fn message(&self) -> Message {
+        match self {
+            Self::Leak => Message::Leak,
+            Self::Flood => Message::Flood,
+            Self::ValveFault => Message::ValveFault,
+            Self::Backflow => Message::Backflow,
+            Self::SensorFault => Message::SensorFault,
+            Self::LowBattery => Message::LowBattery,
+            Self::WifiLost => Message::WifiLost,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Leak => "leak",
+            Self::Flood => "flood",
+            Self::ValveFault => "valve_fault",
+            Self::Backflow => "backflow",
+            Self::SensorFault => "sensor_fault",
+            Self::LowBattery => "low_battery",
+            Self::WifiLost => "wifi_lost",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Alert {
+    pub kind: AlertKind,
+    pub acknowledged: bool,
+    /// Wall clock time when the alert was raised, in UTC seconds
+    pub raised_secs: Option<u64>,
+    /// The leak sensor which raised a flood alert
+    pub sensor: Option<LeakSensorId>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct AlertsState {
+    /// The active alerts, most important first
+    pub alerts: heapless::Vec<Alert, MAX_ALERTS>,
+}
+
+impl AlertsState {
+    pub const fn new() -> Self {
+        Self {
+            alerts: heapless::Vec::new(),
+        }
+    }
+
+    /// Raises the alert, unless it is already active
+    pub fn raise(&mut self, kind: AlertKind, sensor: Option<LeakSensorId>, now_secs: Option<u64>) {
+        if self.alerts.iter().all(|alert| alert.kind != kind) {
+            // There is a slot for each alert kind, so this cannot fail
+            let _ = self.alerts.push(Alert {
+                kind,
+                acknowledged: false,
+                raised_secs: now_secs,
+                sensor,
+            });
+
+            self.alerts.sort_unstable_by_key(|alert| alert.kind);
+        }
+    }
+
+    /// Removes the alert once its cause is gone, whether it was acknowledged or not
+    pub fn clear(&mut self, kind: AlertKind) {
+        if let Some(index) = self.alerts.iter().position(|alert| alert.kind == kind) {
+            self.alerts.swap_remove(index);
+            self.alerts.sort_unstable_by_key(|alert| alert.kind);
+        }
+    }
+
+    /// Acknowledges the alert of the given kind, or all alerts if no kind is given
+    pub fn acknowledge(&mut self, kind: Option<AlertKind>) {
+        for alert in self.alerts.iter_mut() {
+            if kind.map(|kind| kind == alert.kind).unwrap_or(true) {
+                alert.acknowledged = true;
+            }
+        }
+    }
+
+    /// The most important alert which is not acknowledged yet
+    pub fn top(&self) -> Option<&Alert> {
+        self.alerts.iter().find(|alert| !alert.acknowledged)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AlertCommand {
+    Acknowledge(AlertKind),
+    AcknowledgeAll,
+}
@@ -1,36 +1,243 @@
-use core::{
-    cmp::{max, min},
-    fmt::Debug,
-};
+use core::fmt::Debug;
 
 use serde::{Deserialize, Serialize};
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BatteryChemistry {
+    /// The charge grows linearly from the low to the max voltage of the profile
+    Linear = 0,
+    LiIon = 1,
+    LiFePo4 = 2,
+}
+
+impl BatteryChemistry {
+    pub const ALL: [Self; 3] = [Self::Linear, Self::LiIon, Self::LiFePo4];
+
+    pub fn from_index(index: u32) -> Self {
+        Self::ALL[(index as usize).min(Self::ALL.len() - 1)]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Linear => "Linear",
+            Self::LiIon => "Li-ion",
+            Self::LiFePo4 => "LiFePO4",
+        }
+    }
+
+    /// The discharge curve of a single cell as (mV, %) points, by descending voltage
+    fn curve(&self) -> &'static [(u16, u8)] {
+        match self {
+            Self::Linear => &[],
+            Self::LiIon => &[
+                (4200, 100),
+                (4100, 90),
+                (4000, 80),
+                (3900, 65),
+                (3800, 50),
+                (3700, 30),
+                (3600, 15),
+                (3500, 8),
+                (3300, 3),
+                (3000, 0),
+            ],
+            Self::LiFePo4 => &[
+                (3600, 100),
+                (3400, 99),
+                (3350, 90),
+                (3300, 70),
+                (3250, 40),
+                (3200, 20),
+                (3100, 10),
+                (3000, 5),
+                (2500, 0),
+            ],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BatteryLevel {
+    Normal,
+    Low,
+    Critical,
+}
+
+/// How to interpret the voltage measured on the battery pin
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatteryProfile {
+    pub chemistry: BatteryChemistry,
+    /// The battery voltage in mV per 1000 mV measured, as set by the voltage divider
+    pub divider_ratio: u16,
+    pub low_voltage: u16,
+    pub critical_voltage: u16,
+    /// The voltage of a full battery, for the linear chemistry and for reporting it as charged
+    pub max_voltage: u16,
+    /// How much above a threshold the voltage has to rise before the level goes back up
+    pub hysteresis: u16,
+}
+
+impl BatteryProfile {
+    pub const fn new() -> Self {
+        Self {
+            chemistry: BatteryChemistry::Linear,
+            divider_ratio: 1000,
+            low_voltage: BatteryState::LOW_VOLTAGE,
+            critical_voltage: BatteryState::CRITICAL_VOLTAGE,
+            max_voltage: BatteryState::MAX_VOLTAGE,
+            hysteresis: 50,
+        }
+    }
+
+    /// The battery voltage, given the voltage measured on the pin
+    pub fn voltage(&self, measured: u16) -> u16 {
+        (measured as u32 * self.divider_ratio as u32 / 1000).min(u16::MAX as _) as u16
+    }
+
+    /// The charge in percents
+    pub fn percentage(&self, voltage: u16) -> u8 {
+        let curve = self.chemistry.curve();
+
+        if curve.is_empty() {
+            if self.max_voltage <= self.low_voltage {
+                return 0;
+            }
+
+            let voltage = voltage.clamp(self.low_voltage, self.max_voltage);
+
+            return ((voltage - self.low_voltage) as u32 * 100
+                / (self.max_voltage - self.low_voltage) as u32) as u8;
+        }
+
+        if voltage >= curve[0].0 {
+            return curve[0].1;
+        }
+
+        // Interpolate between the two points around the voltage
+        for points in curve.windows(2) {
+            let (high_voltage, high_percentage) = points[0];
+            let (low_voltage, low_percentage) = points[1];
+
+            if voltage >= low_voltage {
+                return low_percentage
+                    + ((voltage - low_voltage) as u32 * (high_percentage - low_percentage) as u32
+                        / (high_voltage - low_voltage) as u32) as u8;
+            }
+        }
+
+        0
+    }
+
+    /// The level for the voltage; going back up needs the hysteresis on top of the threshold
+    pub fn level(&self, voltage: u16, prev_level: Option<BatteryLevel>) -> BatteryLevel {
+        let above = |threshold: u16, was_below: bool| {
+            if was_below {
+                voltage > threshold.saturating_add(self.hysteresis)
+            } else {
+                voltage > threshold
+            }
+        };
+
+        let prev_level = prev_level.unwrap_or(BatteryLevel::Normal);
+
+        if !above(self.critical_voltage, prev_level == BatteryLevel::Critical) {
+            BatteryLevel::Critical
+        } else if !above(self.low_voltage, prev_level != BatteryLevel::Normal) {
+            BatteryLevel::Low
+        } else {
+            BatteryLevel::Normal
+        }
+    }
+}
+
+impl Default for BatteryProfile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct BatteryState {
+    /// In mV, of the battery itself rather than of the pin
     pub voltage: Option<u16>,
     pub powered: Option<bool>,
+    /// In percents
+    pub charge: Option<u8>,
+    pub level: Option<BatteryLevel>,
+    /// In seconds, estimated from how fast the voltage drops towards the critical voltage
+    pub time_to_empty: Option<u32>,
 }
 
 impl BatteryState {
+    // The defaults of the corresponding settings
     pub const LOW_VOLTAGE: u16 = 2700;
+    pub const CRITICAL_VOLTAGE: u16 = 2600;
     pub const MAX_VOLTAGE: u16 = 3100;
 
     pub const fn new() -> Self {
         Self {
             voltage: None,
             powered: None,
+            charge: None,
+            level: None,
+            time_to_empty: None,
         }
     }
 
-    pub fn percentage(&self) -> Option<u8> {
-        self.voltage.map(|voltage| {
-            let voltage = max(
-                BatteryState::LOW_VOLTAGE,
-                min(BatteryState::MAX_VOLTAGE, voltage),
-            );
+    pub fn charged(&self) -> Option<bool> {
+        self.charge.map(|charge| charge >= 100)
+    }
+}
+
+pub const VOLTAGE_HISTORY_DAYS: usize = 7;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoltageRange {
+    /// Local days since the Unix epoch
+    pub day: u64,
+    pub min: u16,
+    pub max: u16,
+}
+
+/// The lowest and highest battery voltage of each of the last days, oldest first
+#[derive(Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct VoltageHistory {
+    pub days: heapless::Vec<VoltageRange, VOLTAGE_HISTORY_DAYS>,
+}
+
+impl VoltageHistory {
+    pub const fn new() -> Self {
+        Self {
+            days: heapless::Vec::new(),
+        }
+    }
+
+    /// Records the voltage for the day, returning whether the history has changed
+    pub fn record(&mut self, day: u64, voltage: u16) -> bool {
+        if let Some(range) = self.days.last_mut() {
+            if range.day == day {
+                let changed = voltage < range.min || voltage > range.max;
+
+                range.min = range.min.min(voltage);
+                range.max = range.max.max(voltage);
+
+                return changed;
+            } else if range.day > day {
+                // The clock went back; keep the history as it is
+                return false;
+            }
+        }
+
+        if self.days.is_full() {
+            self.days = self.days.iter().skip(1).copied().collect();
+        }
+
+        let _ = self.days.push(VoltageRange {
+            day,
+            min: voltage,
+            max: voltage,
+        });
 
-            ((voltage - BatteryState::LOW_VOLTAGE) * 100
-                / (BatteryState::MAX_VOLTAGE - BatteryState::LOW_VOLTAGE)) as u8
-        })
+        true
     }
 }
@@ -0,0 +1,105 @@
+use core::fmt::Debug;
+
+use serde::{Deserialize, Serialize};
+
+use super::water_meter_stats::{WaterMeterStatsState, DAY, MONTH};
+
+pub const WARNING_PERCENTAGE: u64 = 80;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BudgetPeriod {
+    Day,
+    Month,
+}
+
+impl BudgetPeriod {
+    pub fn duration_secs(&self) -> u64 {
+        match self {
+            Self::Day => DAY,
+            Self::Month => MONTH,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BudgetConfig {
+    pub period: BudgetPeriod,
+    /// In litres, over all meters; zero disables the budget
+    pub limit: u64,
+    /// Close the valve once the budget is exceeded
+    pub close_valve: bool,
+}
+
+impl BudgetConfig {
+    pub const fn new() -> Self {
+        Self {
+            period: BudgetPeriod::Day,
+            limit: 0,
+            close_valve: false,
+        }
+    }
+}
+
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BudgetLevel {
+    Normal,
+    Warning,
+    Exceeded,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BudgetState {
+    pub config: BudgetConfig,
+    /// The litres counted by all meters since the start of the current period
+    pub consumed: u64,
+    pub level: BudgetLevel,
+}
+
+impl BudgetState {
+    pub const fn new() -> Self {
+        Self {
+            config: BudgetConfig::new(),
+            consumed: 0,
+            level: BudgetLevel::Normal,
+        }
+    }
+
+    pub fn percentage(&self) -> Option<u64> {
+        (self.config.limit > 0).then(|| self.consumed * 100 / self.config.limit)
+    }
+
+    /// Takes the statistics of each meter with the millilitres per edge of the meter.
+    ///
+    /// The period windows of the statistics follow the local calendar,
+    /// so the consumption resets at midnight or at the start of the month
+    pub fn update<'a>(&mut self, stats: impl IntoIterator<Item = (&'a WaterMeterStatsState, u32)>) {
+        let duration_secs = self.config.period.duration_secs();
+
+        let consumed_ml: u64 = stats
+            .into_iter()
+            .map(|(stats, edge_volume_ml)| {
+                stats.consumption(duration_secs).unwrap_or(0) * edge_volume_ml as u64
+            })
+            .sum();
+
+        self.consumed = consumed_ml / 1000;
+
+        self.level = match self.percentage() {
+            Some(percentage) if percentage >= 100 => BudgetLevel::Exceeded,
+            Some(percentage) if percentage >= WARNING_PERCENTAGE => BudgetLevel::Warning,
+            _ => BudgetLevel::Normal,
+        };
+    }
+}
+
+impl Default for BudgetState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
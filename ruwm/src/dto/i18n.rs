@@ -0,0 +1,203 @@
+use core::fmt::Debug;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Language {
+    En = 0,
+    De = 1,
+    Bg = 2,
+}
+
+impl Language {
+    pub const ALL: [Self; 3] = [Self::En, Self::De, Self::Bg];
+
+    pub const fn new() -> Self {
+        Self::En
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            Self::En => Self::De,
+            Self::De => Self::Bg,
+            Self::Bg => Self::En,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::En => "en",
+            Self::De => "de",
+            Self::Bg => "bg",
+        }
+    }
+
+    /// The name of the language, in that language
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::En => "English",
+            Self::De => "Deutsch",
+            Self::Bg => "Български",
+        }
+    }
+
+    /// The abbreviated name of the weekday, with 0 being Monday
+    pub fn weekday(&self, weekday: u8) -> &'static str {
+        const WEEKDAYS: [[&str; 7]; 3] = [
+            ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"],
+            ["Mo", "Di", "Mi", "Do", "Fr", "Sa", "So"],
+            ["Пн", "Вт", "Ср", "Чт", "Пт", "Сб", "Нд"],
+        ];
+
+        WEEKDAYS[*self as usize][weekday as usize % 7]
+    }
+}
+
+impl Default for Language {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The ids of all translated messages
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Message {
+    Dismiss,
+    OpenValve,
+    CloseValve,
+    Arm,
+    Disarm,
+    CheckForUpdate,
+    Update,
+    Pair,
+    Provision,
+    Reprovision,
+    ChangeLanguage,
+    EditBudget,
+
+    Home,
+    Battery,
+    Network,
+    Settings,
+    Advanced,
+    Stats,
+    Last24h,
+
+    PoweredShort,
+    Budget,
+    BudgetExceeded,
+    SleepIn,
+    Open,
+    Close,
+
+    Leak,
+    Flood,
+    ValveFault,
+    Backflow,
+    SensorFault,
+    LowBattery,
+    WifiLost,
+    Acknowledge,
+    Save,
+
+    LeakThreshold,
+    FlashWriteCycle,
+    KeepaliveTimeout,
+    BatteryChemistry,
+    BatteryDividerRatio,
+    BatteryLowVoltage,
+    BatteryCriticalVoltage,
+    BatteryMaxVoltage,
+    BatteryHysteresis,
+    ValveTurnTicks,
+    OutageValveClose,
+
+    Valve,
+    ValveOpen,
+    ValveOpening,
+    ValveClosed,
+    ValveClosing,
+    Powered,
+    Voltage,
+    TimeToEmpty,
+    Schedule,
+    Vacation,
+    Remove,
+    Yes,
+    No,
+    Unknown,
+}
+
+impl Message {
+    pub fn text(&self, language: Language) -> &'static str {
+        let texts = match self {
+            Self::Dismiss => ["Dismiss", "Abbrechen", "Отказ"],
+            Self::OpenValve => ["Open Valve", "Ventil öffnen", "Отвори крана"],
+            Self::CloseValve => ["Close Valve", "Ventil schließen", "Затвори крана"],
+            Self::Arm => ["Arm", "Aktivieren", "Активирай"],
+            Self::Disarm => ["Disarm", "Deaktivieren", "Деактивирай"],
+            Self::CheckForUpdate => ["Check for Update", "Update suchen", "Търси обновление"],
+            Self::Update => ["Update", "Aktualisieren", "Обнови"],
+            Self::Pair => ["Pair", "Koppeln", "Сдвояване"],
+            Self::Provision => ["Provision", "Einrichten", "Настройка"],
+            Self::Reprovision => ["Reprovision", "Neu einrichten", "Нова настройка"],
+            Self::ChangeLanguage => ["Language", "Sprache", "Език"],
+            Self::EditBudget => ["Budget Limit", "Budgetlimit", "Лимит на бюджета"],
+
+            Self::Home => ["Home", "Start", "Начало"],
+            Self::Battery => ["Battery", "Batterie", "Батерия"],
+            Self::Network => ["Network", "Netzwerk", "Мрежа"],
+            Self::Settings => ["Settings", "Einstellungen", "Настройки"],
+            Self::Advanced => ["Advanced", "Erweitert", "Разширени"],
+            Self::Stats => ["Stats", "Statistik", "Статистика"],
+            Self::Last24h => ["Last 24h", "Letzte 24h", "Последни 24ч"],
+
+            Self::PoweredShort => ["PWR", "NETZ", "ЗАХР"],
+            Self::Budget => ["Budget", "Budget", "Бюджет"],
+            Self::BudgetExceeded => ["Budget exceeded", "Budget überschritten", "Бюджет превишен"],
+            Self::SleepIn => ["Sleep in", "Ruhe in", "Сън след"],
+            Self::Open => ["Open", "Auf", "Отвори"],
+            Self::Close => ["Close", "Zu", "Затвори"],
+
+            Self::Leak => ["Leak detected", "Leck erkannt", "Открит теч"],
+            Self::Flood => ["Water on floor", "Wasser am Boden", "Вода на пода"],
+            Self::ValveFault => ["Valve fault", "Ventilfehler", "Повреда на крана"],
+            Self::Backflow => ["Backflow", "Rückfluss", "Обратен поток"],
+            Self::SensorFault => ["Sensor fault", "Sensorfehler", "Повреда на сензора"],
+            Self::LowBattery => ["Low battery", "Batterie schwach", "Слаба батерия"],
+            Self::WifiLost => ["Wi-Fi lost", "WLAN getrennt", "Няма Wi-Fi"],
+            Self::Acknowledge => ["[3] Ack", "[3] OK", "[3] ОК"],
+            Self::Save => ["[3] Save", "[3] Speichern", "[3] Запази"],
+
+            // At most 12 characters, so that they fit on the Advanced page
+            Self::LeakThreshold => ["Leak edges", "Leck-Impulse", "Имп. за теч"],
+            Self::FlashWriteCycle => ["Flash cycle", "Flash-Zyklus", "Флаш цикъл"],
+            Self::KeepaliveTimeout => ["Awake secs", "Wach Sek.", "Будност сек"],
+            Self::BatteryChemistry => ["Bat. type", "Bat.-Typ", "Вид бат."],
+            Self::BatteryDividerRatio => ["Bat. divider", "Bat.-Teiler", "Бат. делител"],
+            Self::BatteryLowVoltage => ["Bat. low mV", "Bat. min mV", "Бат. мин mV"],
+            Self::BatteryCriticalVoltage => ["Bat. crit mV", "Bat. krit mV", "Бат. крит mV"],
+            Self::BatteryMaxVoltage => ["Bat. max mV", "Bat. max mV", "Бат. макс mV"],
+            Self::BatteryHysteresis => ["Bat. hyst mV", "Bat. Hyst mV", "Бат. хист mV"],
+            Self::ValveTurnTicks => ["Valve ticks", "Ventil-Ticks", "Кран тактове"],
+            Self::OutageValveClose => ["Outage min", "Ausfall Min.", "Авария мин"],
+
+            Self::Valve => ["Valve", "Ventil", "Кран"],
+            Self::ValveOpen => ["open", "offen", "отворен"],
+            Self::ValveOpening => ["opening", "öffnet", "отваря се"],
+            Self::ValveClosed => ["closed", "geschlossen", "затворен"],
+            Self::ValveClosing => ["closing", "schließt", "затваря се"],
+            Self::Powered => ["Powered", "Netzbetrieb", "Захранване"],
+            Self::Voltage => ["Voltage", "Spannung", "Напрежение"],
+            Self::TimeToEmpty => ["Empty in", "Leer in", "Изтощена след"],
+            Self::Schedule => ["Schedule", "Zeitplan", "График"],
+            Self::Vacation => ["Vacation", "Urlaub", "Ваканция"],
+            Self::Remove => ["Remove", "Entfernen", "Премахни"],
+            Self::Yes => ["Yes", "Ja", "Да"],
+            Self::No => ["No", "Nein", "Не"],
+            Self::Unknown => ["Unknown", "Unbekannt", "Неизвестно"],
+        };
+
+        texts[language as usize]
+    }
+}
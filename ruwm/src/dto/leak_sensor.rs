@@ -0,0 +1,30 @@
+use core::fmt::Debug;
+
+use serde::{Deserialize, Serialize};
+
+/// How many external leak sensors a device can watch
+pub const MAX_LEAK_SENSORS: usize = 4;
+
+/// The index of a leak sensor, below `MAX_LEAK_SENSORS`
+pub type LeakSensorId = u8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct LeakSensorState {
+    /// `None` until the sensor reports for the first time
+    pub wet: Option<bool>,
+    /// Wall clock time when the sensor last became wet, in UTC seconds
+    pub wet_since_secs: Option<u64>,
+}
+
+impl LeakSensorState {
+    pub const fn new() -> Self {
+        Self {
+            wet: None,
+            wet_since_secs: None,
+        }
+    }
+
+    pub fn is_wet(&self) -> bool {
+        self.wet == Some(true)
+    }
+}
@@ -0,0 +1,83 @@
+use core::fmt::Debug;
+
+use serde::{Deserialize, Serialize};
+
+pub const POWER_LOG_LEN: usize = 8;
+
+/// A period during which the device ran on battery
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Outage {
+    /// UTC seconds, if the clock was synchronized when the mains power was lost
+    pub started: Option<u64>,
+    /// UTC seconds, if the clock was synchronized when the mains power came back
+    pub ended: Option<u64>,
+    /// Seconds on battery, once the outage is over and if it could be measured
+    pub duration: Option<u32>,
+    pub ongoing: bool,
+}
+
+impl Outage {
+    pub const fn new(started: Option<u64>) -> Self {
+        Self {
+            started,
+            ended: None,
+            duration: None,
+            ongoing: true,
+        }
+    }
+
+    /// Seconds since the outage started, while it lasts
+    pub fn elapsed(&self, now: Option<u64>) -> Option<u64> {
+        if self.ongoing {
+            self.started
+                .zip(now)
+                .map(|(started, now)| now.saturating_sub(started))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct PowerState {
+    /// The most recent outages, oldest first
+    pub outages: heapless::Vec<Outage, POWER_LOG_LEN>,
+    /// The measured seconds on battery over all outages, including the ones no longer in the log
+    pub on_battery_secs: u64,
+}
+
+impl PowerState {
+    pub const fn new() -> Self {
+        Self {
+            outages: heapless::Vec::new(),
+            on_battery_secs: 0,
+        }
+    }
+
+    pub fn outage(&self) -> Option<&Outage> {
+        self.outages.last().filter(|outage| outage.ongoing)
+    }
+
+    pub fn start_outage(&mut self, now: Option<u64>) {
+        if self.outage().is_none() {
+            if self.outages.is_full() {
+                self.outages = self.outages.iter().skip(1).copied().collect();
+            }
+
+            let _ = self.outages.push(Outage::new(now));
+        }
+    }
+
+    /// Ends the ongoing outage, if any; `measured` is the time on battery, if known otherwise than by the clock
+    pub fn end_outage(&mut self, now: Option<u64>, measured: Option<u32>) {
+        if let Some(outage) = self.outages.last_mut().filter(|outage| outage.ongoing) {
+            let duration = measured.or_else(|| outage.elapsed(now).map(|secs| secs as u32));
+
+            outage.ended = now;
+            outage.duration = duration;
+            outage.ongoing = false;
+
+            self.on_battery_secs += duration.unwrap_or(0) as u64;
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use core::fmt::{self, Debug, Display};
+use core::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use super::i18n::Message;
+use super::time::{weekday, SECS_PER_DAY, SECS_PER_MINUTE};
+use super::valve::ValveSet;
+
+pub const MAX_RULES: usize = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScheduleAction {
+    CloseValve,
+    ArmMeter,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScheduleWindow {
+    /// On each day in `weekdays` (bit 0 = Monday) from `start` till `end` minutes
+    /// of the local day. A window with `end <= start` spans midnight.
+    Weekly { weekdays: u8, start: u16, end: u16 },
+    /// From `start` till `end`, in local seconds since the Unix epoch
+    Once { start: u64, end: u64 },
+}
+
+impl ScheduleWindow {
+    pub fn is_active(&self, local_secs: u64) -> bool {
+        match self {
+            Self::Weekly { .. } => {
+                let today = local_secs / SECS_PER_DAY;
+
+                self.weekly_windows(today.saturating_sub(1), today)
+                    .any(|(start, end)| local_secs >= start && local_secs < end)
+            }
+            Self::Once { start, end } => local_secs >= *start && local_secs < *end,
+        }
+    }
+
+    /// The time of the next start (`true`) or end (`false`) of the window, after `local_secs`
+    pub fn next_transition(&self, local_secs: u64) -> Option<(u64, bool)> {
+        match self {
+            Self::Weekly { .. } => {
+                let today = local_secs / SECS_PER_DAY;
+
+                self.weekly_windows(today.saturating_sub(1), today + 7)
+                    .flat_map(|(start, end)| [(start, true), (end, false)])
+                    .filter(|(time, _)| *time > local_secs)
+                    .min_by_key(|(time, _)| *time)
+            }
+            Self::Once { start, end } => IntoIterator::into_iter([(*start, true), (*end, false)])
+                .filter(|(time, _)| *time > local_secs)
+                .min_by_key(|(time, _)| *time),
+        }
+    }
+
+    fn weekly_windows(&self, from_day: u64, to_day: u64) -> impl Iterator<Item = (u64, u64)> {
+        let (weekdays, start, end) = if let Self::Weekly {
+            weekdays,
+            start,
+            end,
+        } = self
+        {
+            (*weekdays, *start as u64, *end as u64)
+        } else {
+            (0, 0, 0)
+        };
+
+        (from_day..=to_day)
+            .filter(move |day| weekdays & (1 << weekday(*day)) != 0)
+            .map(move |day| {
+                let day_start = day * SECS_PER_DAY;
+
+                let end_day_start = if end > start {
+                    day_start
+                } else {
+                    day_start + SECS_PER_DAY
+                };
+
+                (
+                    day_start + start * SECS_PER_MINUTE,
+                    end_day_start + end * SECS_PER_MINUTE,
+                )
+            })
+    }
+}
+
+/// Who created a rule
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RuleSource {
+    User,
+    /// Created and removed by the vacation commands
+    Vacation,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduleRule {
+    pub window: ScheduleWindow,
+    pub action: ScheduleAction,
+    pub source: RuleSource,
+}
+
+/// The text form used by MQTT, e.g. `close weekly 31 22:00-06:00` (weekdays as in
+/// `ScheduleWindow::Weekly`) or `arm once 1700000000-1700086400`
+impl FromStr for ScheduleRule {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+
+        let action = match parts.next() {
+            Some("close") => ScheduleAction::CloseValve,
+            Some("arm") => ScheduleAction::ArmMeter,
+            _ => return Err(()),
+        };
+
+        let window = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some("weekly"), Some(weekdays), Some(range), None) => {
+                let weekdays = weekdays.parse::<u8>().map_err(|_| ())?;
+                let (start, end) = range.split_once('-').ok_or(())?;
+
+                if weekdays == 0 || weekdays >= 1 << 7 {
+                    return Err(());
+                }
+
+                ScheduleWindow::Weekly {
+                    weekdays,
+                    start: parse_minute_of_day(start)?,
+                    end: parse_minute_of_day(end)?,
+                }
+            }
+            (Some("once"), Some(range), None, None) => {
+                let (start, end) = range.split_once('-').ok_or(())?;
+                let start = start.parse::<u64>().map_err(|_| ())?;
+                let end = end.parse::<u64>().map_err(|_| ())?;
+
+                if end <= start {
+                    return Err(());
+                }
+
+                ScheduleWindow::Once { start, end }
+            }
+            _ => return Err(()),
+        };
+
+        Ok(Self {
+            window,
+            action,
+            source: RuleSource::User,
+        })
+    }
+}
+
+impl Display for ScheduleRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.action {
+            ScheduleAction::CloseValve => write!(f, "close {}", self.window),
+            ScheduleAction::ArmMeter => write!(f, "arm {}", self.window),
+        }
+    }
+}
+
+impl Display for ScheduleWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Weekly {
+                weekdays,
+                start,
+                end,
+            } => write!(
+                f,
+                "weekly {} {:02}:{:02}-{:02}:{:02}",
+                weekdays,
+                start / 60,
+                start % 60,
+                end / 60,
+                end % 60
+            ),
+            Self::Once { start, end } => write!(f, "once {}-{}", start, end),
+        }
+    }
+}
+
+fn parse_minute_of_day(s: &str) -> Result<u16, ()> {
+    let (hours, minutes) = s.split_once(':').ok_or(())?;
+    let hours = hours.parse::<u16>().map_err(|_| ())?;
+    let minutes = minutes.parse::<u16>().map_err(|_| ())?;
+
+    if hours < 24 && minutes < 60 {
+        Ok(hours * 60 + minutes)
+    } else {
+        Err(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduledAction {
+    /// Local seconds since the Unix epoch
+    pub time: u64,
+    pub action: ScheduleAction,
+    pub start: bool,
+}
+
+impl ScheduledAction {
+    pub fn message(&self) -> Message {
+        match (self.action, self.start) {
+            (ScheduleAction::CloseValve, true) => Message::Close,
+            (ScheduleAction::CloseValve, false) => Message::Open,
+            (ScheduleAction::ArmMeter, true) => Message::Arm,
+            (ScheduleAction::ArmMeter, false) => Message::Disarm,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct ScheduleState {
+    pub enabled: bool,
+    pub rules: heapless::Vec<ScheduleRule, MAX_RULES>,
+    /// Whether the valve is currently closed by the schedule
+    pub valve_closed: bool,
+    /// The valves the schedule closed, which are the only ones it reopens
+    pub closed_valves: ValveSet,
+    /// Whether the meter is currently armed by the schedule
+    pub meter_armed: bool,
+}
+
+impl ScheduleState {
+    pub const fn new() -> Self {
+        Self {
+            enabled: false,
+            rules: heapless::Vec::new(),
+            valve_closed: false,
+            closed_valves: ValveSet::empty(),
+            meter_armed: false,
+        }
+    }
+
+    pub fn is_active(&self, action: ScheduleAction, local_secs: u64) -> bool {
+        self.enabled
+            && self
+                .rules
+                .iter()
+                .any(|rule| rule.action == action && rule.window.is_active(local_secs))
+    }
+
+    pub fn next_action(&self, local_secs: u64) -> Option<ScheduledAction> {
+        if !self.enabled {
+            return None;
+        }
+
+        self.rules
+            .iter()
+            .filter_map(|rule| {
+                rule.window
+                    .next_transition(local_secs)
+                    .map(|(time, start)| ScheduledAction {
+                        time,
+                        action: rule.action,
+                        start,
+                    })
+            })
+            .min_by_key(|action| action.time)
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScheduleCommand {
+    Enable(bool),
+    SetRules(heapless::Vec<ScheduleRule, MAX_RULES>),
+    AddRule(ScheduleRule),
+    /// Replaces the rule at the given index of `ScheduleState::rules`
+    UpdateRule(u8, ScheduleRule),
+    RemoveRule(u8),
+    /// Close the valve for the given number of days, starting now. Zero ends the vacation.
+    Vacation(u16),
+}
@@ -0,0 +1,188 @@
+use core::fmt::{Debug, Write};
+
+use serde::{Deserialize, Serialize};
+
+use super::battery::{BatteryChemistry, BatteryProfile};
+use super::i18n::Message;
+
+/// The tunables which can be changed on the device itself
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Setting {
+    /// The edges counted while the meter is armed before a leak is reported
+    LeakThreshold,
+    /// The statistics are written once every that many edges, to spare the flash
+    FlashWriteCycle,
+    /// In seconds; how long the device stays awake on battery after the last activity
+    KeepaliveTimeout,
+    /// The index of a `BatteryChemistry`
+    BatteryChemistry,
+    /// In battery mV per 1000 mV measured
+    BatteryDividerRatio,
+    /// In mV
+    BatteryLowVoltage,
+    /// In mV
+    BatteryCriticalVoltage,
+    /// In mV
+    BatteryMaxVoltage,
+    /// In mV
+    BatteryHysteresis,
+    /// How many valve ticks a full turn of the valve takes
+    ValveTurnTicks,
+    /// In minutes; the valve is closed once the mains power is out for that long, 0 disables it
+    OutageValveClose,
+}
+
+impl Setting {
+    pub const ALL: [Self; 11] = [
+        Self::LeakThreshold,
+        Self::FlashWriteCycle,
+        Self::KeepaliveTimeout,
+        Self::BatteryChemistry,
+        Self::BatteryDividerRatio,
+        Self::BatteryLowVoltage,
+        Self::BatteryCriticalVoltage,
+        Self::BatteryMaxVoltage,
+        Self::BatteryHysteresis,
+        Self::ValveTurnTicks,
+        Self::OutageValveClose,
+    ];
+
+    /// The minimum and maximum value, both inclusive
+    pub fn range(&self) -> (u32, u32) {
+        match self {
+            Self::LeakThreshold => (1, 1000),
+            Self::FlashWriteCycle => (1, 1000),
+            Self::KeepaliveTimeout => (5, 600),
+            Self::BatteryChemistry => (0, BatteryChemistry::ALL.len() as u32 - 1),
+            Self::BatteryDividerRatio => (1000, 10000),
+            Self::BatteryLowVoltage => (2000, 4500),
+            Self::BatteryCriticalVoltage => (2000, 4500),
+            Self::BatteryMaxVoltage => (2500, 4500),
+            Self::BatteryHysteresis => (0, 500),
+            Self::ValveTurnTicks => (5, 120),
+            Self::OutageValveClose => (0, 1440),
+        }
+    }
+
+    pub fn message(&self) -> Message {
+        match self {
+            Self::LeakThreshold => Message::LeakThreshold,
+            Self::FlashWriteCycle => Message::FlashWriteCycle,
+            Self::KeepaliveTimeout => Message::KeepaliveTimeout,
+            Self::BatteryChemistry => Message::BatteryChemistry,
+            Self::BatteryDividerRatio => Message::BatteryDividerRatio,
+            Self::BatteryLowVoltage => Message::BatteryLowVoltage,
+            Self::BatteryCriticalVoltage => Message::BatteryCriticalVoltage,
+            Self::BatteryMaxVoltage => Message::BatteryMaxVoltage,
+            Self::BatteryHysteresis => Message::BatteryHysteresis,
+            Self::ValveTurnTicks => Message::ValveTurnTicks,
+            Self::OutageValveClose => Message::OutageValveClose,
+        }
+    }
+
+    /// The value as shown to the user
+    pub fn format(&self, value: u32) -> heapless::String<16> {
+        let mut text = heapless::String::new();
+
+        match self {
+            Self::BatteryChemistry => {
+                let _ = text.push_str(BatteryChemistry::from_index(value).name());
+            }
+            _ => {
+                let _ = write!(&mut text, "{}", value);
+            }
+        }
+
+        text
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Settings {
+    pub leak_threshold: u32,
+    pub flash_write_cycle: u32,
+    pub keepalive_timeout: u32,
+    pub battery_chemistry: u32,
+    pub battery_divider_ratio: u32,
+    pub battery_low_voltage: u32,
+    pub battery_critical_voltage: u32,
+    pub battery_max_voltage: u32,
+    pub battery_hysteresis: u32,
+    pub valve_turn_ticks: u32,
+    pub outage_valve_close: u32,
+}
+
+impl Settings {
+    pub const fn new() -> Self {
+        let battery_profile = BatteryProfile::new();
+
+        Self {
+            leak_threshold: 1,
+            flash_write_cycle: 20,
+            keepalive_timeout: 20,
+            battery_chemistry: battery_profile.chemistry as _,
+            battery_divider_ratio: battery_profile.divider_ratio as _,
+            battery_low_voltage: battery_profile.low_voltage as _,
+            battery_critical_voltage: battery_profile.critical_voltage as _,
+            battery_max_voltage: battery_profile.max_voltage as _,
+            battery_hysteresis: battery_profile.hysteresis as _,
+            valve_turn_ticks: 20,
+            outage_valve_close: 0,
+        }
+    }
+
+    pub fn get(&self, setting: Setting) -> u32 {
+        match setting {
+            Setting::LeakThreshold => self.leak_threshold,
+            Setting::FlashWriteCycle => self.flash_write_cycle,
+            Setting::KeepaliveTimeout => self.keepalive_timeout,
+            Setting::BatteryChemistry => self.battery_chemistry,
+            Setting::BatteryDividerRatio => self.battery_divider_ratio,
+            Setting::BatteryLowVoltage => self.battery_low_voltage,
+            Setting::BatteryCriticalVoltage => self.battery_critical_voltage,
+            Setting::BatteryMaxVoltage => self.battery_max_voltage,
+            Setting::BatteryHysteresis => self.battery_hysteresis,
+            Setting::ValveTurnTicks => self.valve_turn_ticks,
+            Setting::OutageValveClose => self.outage_valve_close,
+        }
+    }
+
+    /// Sets the value, clamped to the range of the setting
+    pub fn set(&mut self, setting: Setting, value: u32) {
+        let (min, max) = setting.range();
+        let value = value.clamp(min, max);
+
+        let field = match setting {
+            Setting::LeakThreshold => &mut self.leak_threshold,
+            Setting::FlashWriteCycle => &mut self.flash_write_cycle,
+            Setting::KeepaliveTimeout => &mut self.keepalive_timeout,
+            Setting::BatteryChemistry => &mut self.battery_chemistry,
+            Setting::BatteryDividerRatio => &mut self.battery_divider_ratio,
+            Setting::BatteryLowVoltage => &mut self.battery_low_voltage,
+            Setting::BatteryCriticalVoltage => &mut self.battery_critical_voltage,
+            Setting::BatteryMaxVoltage => &mut self.battery_max_voltage,
+            Setting::BatteryHysteresis => &mut self.battery_hysteresis,
+            Setting::ValveTurnTicks => &mut self.valve_turn_ticks,
+            Setting::OutageValveClose => &mut self.outage_valve_close,
+        };
+
+        *field = value;
+    }
+
+    pub fn battery_profile(&self) -> BatteryProfile {
+        BatteryProfile {
+            chemistry: BatteryChemistry::from_index(self.battery_chemistry),
+            divider_ratio: self.battery_divider_ratio as _,
+            low_voltage: self.battery_low_voltage as _,
+            critical_voltage: self.battery_critical_voltage as _,
+            max_voltage: self.battery_max_voltage as _,
+            hysteresis: self.battery_hysteresis as _,
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,221 @@
+use core::fmt::Debug;
+
+use serde::{Deserialize, Serialize};
+
+pub const SECS_PER_MINUTE: u64 = 60;
+pub const SECS_PER_HOUR: u64 = 60 * SECS_PER_MINUTE;
+pub const SECS_PER_DAY: u64 = 24 * SECS_PER_HOUR;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    /// 0 = Monday, ..., 6 = Sunday
+    pub weekday: u8,
+}
+
+impl DateTime {
+    pub fn from_secs(secs: u64) -> Self {
+        let days = secs / SECS_PER_DAY;
+        let secs_of_day = secs % SECS_PER_DAY;
+
+        let (year, month, day) = civil_from_days(days);
+
+        Self {
+            year: year as _,
+            month: month as _,
+            day: day as _,
+            hour: (secs_of_day / SECS_PER_HOUR) as _,
+            minute: (secs_of_day % SECS_PER_HOUR / SECS_PER_MINUTE) as _,
+            second: (secs_of_day % SECS_PER_MINUTE) as _,
+            weekday: weekday(days),
+        }
+    }
+
+    pub fn to_secs(&self) -> u64 {
+        days_from_civil(self.year as _, self.month as _, self.day as _) * SECS_PER_DAY
+            + self.hour as u64 * SECS_PER_HOUR
+            + self.minute as u64 * SECS_PER_MINUTE
+            + self.second as u64
+    }
+
+    pub fn minute_of_day(&self) -> u16 {
+        self.hour as u16 * 60 + self.minute as u16
+    }
+
+    /// Months elapsed since 1970-01
+    pub fn month_index(&self) -> u64 {
+        (self.year as u64 - 1970) * 12 + self.month as u64 - 1
+    }
+}
+
+/// When a DST period starts or ends: the `week`-th `weekday` of `month`,
+/// at `utc_minutes` past midnight UTC. Week 5 means the last one in the month.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transition {
+    pub month: u8,
+    pub week: u8,
+    /// 0 = Monday, ..., 6 = Sunday
+    pub weekday: u8,
+    pub utc_minutes: u16,
+}
+
+impl Transition {
+    pub fn secs(&self, year: u16) -> u64 {
+        let first = days_from_civil(year as _, self.month as _, 1);
+
+        let mut day = first + (self.weekday as u64 + 7 - weekday(first) as u64) % 7;
+        day += (self.week.max(1) as u64 - 1) * 7;
+
+        let next_month = if self.month >= 12 {
+            days_from_civil(year as u64 + 1, 1, 1)
+        } else {
+            days_from_civil(year as _, self.month as u64 + 1, 1)
+        };
+
+        while day >= next_month {
+            day -= 7;
+        }
+
+        day * SECS_PER_DAY + self.utc_minutes as u64 * SECS_PER_MINUTE
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DstRule {
+    pub start: Transition,
+    pub end: Transition,
+    pub offset_mins: i16,
+}
+
+impl DstRule {
+    /// The rule used in the EU: last Sunday of March till last Sunday of October, at 01:00 UTC
+    pub const EU: Self = Self {
+        start: Transition {
+            month: 3,
+            week: 5,
+            weekday: 6,
+            utc_minutes: 60,
+        },
+        end: Transition {
+            month: 10,
+            week: 5,
+            weekday: 6,
+            utc_minutes: 60,
+        },
+        offset_mins: 60,
+    };
+
+    pub fn is_active(&self, utc_secs: u64) -> bool {
+        let year = DateTime::from_secs(utc_secs).year;
+
+        let start = self.start.secs(year);
+        let end = self.end.secs(year);
+
+        if start <= end {
+            utc_secs >= start && utc_secs < end
+        } else {
+            utc_secs >= start || utc_secs < end
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeZone {
+    pub offset_mins: i16,
+    pub dst: Option<DstRule>,
+}
+
+impl TimeZone {
+    pub const UTC: Self = Self {
+        offset_mins: 0,
+        dst: None,
+    };
+
+    pub const CENTRAL_EUROPE: Self = Self {
+        offset_mins: 60,
+        dst: Some(DstRule::EU),
+    };
+
+    pub const EASTERN_EUROPE: Self = Self {
+        offset_mins: 120,
+        dst: Some(DstRule::EU),
+    };
+
+    /// The zones which can be chosen by name, e.g. in MQTT commands
+    pub const NAMED: [(&'static str, Self); 3] = [
+        ("utc", Self::UTC),
+        ("central_europe", Self::CENTRAL_EUROPE),
+        ("eastern_europe", Self::EASTERN_EUROPE),
+    ];
+
+    pub fn named(name: &str) -> Option<Self> {
+        Self::NAMED
+            .iter()
+            .find(|(zone_name, _)| *zone_name == name)
+            .map(|(_, zone)| *zone)
+    }
+
+    pub fn offset_secs(&self, utc_secs: u64) -> i64 {
+        let dst_mins = self
+            .dst
+            .filter(|dst| dst.is_active(utc_secs))
+            .map(|dst| dst.offset_mins)
+            .unwrap_or(0);
+
+        (self.offset_mins as i64 + dst_mins as i64) * SECS_PER_MINUTE as i64
+    }
+
+    pub fn to_local(&self, utc_secs: u64) -> u64 {
+        (utc_secs as i64 + self.offset_secs(utc_secs)).max(0) as u64
+    }
+
+    pub fn local(&self, utc_secs: u64) -> DateTime {
+        DateTime::from_secs(self.to_local(utc_secs))
+    }
+}
+
+impl Default for TimeZone {
+    fn default() -> Self {
+        Self::UTC
+    }
+}
+
+/// 0 = Monday, ..., 6 = Sunday
+pub fn weekday(days: u64) -> u8 {
+    // 1970-01-01 was a Thursday
+    ((days + 3) % 7) as u8
+}
+
+// Algorithms from http://howardhinnant.github.io/date_algorithms.html
+
+pub fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146097 + doe - 719468
+}
+
+pub fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719468;
+
+    let era = days / 146097;
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+
+    let year = yoe + era * 400;
+
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
@@ -2,6 +2,41 @@ use core::fmt::Debug;
 
 use serde::{Deserialize, Serialize};
 
+/// How many valves a device can control, e.g. the main line, the garden and the boiler
+pub const MAX_VALVES: usize = 3;
+
+/// The valve of the main line, which all meters are behind
+pub const MAIN_VALVE: ValveId = 0;
+
+/// The index of a valve, below `MAX_VALVES`
+pub type ValveId = u8;
+
+/// A set of valves, as a bit mask of their ids
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct ValveSet(u8);
+
+impl ValveSet {
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn all() -> Self {
+        Self((1 << MAX_VALVES) - 1)
+    }
+
+    pub const fn with(self, valve: ValveId) -> Self {
+        Self(self.0 | (1 << valve))
+    }
+
+    pub const fn without(self, valve: ValveId) -> Self {
+        Self(self.0 & !(1 << valve))
+    }
+
+    pub const fn contains(&self, valve: ValveId) -> bool {
+        self.0 & (1 << valve) != 0
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ValveState {
     Open,
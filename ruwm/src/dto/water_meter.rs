@@ -2,19 +2,32 @@ use core::fmt::Debug;
 
 use serde::{Deserialize, Serialize};
 
+/// How many meters a device can count, e.g. one for cold and one for hot water
+pub const MAX_METERS: usize = 2;
+
+/// The index of a meter, below `MAX_METERS`
+pub type MeterId = u8;
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct WaterMeterState {
     pub edges_count: u64,
+    /// The edges counted while the meter turned backwards; these are not part of `edges_count`
+    pub reverse_edges_count: u64,
     pub armed: bool,
     pub leaking: bool,
+    /// Whether the meter turned backwards, i.e. water flowing back into the supply or tampering;
+    /// kept until acknowledged or until the meter is disarmed
+    pub backflow: bool,
 }
 
 impl WaterMeterState {
     pub const fn new() -> Self {
         Self {
             edges_count: 0,
+            reverse_edges_count: 0,
             armed: false,
             leaking: false,
+            backflow: false,
         }
     }
 }
@@ -24,3 +37,39 @@ pub enum WaterMeterCommand {
     Arm,
     Disarm,
 }
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SensorFault {
+    /// More pulses than the meter can physically produce, e.g. a loose wire
+    Runaway,
+    /// No pulses for long with the sensor active, e.g. a magnet stuck in front of it
+    Stuck,
+    /// No pulses for long with the sensor inactive, e.g. a disconnected sensor
+    Silent,
+}
+
+impl SensorFault {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Runaway => "runaway",
+            Self::Stuck => "stuck",
+            Self::Silent => "silent",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct SensorHealthState {
+    pub fault: Option<SensorFault>,
+    /// UTC seconds since no pulses were counted while the valve was open
+    pub quiet_since: Option<u64>,
+}
+
+impl SensorHealthState {
+    pub const fn new() -> Self {
+        Self {
+            fault: None,
+            quiet_since: None,
+        }
+    }
+}
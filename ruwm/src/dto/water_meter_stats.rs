@@ -2,18 +2,23 @@ use core::fmt::Debug;
 
 use serde::{Deserialize, Serialize};
 
-const FLOW_STATS_INSTANCES: usize = 8;
-
-const DURATIONS: [u64; FLOW_STATS_INSTANCES] = [
-    60 * 5,
-    60 * 30,
-    60 * 60,
-    60 * 60 * 6,
-    60 * 60 * 12,
-    60 * 60 * 24,
-    60 * 60 * 24 * 7,
-    60 * 60 * 24 * 30,
-];
+use super::time::{TimeZone, SECS_PER_DAY};
+
+pub const FLOW_STATS_INSTANCES: usize = 8;
+
+/// Number of hourly consumption values kept in the history
+pub const HISTORY_LEN: usize = 24;
+
+const HOUR: u64 = 60 * 60;
+
+pub const DAY: u64 = HOUR * 24;
+pub const WEEK: u64 = DAY * 7;
+pub const MONTH: u64 = DAY * 30;
+
+/// Measurement windows, aligned to the local time of day.
+/// The week window starts on Monday and the "30 days" one follows the calendar months.
+pub const DURATIONS: [u64; FLOW_STATS_INSTANCES] =
+    [60 * 5, 60 * 30, HOUR, HOUR * 6, HOUR * 12, DAY, WEEK, MONTH];
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct FlowSnapshot {
@@ -47,12 +52,10 @@ impl FlowSnapshot {
         &self,
         measurement_duration_secs: u64,
         current_time_secs: u64,
+        time_zone: &TimeZone,
     ) -> bool {
-        Self::is_aligned_measurement_due(
-            self.time_secs,
-            current_time_secs,
-            measurement_duration_secs,
-        )
+        Self::period(measurement_duration_secs, self.time_secs, time_zone)
+            != Self::period(measurement_duration_secs, current_time_secs, time_zone)
     }
 
     pub fn flow_detected(&self, current_edges_count: u64) -> bool {
@@ -63,27 +66,15 @@ impl FlowSnapshot {
         current_edges_count - self.edges_count
     }
 
-    fn is_nonaligned_measurement_due(
-        start_time_secs: u64,
-        current_time_secs: u64,
-        measurement_duration_secs: u64,
-    ) -> bool {
-        current_time_secs - start_time_secs >= measurement_duration_secs
-    }
+    fn period(measurement_duration_secs: u64, time_secs: u64, time_zone: &TimeZone) -> u64 {
+        let local_secs = time_zone.to_local(time_secs);
 
-    fn is_aligned_measurement_due(
-        start_time_secs: u64,
-        current_time_secs: u64,
-        measurement_duration_secs: u64,
-    ) -> bool {
-        let start_time_secs =
-            start_time_secs / measurement_duration_secs * measurement_duration_secs;
-
-        Self::is_nonaligned_measurement_due(
-            start_time_secs,
-            current_time_secs,
-            measurement_duration_secs,
-        )
+        match measurement_duration_secs {
+            // Weeks since Monday, 1969-12-29
+            WEEK => (local_secs / SECS_PER_DAY + 3) / 7,
+            MONTH => time_zone.local(time_secs).month_index(),
+            _ => local_secs / measurement_duration_secs,
+        }
     }
 }
 
@@ -115,6 +106,9 @@ pub struct WaterMeterStatsState {
 
     pub snapshots: [FlowSnapshot; FLOW_STATS_INSTANCES],
     pub measurements: [Option<FlowMeasurement>; FLOW_STATS_INSTANCES],
+
+    /// The consumption of the most recent full hours, the newest one being the last
+    pub history: [u64; HISTORY_LEN],
 }
 
 impl WaterMeterStatsState {
@@ -126,10 +120,12 @@ impl WaterMeterStatsState {
             most_recent: FlowSnapshot::new_default(),
             snapshots: [DEFAULT_SNAPSHOT; FLOW_STATS_INSTANCES],
             measurements: [None; FLOW_STATS_INSTANCES],
+            history: [0; HISTORY_LEN],
         }
     }
 
-    pub fn update(&mut self, edges_count: u64, now_secs: u64) -> bool {
+    /// Updates the statistics. `now_secs` is the wall clock time in UTC seconds
+    pub fn update(&mut self, edges_count: u64, now_secs: u64, time_zone: &TimeZone) -> bool {
         let most_recent = FlowSnapshot::new(now_secs, edges_count);
 
         let mut updated = self.most_recent != most_recent;
@@ -137,15 +133,56 @@ impl WaterMeterStatsState {
             self.most_recent = most_recent;
         }
 
+        if self.installation.time_secs == 0 {
+            self.installation.time_secs = self.most_recent.time_secs;
+
+            // Keep the edges count at installation carried over by a migration
+            if self.installation.edges_count == 0 {
+                self.installation.edges_count = self.most_recent.edges_count;
+            }
+        }
+
         for (index, snapshot) in self.snapshots.iter_mut().enumerate() {
-            if snapshot.is_measurement_due(DURATIONS[index], now_secs) {
+            if snapshot.time_secs == 0 {
+                *snapshot = self.most_recent;
+
+                updated = true;
+            } else if snapshot.is_measurement_due(DURATIONS[index], now_secs, time_zone) {
                 let prev = core::mem::replace(snapshot, self.most_recent);
                 self.measurements[index] = Some(FlowMeasurement::new(prev, self.most_recent));
 
+                if DURATIONS[index] == HOUR {
+                    // Hours without an update, e.g. while sleeping, count nothing, and
+                    // the consumption goes to the hour of the previous snapshot
+                    let elapsed = FlowSnapshot::period(HOUR, now_secs, time_zone)
+                        .saturating_sub(FlowSnapshot::period(HOUR, prev.time_secs, time_zone))
+                        .max(1) as usize;
+
+                    let rotated = elapsed.min(HISTORY_LEN);
+
+                    self.history.rotate_left(rotated);
+                    self.history[HISTORY_LEN - rotated..].fill(0);
+
+                    if elapsed <= HISTORY_LEN {
+                        self.history[HISTORY_LEN - elapsed] =
+                            prev.statistics(self.most_recent.edges_count);
+                    }
+                }
+
                 updated = true;
             }
         }
 
         updated
     }
+
+    /// The edges counted since the start of the current `duration_secs` window
+    pub fn consumption(&self, duration_secs: u64) -> Option<u64> {
+        DURATIONS
+            .iter()
+            .position(|duration| *duration == duration_secs)
+            .map(|index| &self.snapshots[index])
+            .filter(|snapshot| snapshot.time_secs != 0)
+            .map(|snapshot| snapshot.statistics(self.most_recent.edges_count))
+    }
 }
@@ -6,9 +6,16 @@ use heapless::String;
 
 use edge_frame::dto::Role;
 
-use super::battery::BatteryState;
-use super::valve::{ValveCommand, ValveState};
-use super::water_meter::{WaterMeterCommand, WaterMeterState};
+use super::alert::{AlertCommand, AlertsState};
+use super::battery::{BatteryState, VoltageHistory};
+use super::budget::{BudgetConfig, BudgetState};
+use super::i18n::Language;
+use super::leak_sensor::{LeakSensorId, LeakSensorState};
+use super::power::PowerState;
+use super::schedule::{ScheduleCommand, ScheduleState};
+use super::time::TimeZone;
+use super::valve::{ValveCommand, ValveId, ValveState};
+use super::water_meter::{MeterId, SensorHealthState, WaterMeterCommand, WaterMeterState};
 
 pub const USERNAME_MAX_LEN: usize = 32;
 pub const PASSWORD_MAX_LEN: usize = 32;
@@ -17,9 +24,16 @@ pub const PASSWORD_MAX_LEN: usize = 32;
 pub enum WebRequest {
     Authenticate(String<USERNAME_MAX_LEN>, String<PASSWORD_MAX_LEN>),
     Logout,
+    /// Authenticates with the pairing code shown on the device screen
+    Pair(u32),
 
-    ValveCommand(ValveCommand),
-    WaterMeterCommand(WaterMeterCommand),
+    ValveCommand(ValveId, ValveCommand),
+    WaterMeterCommand(MeterId, WaterMeterCommand),
+    ScheduleCommand(ScheduleCommand),
+    BudgetConfig(BudgetConfig),
+    AlertCommand(AlertCommand),
+    Language(Language),
+    TimeZone(TimeZone),
     // TODO
     //WifiSettingsUpdate(...),
 }
@@ -29,22 +43,36 @@ impl WebRequest {
         match self {
             Self::Authenticate(_, _) => Role::None,
             Self::Logout => Role::None,
-            Self::ValveCommand(_) => Role::User,
-            Self::WaterMeterCommand(_) => Role::User,
+            Self::Pair(_) => Role::None,
+            Self::ValveCommand(..) => Role::User,
+            Self::WaterMeterCommand(..) => Role::User,
+            Self::ScheduleCommand(_) => Role::User,
+            Self::BudgetConfig(_) => Role::User,
+            Self::AlertCommand(_) => Role::User,
+            Self::Language(_) => Role::User,
+            Self::TimeZone(_) => Role::User,
         }
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum WebEvent {
     NoPermissions,
 
     AuthenticationFailed,
 
     RoleState(Role),
-    ValveState(Option<ValveState>),
-    WaterMeterState(WaterMeterState),
+    ValveState(ValveId, Option<ValveState>),
+    WaterMeterState(MeterId, WaterMeterState),
+    SensorHealth(MeterId, SensorHealthState),
+    LeakSensorState(LeakSensorId, LeakSensorState),
     BatteryState(BatteryState),
+    BatteryHistory(VoltageHistory),
+    PowerState(PowerState),
+    ScheduleState(ScheduleState),
+    BudgetState(BudgetState),
+    AlertsState(AlertsState),
+    LanguageState(Language),
     //WifiState(Status),
 
     // MqttPublishNotification(MessageId),
@@ -57,9 +85,17 @@ impl WebEvent {
             Self::NoPermissions => Role::None,
             Self::AuthenticationFailed => Role::None,
             Self::RoleState(_) => Role::None,
-            Self::ValveState(_) => Role::User,
-            Self::WaterMeterState(_) => Role::User,
+            Self::ValveState(..) => Role::User,
+            Self::WaterMeterState(..) => Role::User,
+            Self::SensorHealth(..) => Role::User,
+            Self::LeakSensorState(..) => Role::User,
             Self::BatteryState(_) => Role::User,
+            Self::BatteryHistory(_) => Role::User,
+            Self::PowerState(_) => Role::User,
+            Self::ScheduleState(_) => Role::User,
+            Self::BudgetState(_) => Role::User,
+            Self::AlertsState(_) => Role::User,
+            Self::LanguageState(_) => Role::None,
             //Self::WifiState(_) => Role::User,
         }
     }
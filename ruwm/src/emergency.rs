@@ -1,53 +1,190 @@
-use embassy_futures::select::{select3, Either3};
+use core::cell::Cell;
+use core::future::pending;
+
+use embassy_futures::select::{select, select3, select4, Either, Either3, Either4};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::{Duration, Instant, Timer};
 
 use channel_bridge::notification::Notification;
 
-use crate::battery::{self, BatteryState};
-use crate::valve::{self, ValveCommand, ValveState};
-use crate::wm;
+use crate::battery::{self, BatteryLevel};
+use crate::budget::{self, BudgetLevel};
+use crate::valve::{self, ValveCommand, ValveId, ValveSet, ValveState, MAX_VALVES};
+use crate::{clock, leak_sensor, power, settings, wm};
 
 pub(crate) static VALVE_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static WM_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static BATTERY_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static BUDGET_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static POWER_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static LEAK_SENSOR_STATE_NOTIF: Notification = Notification::new();
+
+/// The valves closed by an emergency, latched until they are opened again by hand
+static CLOSED: Mutex<CriticalSectionRawMutex, Cell<ValveSet>> =
+    Mutex::new(Cell::new(ValveSet::empty()));
+
+/// The valves an emergency closed, which nothing but the user is to reopen
+pub fn closed_valves() -> ValveSet {
+    CLOSED.lock(Cell::get)
+}
+
+/// Which valves each of the emergencies closes
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct EmergencyPolicy {
+    pub leak: ValveSet,
+    pub battery_critical: ValveSet,
+    pub budget_exceeded: ValveSet,
+    pub outage: ValveSet,
+    /// Water on the floor, as reported by the leak sensors
+    pub flood: ValveSet,
+}
+
+impl EmergencyPolicy {
+    pub const fn new() -> Self {
+        Self {
+            leak: ValveSet::all(),
+            battery_critical: ValveSet::all(),
+            budget_exceeded: ValveSet::all(),
+            outage: ValveSet::all(),
+            flood: ValveSet::all(),
+        }
+    }
+}
 
-pub async fn process() {
-    let mut valve_state = None;
+impl Default for EmergencyPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub async fn process(policy: EmergencyPolicy) {
+    let mut valve_states: [Option<ValveState>; MAX_VALVES] = [None; MAX_VALVES];
+    let mut outage_deadline = outage_close_time();
 
     loop {
-        let emergency_close = match select3(
-            VALVE_STATE_NOTIF.wait(),
-            WM_STATE_NOTIF.wait(),
-            BATTERY_STATE_NOTIF.wait(),
+        let outage_timer = if let Some(outage_deadline) = outage_deadline {
+            futures::future::Either::Left(Timer::at(outage_deadline))
+        } else {
+            futures::future::Either::Right(pending())
+        };
+
+        let close_valves = match select(
+            select4(
+                VALVE_STATE_NOTIF.wait(),
+                WM_STATE_NOTIF.wait(),
+                BATTERY_STATE_NOTIF.wait(),
+                BUDGET_STATE_NOTIF.wait(),
+            ),
+            select3(
+                POWER_STATE_NOTIF.wait(),
+                LEAK_SENSOR_STATE_NOTIF.wait(),
+                outage_timer,
+            ),
         )
         .await
         {
-            Either3::First(_) => {
-                valve_state = valve::STATE.get();
+            Either::First(Either4::First(_)) => {
+                for (valve, valve_state) in valve_states.iter_mut().enumerate() {
+                    let state = valve::STATES[valve].get();
+
+                    if opened(*valve_state, state) {
+                        CLOSED.lock(|closed| closed.set(closed.get().without(valve as ValveId)));
+                    }
 
-                false
+                    *valve_state = state;
+                }
+
+                ValveSet::empty()
+            }
+            Either::First(Either4::Second(_)) => {
+                if wm::STATES.iter().any(|state| state.get().leaking) {
+                    policy.leak
+                } else {
+                    ValveSet::empty()
+                }
             }
-            Either3::Second(_) => wm::STATE.get().leaking,
-            Either3::Third(_) => {
+            Either::First(Either4::Third(_)) => {
                 let battery = battery::STATE.get();
-
-                let battery_low = battery
-                    .voltage
-                    .map(|voltage| voltage <= BatteryState::LOW_VOLTAGE)
-                    .unwrap_or(false);
+                // Low only raises an alert, the valve is closed while it can still be closed
+                let battery_critical = battery.level == Some(BatteryLevel::Critical);
 
                 let powered = battery.powered.unwrap_or(false);
 
-                battery_low && !powered
+                if battery_critical && !powered {
+                    policy.battery_critical
+                } else {
+                    ValveSet::empty()
+                }
+            }
+            Either::First(Either4::Fourth(_)) => {
+                let budget = budget::STATE.get();
+
+                if budget.config.close_valve && budget.level == BudgetLevel::Exceeded {
+                    policy.budget_exceeded
+                } else {
+                    ValveSet::empty()
+                }
+            }
+            Either::Second(Either3::First(_)) => {
+                outage_deadline = outage_close_time();
+
+                ValveSet::empty()
+            }
+            Either::Second(Either3::Second(_)) => {
+                if leak_sensor::wet_sensor().is_some() {
+                    policy.flood
+                } else {
+                    ValveSet::empty()
+                }
+            }
+            Either::Second(Either3::Third(_)) => {
+                outage_deadline = None;
+
+                if power::STATE.get().outage().is_some() {
+                    policy.outage
+                } else {
+                    ValveSet::empty()
+                }
             }
         };
 
-        if emergency_close
-            && !matches!(
-                valve_state,
-                Some(ValveState::Closing(_)) | Some(ValveState::Closed)
-            )
-        {
-            valve::COMMAND.signal(ValveCommand::Close);
+        for valve in valve::configured() {
+            if close_valves.contains(valve) {
+                CLOSED.lock(|closed| closed.set(closed.get().with(valve)));
+
+                if !matches!(
+                    valve_states[valve as usize],
+                    Some(ValveState::Closing(_)) | Some(ValveState::Closed)
+                ) {
+                    valve::command(Some(valve), ValveCommand::Close);
+                }
+            }
         }
     }
 }
+
+/// Whether the valve started opening, which only the user does to a valve closed by an emergency
+fn opened(previous: Option<ValveState>, current: Option<ValveState>) -> bool {
+    let open = |state: Option<ValveState>| {
+        matches!(state, Some(ValveState::Opening(_) | ValveState::Open))
+    };
+
+    open(current) && !open(previous)
+}
+
+/// When the valves are to be closed because of the ongoing outage, if the policy is enabled
+fn outage_close_time() -> Option<Instant> {
+    let minutes = settings::STATE.get().outage_valve_close;
+
+    power::STATE
+        .get()
+        .outage()
+        .filter(|_| minutes > 0)
+        .map(|outage| {
+            // Without a synchronized clock, the outage is timed from now on
+            let elapsed = outage.elapsed(clock::now()).unwrap_or(0);
+
+            Instant::now() + Duration::from_secs((minutes as u64 * 60).saturating_sub(elapsed))
+        })
+}
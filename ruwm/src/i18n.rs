@@ -0,0 +1,48 @@
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+
+use channel_bridge::notification::Notification;
+
+use crate::state::State;
+use crate::storage::{self, Storage, Versioned};
+
+pub use crate::dto::i18n::*;
+
+const STORAGE_KEY: &str = "lang";
+
+pub static LANGUAGE: State<Language> = State::new(
+    "LANGUAGE",
+    Language::new(),
+    &[
+        &crate::screen::LANGUAGE_STATE_NOTIF,
+        &crate::web::LANGUAGE_STATE_NOTIF,
+        &LANGUAGE_PERSIST_NOTIFY,
+    ],
+);
+
+static LANGUAGE_PERSIST_NOTIFY: Notification = Notification::new();
+
+/// The message in the currently selected language
+pub fn text(message: Message) -> &'static str {
+    message.text(LANGUAGE.get())
+}
+
+impl Versioned for Language {
+    const VERSION: u8 = 1;
+}
+
+pub fn restore(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>) {
+    if let Some(language) = storage::restore(storage, STORAGE_KEY) {
+        LANGUAGE.set(language);
+    }
+}
+
+pub async fn persist(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>) {
+    loop {
+        LANGUAGE_PERSIST_NOTIFY.wait().await;
+
+        storage::persist(storage, STORAGE_KEY, &LANGUAGE.get());
+    }
+}
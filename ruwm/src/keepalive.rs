@@ -1,15 +1,19 @@
+use core::cell::Cell;
 use core::cmp::max;
 use core::fmt::Debug;
 
 use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
 use embassy_time::{Duration, Instant, Timer};
 
+use enumset::{EnumSet, EnumSetType};
+
 use channel_bridge::notification::Notification;
 
+use crate::sleep::{self, SleepPolicy};
 use crate::state::State;
-use crate::{battery, quit};
-
-const TIMEOUT: Duration = Duration::from_secs(20);
+use crate::{quit, settings};
 
 pub static STATE: State<RemainingTime> = State::new(
     "REMAINING TIME",
@@ -25,10 +29,119 @@ pub(crate) static NOTIF: Notification = Notification::new();
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum RemainingTime {
     Indefinite,
+    /// Kept awake by these leases
+    Leased(EnumSet<Lease>),
     Duration(Duration),
 }
 
-pub async fn process() {
+/// The subsystems which can hold the device awake
+#[derive(Debug, EnumSetType)]
+pub enum Lease {
+    WebSession,
+    ValveTurn,
+    Update,
+    Provisioning,
+    Pairing,
+}
+
+impl Lease {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::WebSession => "web",
+            Self::ValveTurn => "valve",
+            Self::Update => "update",
+            Self::Provisioning => "wifi",
+            Self::Pairing => "pairing",
+        }
+    }
+}
+
+const LEASES: usize = 5;
+
+#[derive(Copy, Clone, Debug)]
+struct LeaseState {
+    /// Acquisitions without an expiry which are not released yet
+    holders: u8,
+    expires: Option<Instant>,
+}
+
+static LEASE_STATES: Mutex<CriticalSectionRawMutex, Cell<[LeaseState; LEASES]>> =
+    Mutex::new(Cell::new(
+        [LeaseState {
+            holders: 0,
+            expires: None,
+        }; LEASES],
+    ));
+
+/// Holds the device awake until the lease is released or, with an expiry, until it expires.
+///
+/// A lease can be acquired more than once, e.g. by each web session; without an expiry
+/// each of the acquisitions has to be released.
+pub fn acquire(lease: Lease, expiry: Option<Duration>) {
+    update_lease(lease, |state| match expiry {
+        Some(expiry) => {
+            let expires = Instant::now() + expiry;
+
+            state.expires = Some(state.expires.map_or(expires, |prev| max(prev, expires)));
+        }
+        None => state.holders = state.holders.saturating_add(1),
+    });
+}
+
+/// Releases one acquisition of the lease without an expiry
+pub fn release(lease: Lease) {
+    update_lease(lease, |state| {
+        state.holders = state.holders.saturating_sub(1);
+    });
+}
+
+/// Ends the acquisitions of the lease with an expiry before they expire
+pub fn end(lease: Lease) {
+    update_lease(lease, |state| state.expires = None);
+}
+
+/// Acquires the lease without an expiry, and releases it when the returned guard is dropped,
+/// also when the future holding the guard is cancelled
+pub fn hold(lease: Lease) -> LeaseGuard {
+    acquire(lease, None);
+
+    LeaseGuard(lease)
+}
+
+pub struct LeaseGuard(Lease);
+
+impl Drop for LeaseGuard {
+    fn drop(&mut self) {
+        release(self.0);
+    }
+}
+
+/// The leases currently holding the device awake
+pub fn leases(now: Instant) -> EnumSet<Lease> {
+    let states = LEASE_STATES.lock(Cell::get);
+
+    EnumSet::all()
+        .iter()
+        .filter(|lease| {
+            let state = states[*lease as usize];
+
+            state.holders > 0 || state.expires.map(|expires| now < expires).unwrap_or(false)
+        })
+        .collect()
+}
+
+fn update_lease(lease: Lease, f: impl FnOnce(&mut LeaseState)) {
+    LEASE_STATES.lock(|states| {
+        let mut lease_states = states.get();
+        f(&mut lease_states[lease as usize]);
+
+        states.set(lease_states);
+    });
+
+    NOTIF.notify();
+}
+
+pub async fn process(policy: SleepPolicy) {
     let mut quit_time = None;
     let mut remaining_time_sent = None;
 
@@ -41,14 +154,24 @@ pub async fn process() {
 
         let now = Instant::now();
 
-        if battery::STATE.get().powered.unwrap_or(false) {
+        let policy = SleepPolicy {
+            awake: Duration::from_secs(settings::STATE.get().keepalive_timeout as _),
+            ..policy
+        };
+
+        let awake = sleep::plan(&policy).awake;
+        let leases = leases(now);
+
+        if awake.is_none() || !leases.is_empty() {
             quit_time = None;
-        } else if matches!(result, Either::First(_)) {
-            quit_time = Some(now + TIMEOUT);
+        } else if matches!(result, Either::First(_)) || quit_time.is_none() {
+            quit_time = awake.map(|awake| now + awake);
         }
 
         let remaining_time = if let Some(quit_time) = quit_time {
             RemainingTime::Duration(max(quit_time - now, Duration::from_secs(0)))
+        } else if awake.is_some() {
+            RemainingTime::Leased(leases)
         } else {
             RemainingTime::Indefinite
         };
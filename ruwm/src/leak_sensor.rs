@@ -0,0 +1,141 @@
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::Duration;
+
+use embedded_hal::digital::v2::InputPin;
+
+use channel_bridge::notification::Notification;
+
+use crate::button::{self, PressedLevel};
+use crate::clock;
+use crate::state::State;
+
+pub use crate::dto::leak_sensor::*;
+
+/// Water splashing over a probe should not make it flicker
+const PROBE_DEBOUNCE: Duration = Duration::from_secs(1);
+
+const SENSOR_STATE: State<LeakSensorState> = State::new(
+    "LEAK SENSOR",
+    LeakSensorState::new(),
+    &[
+        &crate::keepalive::NOTIF,
+        &crate::emergency::LEAK_SENSOR_STATE_NOTIF,
+        &crate::alert::LEAK_SENSOR_STATE_NOTIF,
+        &crate::web::LEAK_SENSOR_STATE_NOTIF,
+    ],
+);
+
+const PIN_EDGE: Notification = Notification::new();
+
+/// Indexed by `LeakSensorId`
+pub static STATES: [State<LeakSensorState>; MAX_LEAK_SENSORS] = [SENSOR_STATE; MAX_LEAK_SENSORS];
+
+/// Notified on the edges of the probe inputs, indexed by `LeakSensorId`
+pub static PIN_EDGES: [Notification; MAX_LEAK_SENSORS] = [PIN_EDGE; MAX_LEAK_SENSORS];
+
+static CONFIGS: Mutex<CriticalSectionRawMutex, Cell<[Option<LeakSensorConfig>; MAX_LEAK_SENSORS]>> =
+    Mutex::new(Cell::new([None; MAX_LEAK_SENSORS]));
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LeakSensorInput {
+    /// A probe on an input pin, which reads the given level when wet
+    Probe(PressedLevel),
+    /// A sensor reporting `{"water_leak": true}` to the topic, as the ones of zigbee2mqtt do
+    Mqtt(&'static str),
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct LeakSensorConfig {
+    /// Shown with the alerts of the sensor, e.g. "Kitchen"
+    pub name: &'static str,
+    pub input: LeakSensorInput,
+}
+
+/// The sensors are numbered in the order they are given
+pub fn configure(configs: &[LeakSensorConfig]) {
+    CONFIGS.lock(|cell| {
+        let mut all = [None; MAX_LEAK_SENSORS];
+
+        for (slot, config) in all.iter_mut().zip(configs) {
+            *slot = Some(*config);
+        }
+
+        cell.set(all);
+    });
+}
+
+/// The number of sensors being watched
+pub fn sensors() -> usize {
+    CONFIGS.lock(|configs| configs.get().iter().flatten().count())
+}
+
+pub fn config(sensor: LeakSensorId) -> Option<LeakSensorConfig> {
+    CONFIGS.lock(|configs| configs.get()[sensor as usize])
+}
+
+/// The topics of the MQTT sensors
+pub fn mqtt_topics() -> impl Iterator<Item = &'static str> {
+    IntoIterator::into_iter(CONFIGS.lock(Cell::get))
+        .flatten()
+        .filter_map(|config| match config.input {
+            LeakSensorInput::Mqtt(topic) => Some(topic),
+            LeakSensorInput::Probe(_) => None,
+        })
+}
+
+/// The MQTT sensor reporting to the topic
+pub fn mqtt_sensor(topic: &str) -> Option<LeakSensorId> {
+    CONFIGS
+        .lock(Cell::get)
+        .iter()
+        .position(|config| config.map(|config| config.input) == Some(LeakSensorInput::Mqtt(topic)))
+        .map(|sensor| sensor as LeakSensorId)
+}
+
+/// The first of the sensors which is wet
+pub fn wet_sensor() -> Option<LeakSensorId> {
+    STATES
+        .iter()
+        .position(|state| state.get().is_wet())
+        .map(|sensor| sensor as LeakSensorId)
+}
+
+pub fn report(sensor: LeakSensorId, wet: bool) {
+    STATES[sensor as usize].update_with(|state| LeakSensorState {
+        wet: Some(wet),
+        wet_since_secs: if !wet {
+            None
+        } else if state.is_wet() {
+            state.wet_since_secs
+        } else {
+            clock::now()
+        },
+    });
+}
+
+pub async fn process_probe(sensor: LeakSensorId, mut pin: impl InputPin) {
+    let wet_level = match config(sensor).map(|config| config.input) {
+        Some(LeakSensorInput::Probe(wet_level)) => wet_level,
+        _ => {
+            log::error!("Leak sensor {} is not configured as a probe", sensor);
+            return;
+        }
+    };
+
+    let pin_edge = &PIN_EDGES[sensor as usize];
+
+    loop {
+        let wet = button::is_pressed(&pin, wet_level);
+
+        report(sensor, wet);
+
+        if wet {
+            button::wait_release(&mut pin, wet_level, pin_edge, Some(PROBE_DEBOUNCE)).await;
+        } else {
+            button::wait_press(&mut pin, wet_level, pin_edge, Some(PROBE_DEBOUNCE)).await;
+        }
+    }
+}
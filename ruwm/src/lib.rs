@@ -4,30 +4,59 @@
 #![cfg_attr(not(version("1.64")), feature(future_poll_fn))]
 #![feature(type_alias_impl_trait)]
 
+#[cfg(test)]
+extern crate std;
+
+#[cfg(feature = "system")]
+pub mod alert;
 #[cfg(feature = "system")]
 pub mod battery;
 #[cfg(feature = "system")]
+pub mod budget;
+#[cfg(feature = "system")]
 pub mod button;
+#[cfg(feature = "system")]
+pub mod clock;
 pub mod dto;
 #[cfg(feature = "system")]
 pub mod emergency;
 #[cfg(feature = "system")]
 pub mod error;
 #[cfg(feature = "system")]
+pub mod i18n;
+#[cfg(feature = "system")]
 pub mod keepalive;
 #[cfg(feature = "system")]
+pub mod leak_sensor;
+#[cfg(feature = "system")]
 pub mod mqtt;
 #[cfg(feature = "system")]
+pub mod pairing;
+#[cfg(feature = "system")]
+pub mod power;
+#[cfg(feature = "system")]
 pub mod pulse_counter;
 #[cfg(feature = "system")]
 pub mod quit;
 #[cfg(feature = "system")]
+pub mod reset;
+#[cfg(feature = "system")]
+pub mod schedule;
+#[cfg(feature = "system")]
 pub mod screen;
+#[cfg(feature = "system")]
+pub mod settings;
+#[cfg(feature = "system")]
+pub mod sleep;
 #[cfg(all(feature = "system", feature = "edge-executor"))]
 pub mod spawn;
 #[cfg(feature = "system")]
 pub mod state;
 #[cfg(feature = "system")]
+pub mod storage;
+#[cfg(feature = "system")]
+pub mod update;
+#[cfg(feature = "system")]
 pub mod valve;
 #[cfg(feature = "system")]
 pub mod web;
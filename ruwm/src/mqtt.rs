@@ -1,3 +1,4 @@
+use core::fmt::Write;
 use core::str::{self, FromStr};
 use core::time::Duration;
 
@@ -15,12 +16,20 @@ use embedded_svc::mqtt::client::asynch::{Client, Connection, Event, Message, Pub
 use embedded_svc::mqtt::client::Details;
 
 use channel_bridge::notification::Notification;
-use wm::WaterMeterState;
 
-use crate::battery::{self, BatteryState};
-use crate::valve::{ValveCommand, ValveState};
-use crate::wm::WaterMeterCommand;
-use crate::{error, valve, wm};
+use crate::alert::{self, AlertCommand};
+use crate::battery::{self, BatteryLevel, BatteryState};
+use crate::budget::{self, BudgetLevel, BudgetState};
+use crate::clock::TimeZone;
+use crate::leak_sensor::{self, LeakSensorId};
+use crate::power::{self, Outage};
+use crate::schedule::{ScheduleCommand, ScheduleRule, MAX_RULES};
+use crate::sleep::{self, Activity};
+use crate::state::State;
+use crate::update::UpdateCommand;
+use crate::valve::{ValveCommand, ValveId, ValveState, MAX_VALVES};
+use crate::wm::{MeterId, SensorFault, WaterMeterCommand, WaterMeterState, MAX_METERS};
+use crate::{clock, error, schedule, update, valve, wm};
 
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub struct MqttConfiguration {
@@ -34,29 +43,76 @@ pub struct MqttConfiguration {
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum MqttCommand {
     KeepAlive(Duration),
-    Valve(bool),
-    FlowWatch(bool),
+    /// Opens or closes the given valve, or all valves if `None`
+    Valve(Option<ValveId>, bool),
+    /// Arms or disarms the given meter, or all meters if `None`
+    FlowWatch(Option<MeterId>, bool),
+    Schedule(bool),
+    /// Adds a rule if the index is `None`, otherwise replaces the rule at the index,
+    /// or removes it if there is no rule
+    ScheduleRule(Option<u8>, Option<ScheduleRule>),
+    Vacation(u16),
     SystemUpdate,
+    AcknowledgeAlerts,
+    /// A report of an external leak sensor, wet or dry
+    LeakSensor(Option<LeakSensorId>, bool),
+    TimeZone(TimeZone),
 }
 
 pub type MqttClientNotification = Result<Event<Option<MqttCommand>>, ()>;
 
-// TODO: Web: connected info at least
-static PUBLISH_NOTIFY: &[&Notification] =
-    &[&crate::keepalive::NOTIF, &crate::screen::MQTT_STATE_NOTIF];
-static RECEIVE_NOTIFY: &[&Notification] =
-    &[&crate::keepalive::NOTIF, &crate::screen::MQTT_STATE_NOTIF];
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct MqttState {
+    pub connected: Option<bool>,
+    pub broker: heapless::String<64>,
+    /// Wall clock time of the last acknowledged publish, in UTC seconds
+    pub last_publish_secs: Option<u64>,
+}
+
+impl MqttState {
+    pub const fn new() -> Self {
+        Self {
+            connected: None,
+            broker: heapless::String::new(),
+            last_publish_secs: None,
+        }
+    }
+}
+
+pub static STATE: State<MqttState> = State::new(
+    "MQTT",
+    MqttState::new(),
+    &[
+        &crate::screen::MQTT_STATE_NOTIF,
+        &crate::web::MQTT_STATE_NOTIF,
+    ],
+);
+
+static PUBLISH_NOTIFY: &[&Notification] = &[&crate::keepalive::NOTIF];
+static RECEIVE_NOTIFY: &[&Notification] = &[&crate::keepalive::NOTIF];
 
 pub(crate) static VALVE_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static WM_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static BATTERY_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static BUDGET_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static WIFI_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static ALERTS_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static POWER_STATE_NOTIF: Notification = Notification::new();
 
 static CONN_SIGNAL: Signal<CriticalSectionRawMutex, bool> = Signal::new();
 
-pub async fn send<const L: usize>(topic_prefix: &str, mut mqtt: impl Client + Publish) {
+pub async fn send<const L: usize>(
+    topic_prefix: &str,
+    broker: &str,
+    mut mqtt: impl Client + Publish,
+) {
     let mut connected = false;
 
+    STATE.update_with(|state| MqttState {
+        broker: String::from_str(broker).unwrap_or_default(),
+        ..state
+    });
+
     let topic = |topic_suffix| {
         String::<L>::from_str(topic_prefix.as_ref())
             .and_then(|mut s| s.push_str(topic_suffix).map(|_| s))
@@ -65,47 +121,111 @@ pub async fn send<const L: usize>(topic_prefix: &str, mut mqtt: impl Client + Pu
 
     let topic_commands = topic("/commands/#");
 
-    let topic_valve = topic("/valve");
+    let valve_topic = |valve: usize| {
+        let mut topic = topic("/valve/");
+
+        write!(&mut topic, "{}", valve).unwrap_or_else(|_| panic!(""));
 
-    let topic_meter_edges = topic("/meter/edges");
-    let topic_meter_armed = topic("/meter/armed");
-    let topic_meter_leak = topic("/meter/leak");
+        topic
+    };
+
+    let meter_topic = |meter: usize, topic_suffix: &str| {
+        let mut topic = topic("/meter/");
+
+        write!(&mut topic, "{}/{}", meter, topic_suffix).unwrap_or_else(|_| panic!(""));
+
+        topic
+    };
 
     let topic_battery_voltage = topic("/battery/voltage");
     let topic_battery_low = topic("/battery/low");
+    let topic_battery_critical = topic("/battery/critical");
     let topic_battery_charged = topic("/battery/charged");
+    let topic_battery_time_to_empty = topic("/battery/time_to_empty");
 
     let topic_powered = topic("/powered");
+    let topic_outage_started = topic("/power/outage_started");
+    let topic_outage_ended = topic("/power/outage_ended");
 
-    let mut published_valve_state = None;
-    let mut published_wm_state: Option<WaterMeterState> = None;
+    let topic_budget = topic("/budget");
+
+    let topic_alerts = topic("/alerts");
+
+    let mut published_valve_states: [Option<ValveState>; MAX_VALVES] = [None; MAX_VALVES];
+    let mut published_wm_states: [Option<WaterMeterState>; MAX_METERS] = [None; MAX_METERS];
+    let mut published_sensor_faults: [Option<Option<SensorFault>>; MAX_METERS] = [None; MAX_METERS];
     let mut published_battery_state: Option<BatteryState> = None;
+    let mut published_budget_state: Option<BudgetState> = None;
+    let mut published_alerts: Option<String<128>> = None;
+    let mut published_outage: Option<Outage> = None;
 
     loop {
-        let (conn_state, valve_state, wm_state, battery_state) = if connected {
-            match select4(
-                CONN_SIGNAL.wait(),
-                VALVE_STATE_NOTIF.wait(),
-                WM_STATE_NOTIF.wait(),
-                BATTERY_STATE_NOTIF.wait(),
-            )
-            .await
-            {
-                Either4::First(conn_state) => (Some(conn_state), None, None, None),
-                Either4::Second(_) => (
-                    None,
-                    Some(valve::STATE.get().map(|state| state.simplify())),
-                    None,
-                    None,
-                ),
-                Either4::Third(_) => (None, None, Some(wm::STATE.get()), None),
-                Either4::Fourth(_) => (None, None, None, Some(battery::STATE.get())),
-            }
-        } else {
-            let conn_state = CONN_SIGNAL.wait().await;
+        let (conn_state, valves, meters, battery_state, budget_state, alerts_state, outage) =
+            if connected {
+                match select4(
+                    CONN_SIGNAL.wait(),
+                    VALVE_STATE_NOTIF.wait(),
+                    WM_STATE_NOTIF.wait(),
+                    select4(
+                        BATTERY_STATE_NOTIF.wait(),
+                        BUDGET_STATE_NOTIF.wait(),
+                        ALERTS_STATE_NOTIF.wait(),
+                        POWER_STATE_NOTIF.wait(),
+                    ),
+                )
+                .await
+                {
+                    Either4::First(conn_state) => {
+                        (Some(conn_state), None, None, None, None, None, None)
+                    }
+                    Either4::Second(_) => (
+                        None,
+                        Some(valve::configured()),
+                        None,
+                        None,
+                        None,
+                        None,
+                        None,
+                    ),
+                    Either4::Third(_) => {
+                        (None, None, Some(wm::configured()), None, None, None, None)
+                    }
+                    Either4::Fourth(Either4::First(_)) => (
+                        None,
+                        None,
+                        None,
+                        Some(battery::STATE.get()),
+                        None,
+                        None,
+                        None,
+                    ),
+                    Either4::Fourth(Either4::Second(_)) => (
+                        None,
+                        None,
+                        None,
+                        None,
+                        Some(budget::STATE.get()),
+                        None,
+                        None,
+                    ),
+                    Either4::Fourth(Either4::Third(_)) => {
+                        (None, None, None, None, None, Some(alert::STATE.get()), None)
+                    }
+                    Either4::Fourth(Either4::Fourth(_)) => (
+                        None,
+                        None,
+                        None,
+                        None,
+                        None,
+                        None,
+                        power::STATE.get().outages.last().copied(),
+                    ),
+                }
+            } else {
+                let conn_state = CONN_SIGNAL.wait().await;
 
-            (Some(conn_state), None, None, None)
-        };
+                (Some(conn_state), None, None, None, None, None, None)
+            };
 
         if let Some(conn_state) = conn_state {
             if conn_state {
@@ -117,84 +237,157 @@ pub async fn send<const L: usize>(topic_prefix: &str, mut mqtt: impl Client + Pu
                 )
                 .unwrap();
 
+                for topic in leak_sensor::mqtt_topics() {
+                    error::check!(mqtt.subscribe(topic, QoS::AtLeastOnce).await).unwrap();
+                }
+
                 connected = true;
             } else {
                 info!("MQTT disconnected");
 
                 connected = false;
             }
+
+            STATE.update_with(|state| MqttState {
+                connected: Some(connected),
+                ..state
+            });
         }
 
-        if let Some(valve_state) = valve_state {
-            if published_valve_state != valve_state {
-                published_valve_state = valve_state;
+        if let Some(valves) = valves {
+            for valve in valves {
+                let valve_state = valve::STATES[valve as usize]
+                    .get()
+                    .map(|state| state.simplify());
 
-                let status = match valve_state {
-                    Some(ValveState::Open) => "open",
-                    Some(ValveState::Opening(_)) => "opening",
-                    Some(ValveState::Closed) => "closed",
-                    Some(ValveState::Closing(_)) => "closing",
-                    None => "unknown",
-                };
+                if published_valve_states[valve as usize] != valve_state {
+                    published_valve_states[valve as usize] = valve_state;
 
-                publish(
-                    connected,
-                    &mut mqtt,
-                    &topic_valve,
-                    QoS::AtLeastOnce,
-                    status.as_bytes(),
-                )
-                .await;
+                    let status = match valve_state {
+                        Some(ValveState::Open) => "open",
+                        Some(ValveState::Opening(_)) => "opening",
+                        Some(ValveState::Closed) => "closed",
+                        Some(ValveState::Closing(_)) => "closing",
+                        None => "unknown",
+                    };
+
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &valve_topic(valve as usize),
+                        QoS::AtLeastOnce,
+                        status.as_bytes(),
+                    )
+                    .await;
+                }
             }
         }
 
-        if let Some(wm_state) = wm_state {
-            if published_wm_state
-                .map(|p| p.edges_count != wm_state.edges_count)
-                .unwrap_or(true)
-            {
-                let num = wm_state.edges_count.to_le_bytes();
-                let num_slice: &[u8] = &num;
+        if let Some(meters) = meters {
+            for meter in meters {
+                let meter = meter as usize;
 
-                publish(
-                    connected,
-                    &mut mqtt,
-                    &topic_meter_edges,
-                    QoS::AtLeastOnce,
-                    num_slice,
-                )
-                .await;
-            }
+                let wm_state = wm::STATES[meter].get();
+                let published_wm_state = published_wm_states[meter];
 
-            if published_wm_state
-                .map(|p| p.armed != wm_state.armed)
-                .unwrap_or(true)
-            {
-                publish(
-                    connected,
-                    &mut mqtt,
-                    &topic_meter_armed,
-                    QoS::AtLeastOnce,
-                    (if wm_state.armed { "true" } else { "false" }).as_bytes(),
-                )
-                .await;
-            }
+                if published_wm_state
+                    .map(|p| p.edges_count != wm_state.edges_count)
+                    .unwrap_or(true)
+                {
+                    let num = wm_state.edges_count.to_le_bytes();
+                    let num_slice: &[u8] = &num;
 
-            if published_wm_state
-                .map(|p| p.leaking != wm_state.leaking)
-                .unwrap_or(true)
-            {
-                publish(
-                    connected,
-                    &mut mqtt,
-                    &topic_meter_leak,
-                    QoS::AtLeastOnce,
-                    (if wm_state.armed { "true" } else { "false" }).as_bytes(),
-                )
-                .await;
-            }
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &meter_topic(meter, "edges"),
+                        QoS::AtLeastOnce,
+                        num_slice,
+                    )
+                    .await;
+
+                    if let Some(config) = wm::config(meter as MeterId) {
+                        let num =
+                            (wm_state.edges_count * config.edge_volume_ml as u64).to_le_bytes();
+                        let num_slice: &[u8] = &num;
+
+                        publish(
+                            connected,
+                            &mut mqtt,
+                            &meter_topic(meter, "volume_ml"),
+                            QoS::AtLeastOnce,
+                            num_slice,
+                        )
+                        .await;
+                    }
+                }
+
+                if published_wm_state
+                    .map(|p| p.reverse_edges_count != wm_state.reverse_edges_count)
+                    .unwrap_or(true)
+                {
+                    let num = wm_state.reverse_edges_count.to_le_bytes();
+                    let num_slice: &[u8] = &num;
+
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &meter_topic(meter, "reverse_edges"),
+                        QoS::AtLeastOnce,
+                        num_slice,
+                    )
+                    .await;
+                }
+
+                if published_wm_state
+                    .map(|p| p.armed != wm_state.armed)
+                    .unwrap_or(true)
+                {
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &meter_topic(meter, "armed"),
+                        QoS::AtLeastOnce,
+                        (if wm_state.armed { "true" } else { "false" }).as_bytes(),
+                    )
+                    .await;
+                }
+
+                if published_wm_state
+                    .map(|p| p.leaking != wm_state.leaking)
+                    .unwrap_or(true)
+                {
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &meter_topic(meter, "leak"),
+                        QoS::AtLeastOnce,
+                        (if wm_state.leaking { "true" } else { "false" }).as_bytes(),
+                    )
+                    .await;
+                }
+
+                published_wm_states[meter] = Some(wm_state);
+
+                // The sensor health shares the notification of the meter state
+                let sensor_fault = wm::HEALTH[meter].get().fault;
+
+                if published_sensor_faults[meter] != Some(sensor_fault) {
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &meter_topic(meter, "sensor"),
+                        QoS::AtLeastOnce,
+                        sensor_fault
+                            .map(|fault| fault.name())
+                            .unwrap_or("ok")
+                            .as_bytes(),
+                    )
+                    .await;
 
-            published_wm_state = Some(wm_state);
+                    published_sensor_faults[meter] = Some(sensor_fault);
+                }
+            }
         }
 
         if let Some(battery_state) = battery_state {
@@ -214,46 +407,76 @@ pub async fn send<const L: usize>(topic_prefix: &str, mut mqtt: impl Client + Pu
                         num_slice,
                     )
                     .await;
+                }
+            }
 
-                    if let Some(prev_voltage) = published_battery_state.and_then(|p| p.voltage) {
-                        if (prev_voltage > BatteryState::LOW_VOLTAGE)
-                            != (voltage > BatteryState::LOW_VOLTAGE)
-                        {
-                            let status = if voltage > BatteryState::LOW_VOLTAGE {
-                                "false"
-                            } else {
-                                "true"
-                            };
-
-                            publish(
-                                connected,
-                                &mut mqtt,
-                                &topic_battery_low,
-                                QoS::AtLeastOnce,
-                                status.as_bytes(),
-                            )
-                            .await;
-                        }
+            if published_battery_state
+                .map(|p| p.level != battery_state.level)
+                .unwrap_or(true)
+            {
+                if let Some(level) = battery_state.level {
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &topic_battery_low,
+                        QoS::AtLeastOnce,
+                        (if level != BatteryLevel::Normal {
+                            "true"
+                        } else {
+                            "false"
+                        })
+                        .as_bytes(),
+                    )
+                    .await;
 
-                        if (prev_voltage >= BatteryState::MAX_VOLTAGE)
-                            != (voltage >= BatteryState::MAX_VOLTAGE)
-                        {
-                            let status = if voltage >= BatteryState::MAX_VOLTAGE {
-                                "true"
-                            } else {
-                                "false"
-                            };
-
-                            publish(
-                                connected,
-                                &mut mqtt,
-                                &topic_battery_charged,
-                                QoS::AtMostOnce,
-                                status.as_bytes(),
-                            )
-                            .await;
-                        }
-                    }
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &topic_battery_critical,
+                        QoS::AtLeastOnce,
+                        (if level == BatteryLevel::Critical {
+                            "true"
+                        } else {
+                            "false"
+                        })
+                        .as_bytes(),
+                    )
+                    .await;
+                }
+            }
+
+            if published_battery_state
+                .map(|p| p.charged() != battery_state.charged())
+                .unwrap_or(true)
+            {
+                if let Some(charged) = battery_state.charged() {
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &topic_battery_charged,
+                        QoS::AtMostOnce,
+                        (if charged { "true" } else { "false" }).as_bytes(),
+                    )
+                    .await;
+                }
+            }
+
+            if published_battery_state
+                .map(|p| p.time_to_empty != battery_state.time_to_empty)
+                .unwrap_or(true)
+            {
+                if let Some(time_to_empty) = battery_state.time_to_empty {
+                    let num = time_to_empty.to_le_bytes();
+                    let num_slice: &[u8] = &num;
+
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &topic_battery_time_to_empty,
+                        QoS::AtMostOnce,
+                        num_slice,
+                    )
+                    .await;
                 }
             }
 
@@ -275,6 +498,99 @@ pub async fn send<const L: usize>(topic_prefix: &str, mut mqtt: impl Client + Pu
 
             published_battery_state = Some(battery_state);
         };
+
+        if let Some(budget_state) = budget_state {
+            if published_budget_state
+                .map(|p| p.level != budget_state.level)
+                .unwrap_or(true)
+            {
+                let status = match budget_state.level {
+                    BudgetLevel::Normal => "normal",
+                    BudgetLevel::Warning => "warning",
+                    BudgetLevel::Exceeded => "exceeded",
+                };
+
+                publish(
+                    connected,
+                    &mut mqtt,
+                    &topic_budget,
+                    QoS::AtLeastOnce,
+                    status.as_bytes(),
+                )
+                .await;
+            }
+
+            published_budget_state = Some(budget_state);
+        }
+
+        if let Some(alerts_state) = alerts_state {
+            // Only the alerts which still need the attention of the user are published
+            let mut status = String::<128>::new();
+
+            for alert in alerts_state
+                .alerts
+                .iter()
+                .filter(|alert| !alert.acknowledged)
+            {
+                if !status.is_empty() {
+                    let _ = status.push(',');
+                }
+
+                let _ = status.push_str(alert.kind.name());
+
+                // E.g. `flood:Kitchen`
+                if let Some(config) = alert.sensor.and_then(leak_sensor::config) {
+                    let _ = status.push(':');
+                    let _ = status.push_str(config.name);
+                }
+            }
+
+            if published_alerts.as_ref() != Some(&status) {
+                publish(
+                    connected,
+                    &mut mqtt,
+                    &topic_alerts,
+                    QoS::AtLeastOnce,
+                    status.as_bytes(),
+                )
+                .await;
+            }
+
+            published_alerts = Some(status);
+        }
+
+        if let Some(outage) = outage {
+            if published_outage != Some(outage) {
+                // The payloads are empty when the time is not known
+                if outage.ongoing {
+                    let num = outage.started.map(u64::to_le_bytes);
+                    let num_slice: &[u8] = num.as_ref().map(|num| num as &[u8]).unwrap_or(&[]);
+
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &topic_outage_started,
+                        QoS::AtLeastOnce,
+                        num_slice,
+                    )
+                    .await;
+                } else {
+                    let num = outage.duration.map(u32::to_le_bytes);
+                    let num_slice: &[u8] = num.as_ref().map(|num| num as &[u8]).unwrap_or(&[]);
+
+                    publish(
+                        connected,
+                        &mut mqtt,
+                        &topic_outage_ended,
+                        QoS::AtLeastOnce,
+                        num_slice,
+                    )
+                    .await;
+                }
+            }
+
+            published_outage = Some(outage);
+        }
     }
 }
 
@@ -288,6 +604,11 @@ async fn publish(connected: bool, mqtt: &mut impl Publish, topic: &str, qos: QoS
                 for notification in PUBLISH_NOTIFY {
                     notification.notify();
                 }
+
+                STATE.update_with(|state| MqttState {
+                    last_publish_secs: clock::now().or(state.last_publish_secs),
+                    ..state
+                });
             }
         }
     } else {
@@ -303,20 +624,63 @@ pub async fn receive(mut connection: impl Connection<Message = Option<MqttComman
             info!("[MQTT/CONNECTION]: {:?}", message);
 
             if let Ok(Event::Received(Some(cmd))) = &message {
+                // The reports of the leak sensors are not commands of the user
+                if !matches!(cmd, MqttCommand::LeakSensor(_, _)) {
+                    sleep::mark(Activity::MqttCommand);
+                }
+
                 match cmd {
-                    MqttCommand::Valve(open) => {
-                        valve::COMMAND.signal(if *open {
-                            ValveCommand::Open
-                        } else {
-                            ValveCommand::Close
-                        });
+                    MqttCommand::Valve(valve, open) => {
+                        valve::command(
+                            *valve,
+                            if *open {
+                                ValveCommand::Open
+                            } else {
+                                ValveCommand::Close
+                            },
+                        );
                     }
-                    MqttCommand::FlowWatch(enable) => {
-                        wm::COMMAND.signal(if *enable {
-                            WaterMeterCommand::Arm
-                        } else {
-                            WaterMeterCommand::Disarm
-                        });
+                    MqttCommand::FlowWatch(meter, enable) => {
+                        wm::command(
+                            *meter,
+                            if *enable {
+                                WaterMeterCommand::Arm
+                            } else {
+                                WaterMeterCommand::Disarm
+                            },
+                        );
+                    }
+                    MqttCommand::Schedule(enable) => {
+                        schedule::COMMAND.signal(ScheduleCommand::Enable(*enable));
+                    }
+                    MqttCommand::ScheduleRule(index, rule) => {
+                        let command = match (index, rule) {
+                            (None, Some(rule)) => Some(ScheduleCommand::AddRule(*rule)),
+                            (Some(index), Some(rule)) => {
+                                Some(ScheduleCommand::UpdateRule(*index, *rule))
+                            }
+                            (Some(index), None) => Some(ScheduleCommand::RemoveRule(*index)),
+                            (None, None) => None,
+                        };
+
+                        if let Some(command) = command {
+                            schedule::COMMAND.signal(command);
+                        }
+                    }
+                    MqttCommand::Vacation(days) => {
+                        schedule::COMMAND.signal(ScheduleCommand::Vacation(*days));
+                    }
+                    MqttCommand::SystemUpdate => {
+                        update::COMMAND.signal(UpdateCommand::Update);
+                    }
+                    MqttCommand::AcknowledgeAlerts => {
+                        alert::COMMAND.signal(AlertCommand::AcknowledgeAll);
+                    }
+                    MqttCommand::LeakSensor(Some(sensor), wet) => {
+                        leak_sensor::report(*sensor, *wet);
+                    }
+                    MqttCommand::TimeZone(time_zone) => {
+                        clock::TIME_ZONE.update(*time_zone);
                     }
                     _ => (),
                 }
@@ -335,16 +699,24 @@ pub async fn receive(mut connection: impl Connection<Message = Option<MqttComman
     }
 }
 
-#[derive(Default)]
+/// Large enough for the JSON reports of the zigbee2mqtt sensors, which carry more than the leak
+const PAYLOAD_MAX_LEN: usize = 256;
+
 pub struct MessageParser {
     #[allow(clippy::type_complexity)]
     command_parser: Option<fn(&[u8]) -> Option<MqttCommand>>,
-    payload_buf: [u8; 16],
+    /// The meter, valve or leak sensor addressed by the topic of the command being received
+    command_target: Option<u8>,
+    payload_buf: [u8; PAYLOAD_MAX_LEN],
 }
 
 impl MessageParser {
     pub fn new() -> Self {
-        Default::default()
+        Self {
+            command_parser: None,
+            command_target: None,
+            payload_buf: [0; PAYLOAD_MAX_LEN],
+        }
     }
 
     pub fn convert<M, E>(
@@ -366,13 +738,19 @@ impl MessageParser {
         M: Message,
     {
         match message.details() {
-            Details::Complete => Self::parse_command(message.topic().unwrap())
-                .and_then(|parser| parser(message.data())),
+            Details::Complete => {
+                let topic = message.topic().unwrap();
+
+                Self::parse_command(topic)
+                    .and_then(|parser| parser(message.data()))
+                    .map(|command| Self::address(command, Self::parse_target(topic)))
+            }
             Details::InitialChunk(initial_chunk_data) => {
                 if initial_chunk_data.total_data_size > self.payload_buf.len() {
                     self.command_parser = None;
                 } else {
                     self.command_parser = Self::parse_command(message.topic().unwrap());
+                    self.command_target = Self::parse_target(message.topic().unwrap());
 
                     self.payload_buf[..message.data().len()]
                         .copy_from_slice(message.data().as_ref());
@@ -382,14 +760,16 @@ impl MessageParser {
             }
             Details::SubsequentChunk(subsequent_chunk_data) => {
                 if let Some(command_parser) = self.command_parser.as_ref() {
-                    self.payload_buf
-                        [subsequent_chunk_data.current_data_offset..message.data().len()]
+                    let offset = subsequent_chunk_data.current_data_offset;
+
+                    self.payload_buf[offset..offset + message.data().len()]
                         .copy_from_slice(message.data().as_ref());
 
                     if subsequent_chunk_data.total_data_size
                         == subsequent_chunk_data.current_data_offset + message.data().len()
                     {
                         command_parser(&self.payload_buf[0..subsequent_chunk_data.total_data_size])
+                            .map(|command| Self::address(command, self.command_target))
                     } else {
                         None
                     }
@@ -402,25 +782,134 @@ impl MessageParser {
 
     #[allow(clippy::type_complexity)]
     fn parse_command(topic: &str) -> Option<fn(&[u8]) -> Option<MqttCommand>> {
-        if topic.ends_with("/commands/valve") {
+        if topic.ends_with("/commands/valve") || Self::parse_valve(topic).is_some() {
             Some(Self::parse_valve_command)
-        } else if topic.ends_with("/commands/flow_watch") {
+        } else if topic.ends_with("/commands/flow_watch") || Self::parse_meter(topic).is_some() {
             Some(Self::parse_flow_watch_command)
+        } else if topic.ends_with("/commands/schedule") {
+            Some(Self::parse_schedule_command)
+        } else if topic.ends_with("/commands/schedule/rules")
+            || Self::parse_schedule_rule(topic).is_some()
+        {
+            Some(Self::parse_schedule_rule_command)
+        } else if topic.ends_with("/commands/vacation") {
+            Some(Self::parse_vacation_command)
         } else if topic.ends_with("/commands/keep_alive") {
             Some(Self::parse_keep_alive_command)
         } else if topic.ends_with("/commands/system_update") {
             Some(Self::parse_system_update_command)
+        } else if topic.ends_with("/commands/ack_alerts") {
+            Some(Self::parse_ack_alerts_command)
+        } else if topic.ends_with("/commands/time_zone") {
+            Some(Self::parse_time_zone_command)
+        } else if leak_sensor::mqtt_sensor(topic).is_some() {
+            Some(Self::parse_leak_sensor_report)
         } else {
             None
         }
     }
 
     fn parse_valve_command(data: &[u8]) -> Option<MqttCommand> {
-        Self::parse::<bool>(data).map(MqttCommand::Valve)
+        Self::parse::<bool>(data).map(|open| MqttCommand::Valve(None, open))
+    }
+
+    /// The valve of `<prefix>/commands/valve/<id>`
+    fn parse_valve(topic: &str) -> Option<ValveId> {
+        let (topic, valve) = topic.rsplit_once('/')?;
+
+        if topic.ends_with("/commands/valve") {
+            valve
+                .parse::<ValveId>()
+                .ok()
+                .filter(|valve| (*valve as usize) < MAX_VALVES)
+        } else {
+            None
+        }
     }
 
     fn parse_flow_watch_command(data: &[u8]) -> Option<MqttCommand> {
-        Self::parse::<bool>(data).map(MqttCommand::FlowWatch)
+        Self::parse::<bool>(data).map(|enable| MqttCommand::FlowWatch(None, enable))
+    }
+
+    /// The meter of `<prefix>/commands/meter/<id>/flow_watch`
+    fn parse_meter(topic: &str) -> Option<MeterId> {
+        let (topic, meter) = topic.strip_suffix("/flow_watch")?.rsplit_once('/')?;
+
+        if topic.ends_with("/commands/meter") {
+            meter
+                .parse::<MeterId>()
+                .ok()
+                .filter(|meter| (*meter as usize) < MAX_METERS)
+        } else {
+            None
+        }
+    }
+
+    /// The index of `<prefix>/commands/schedule/rules/<index>`
+    fn parse_schedule_rule(topic: &str) -> Option<u8> {
+        let (topic, index) = topic.rsplit_once('/')?;
+
+        if topic.ends_with("/commands/schedule/rules") {
+            index
+                .parse::<u8>()
+                .ok()
+                .filter(|index| (*index as usize) < MAX_RULES)
+        } else {
+            None
+        }
+    }
+
+    /// A rule in the text form of `ScheduleRule`, or nothing to remove the rule
+    fn parse_schedule_rule_command(data: &[u8]) -> Option<MqttCommand> {
+        if data.is_empty() {
+            Some(MqttCommand::ScheduleRule(None, None))
+        } else {
+            Self::parse::<ScheduleRule>(data)
+                .map(|rule| MqttCommand::ScheduleRule(None, Some(rule)))
+        }
+    }
+
+    /// The `water_leak` property of a JSON payload, as published by zigbee2mqtt
+    fn parse_leak_sensor_report(data: &[u8]) -> Option<MqttCommand> {
+        let (_, value) = str::from_utf8(data).ok()?.split_once("\"water_leak\"")?;
+        let value = value.trim_start().strip_prefix(':')?.trim_start();
+
+        let wet = if value.starts_with("true") {
+            true
+        } else if value.starts_with("false") {
+            false
+        } else {
+            return None;
+        };
+
+        Some(MqttCommand::LeakSensor(None, wet))
+    }
+
+    fn parse_target(topic: &str) -> Option<u8> {
+        Self::parse_valve(topic)
+            .or_else(|| Self::parse_meter(topic))
+            .or_else(|| Self::parse_schedule_rule(topic))
+            .or_else(|| leak_sensor::mqtt_sensor(topic))
+    }
+
+    fn address(command: MqttCommand, target: Option<u8>) -> MqttCommand {
+        match command {
+            MqttCommand::Valve(_, open) if target.is_some() => MqttCommand::Valve(target, open),
+            MqttCommand::FlowWatch(_, enable) if target.is_some() => {
+                MqttCommand::FlowWatch(target, enable)
+            }
+            MqttCommand::ScheduleRule(_, rule) => MqttCommand::ScheduleRule(target, rule),
+            MqttCommand::LeakSensor(_, wet) => MqttCommand::LeakSensor(target, wet),
+            command => command,
+        }
+    }
+
+    fn parse_schedule_command(data: &[u8]) -> Option<MqttCommand> {
+        Self::parse::<bool>(data).map(MqttCommand::Schedule)
+    }
+
+    fn parse_vacation_command(data: &[u8]) -> Option<MqttCommand> {
+        Self::parse::<u16>(data).map(MqttCommand::Vacation)
     }
 
     fn parse_keep_alive_command(data: &[u8]) -> Option<MqttCommand> {
@@ -431,6 +920,18 @@ impl MessageParser {
         Self::parse_empty(data).map(|_| MqttCommand::SystemUpdate)
     }
 
+    fn parse_ack_alerts_command(data: &[u8]) -> Option<MqttCommand> {
+        Self::parse_empty(data).map(|_| MqttCommand::AcknowledgeAlerts)
+    }
+
+    /// One of the names of `TimeZone::NAMED`, e.g. `central_europe`
+    fn parse_time_zone_command(data: &[u8]) -> Option<MqttCommand> {
+        str::from_utf8(data)
+            .ok()
+            .and_then(TimeZone::named)
+            .map(MqttCommand::TimeZone)
+    }
+
     fn parse<T>(data: &[u8]) -> Option<T>
     where
         T: str::FromStr,
@@ -448,3 +949,9 @@ impl MessageParser {
         }
     }
 }
+
+impl Default for MessageParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
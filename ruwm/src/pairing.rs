@@ -0,0 +1,69 @@
+use embassy_time::{Duration, Instant};
+
+use crate::keepalive::{self, Lease};
+use crate::state::State;
+
+const VALIDITY: Duration = Duration::from_secs(60 * 5);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PairingCode {
+    pub code: u32,
+    pub expires: Instant,
+}
+
+impl PairingCode {
+    pub fn is_valid(&self, now: Instant) -> bool {
+        now < self.expires
+    }
+}
+
+pub static STATE: State<Option<PairingCode>> = State::new(
+    "PAIRING",
+    None,
+    &[
+        &crate::keepalive::NOTIF,
+        &crate::screen::PAIRING_STATE_NOTIF,
+    ],
+);
+
+/// Generates a new six-digit pairing code, replacing the previous one
+pub fn generate() {
+    let now = Instant::now();
+
+    // The microsecond at which the user triggered the pairing is unpredictable
+    // enough for a short-lived, single-use code
+    let mut seed = now.as_micros() ^ 0x2545_f491_4f6c_dd1d;
+    seed ^= seed << 13;
+    seed ^= seed >> 7;
+    seed ^= seed << 17;
+
+    STATE.update(Some(PairingCode {
+        code: (seed % 1_000_000) as u32,
+        expires: now + VALIDITY,
+    }));
+
+    keepalive::acquire(Lease::Pairing, Some(VALIDITY));
+}
+
+/// Returns whether the code matches the one shown on the screen.
+/// The code is consumed even by a failed attempt, so that it cannot be guessed.
+pub fn pair(code: u32) -> bool {
+    let mut pairing = None;
+
+    STATE.update_with(|state| {
+        pairing = state;
+
+        None
+    });
+
+    let paired = pairing
+        .map(|pairing| pairing.is_valid(Instant::now()) && pairing.code == code)
+        .unwrap_or(false);
+
+    // After a failed attempt, stay awake for as long as the code was valid, for a new one
+    if paired {
+        keepalive::end(Lease::Pairing);
+    }
+
+    paired
+}
@@ -0,0 +1,86 @@
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::Instant;
+
+use channel_bridge::notification::Notification;
+
+use crate::state::State;
+use crate::storage::{self, Storage, Versioned};
+use crate::{battery, clock};
+
+pub use crate::dto::power::*;
+
+const STORAGE_KEY: &str = "power";
+
+pub static STATE: State<PowerState> = State::new(
+    "POWER",
+    PowerState::new(),
+    &[
+        &crate::keepalive::NOTIF,
+        &crate::emergency::POWER_STATE_NOTIF,
+        &crate::mqtt::POWER_STATE_NOTIF,
+        &crate::web::POWER_STATE_NOTIF,
+        &STATE_PERSIST_NOTIFY,
+    ],
+);
+
+pub(crate) static BATTERY_STATE_NOTIF: Notification = Notification::new();
+
+static STATE_PERSIST_NOTIFY: Notification = Notification::new();
+
+pub async fn process() {
+    // Only known if the outage started since the last boot, which is enough when the clock is not synchronized
+    let mut outage_since: Option<Instant> = None;
+
+    loop {
+        BATTERY_STATE_NOTIF.wait().await;
+
+        let powered = battery::STATE.get().powered;
+
+        match powered {
+            Some(false) => {
+                if STATE.get().outage().is_none() {
+                    outage_since = Some(Instant::now());
+                }
+
+                STATE.update_with(|mut state| {
+                    state.start_outage(clock::now());
+
+                    state
+                });
+            }
+            Some(true) => {
+                let measured = outage_since
+                    .take()
+                    .map(|since| (Instant::now() - since).as_secs() as u32);
+
+                STATE.update_with(|mut state| {
+                    state.end_outage(clock::now(), measured);
+
+                    state
+                });
+            }
+            None => (),
+        }
+    }
+}
+
+impl Versioned for PowerState {
+    const VERSION: u8 = 1;
+}
+
+pub fn restore(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>) {
+    if let Some(state) = storage::restore(storage, STORAGE_KEY) {
+        STATE.set(state);
+    }
+}
+
+pub async fn persist(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>) {
+    loop {
+        STATE_PERSIST_NOTIFY.wait().await;
+
+        storage::persist(storage, STORAGE_KEY, &STATE.get());
+    }
+}
@@ -10,14 +10,24 @@ use channel_bridge::notification::Notification;
 
 use crate::button::{self, PressedLevel};
 
+pub mod pcnt;
+
 pub trait PulseCounter {
     type Error: Debug;
 
-    type TakePulsesFuture<'a>: Future<Output = Result<u64, Self::Error>>
+    /// Resolves to the pulses counted since the previous call; negative when the meter turned backwards
+    ///
+    /// The future is dropped when no pulses came for a while, so it should not hold pulses already counted
+    type TakePulsesFuture<'a>: Future<Output = Result<i64, Self::Error>>
     where
         Self: 'a;
 
     fn take_pulses(&mut self) -> Self::TakePulsesFuture<'_>;
+
+    /// Whether the sensor is active right now, if the counter can tell
+    fn sensor_active(&mut self) -> Option<bool> {
+        None
+    }
 }
 
 impl<T> PulseCounter for &mut T
@@ -31,6 +41,10 @@ where
     fn take_pulses(&mut self) -> Self::TakePulsesFuture<'_> {
         (*self).take_pulses()
     }
+
+    fn sensor_active(&mut self) -> Option<bool> {
+        (*self).sensor_active()
+    }
 }
 
 pub trait PulseWakeup {
@@ -79,7 +93,7 @@ where
 {
     type Error = Infallible;
 
-    type TakePulsesFuture<'b> = impl Future<Output = Result<u64, Self::Error>> + 'b where Self: 'b;
+    type TakePulsesFuture<'b> = impl Future<Output = Result<i64, Self::Error>> + 'b where Self: 'b;
 
     fn take_pulses(&mut self) -> Self::TakePulsesFuture<'_> {
         async move {
@@ -94,6 +108,10 @@ where
             Ok(1)
         }
     }
+
+    fn sensor_active(&mut self) -> Option<bool> {
+        Some(button::is_pressed(&self.pin, self.pressed_level))
+    }
 }
 
 impl<'a, P> PulseWakeup for CpuPulseCounter<'a, P> {
@@ -104,6 +122,101 @@ impl<'a, P> PulseWakeup for CpuPulseCounter<'a, P> {
     }
 }
 
+/// The quarter steps between two A/B readings, indexed by `previous << 2 | current`.
+/// Readings where both sensors changed at once are ambiguous and count as no step.
+const QUADRATURE_STEPS: [i8; 16] = [0, 1, -1, 0, -1, 0, 0, 1, 1, 0, 0, -1, 0, -1, 1, 0];
+
+/// Counts the pulses of a meter with two sensors (A and B) a quarter of a turn apart.
+///
+/// Each full cycle of the sensors is one pulse, negative when they are passed in reverse
+/// order. A sensor bouncing or the meter vibrating on the edge of a sensor only moves
+/// back and forth between two quarter steps, so no debouncing is necessary.
+pub struct QuadraturePulseCounter<'a, PA, PB> {
+    pin_a: PA,
+    pin_b: PB,
+    /// Notified on the edges of both sensors
+    pin_edge: &'a Notification,
+    pressed_level: PressedLevel,
+    ab: Option<u8>,
+    quarter_steps: i8,
+}
+
+impl<'a, PA, PB> QuadraturePulseCounter<'a, PA, PB> {
+    pub const fn new(
+        pin_a: PA,
+        pin_b: PB,
+        pressed_level: PressedLevel,
+        pin_edge: &'a Notification,
+    ) -> Self {
+        Self {
+            pin_a,
+            pin_b,
+            pin_edge,
+            pressed_level,
+            ab: None,
+            quarter_steps: 0,
+        }
+    }
+}
+
+impl<'a, PA, PB> QuadraturePulseCounter<'a, PA, PB>
+where
+    PA: InputPin,
+    PB: InputPin,
+{
+    fn read(&self) -> u8 {
+        let active = |high: bool| high == (self.pressed_level == PressedLevel::High);
+
+        (active(self.pin_a.is_high().unwrap_or(false)) as u8) << 1
+            | active(self.pin_b.is_high().unwrap_or(false)) as u8
+    }
+}
+
+impl<'a, PA, PB> PulseCounter for QuadraturePulseCounter<'a, PA, PB>
+where
+    PA: InputPin,
+    PB: InputPin,
+{
+    type Error = Infallible;
+
+    type TakePulsesFuture<'b> = impl Future<Output = Result<i64, Self::Error>> + 'b where Self: 'b;
+
+    fn take_pulses(&mut self) -> Self::TakePulsesFuture<'_> {
+        async move {
+            if self.ab.is_none() {
+                self.ab = Some(self.read());
+            }
+
+            loop {
+                self.pin_edge.wait().await;
+
+                let ab = self.read();
+                let prev_ab = self.ab.replace(ab).unwrap_or(ab);
+
+                self.quarter_steps += QUADRATURE_STEPS[(prev_ab << 2 | ab) as usize];
+
+                if self.quarter_steps >= 4 {
+                    self.quarter_steps -= 4;
+
+                    return Ok(1);
+                } else if self.quarter_steps <= -4 {
+                    self.quarter_steps += 4;
+
+                    return Ok(-1);
+                }
+            }
+        }
+    }
+}
+
+impl<'a, PA, PB> PulseWakeup for QuadraturePulseCounter<'a, PA, PB> {
+    type Error = Infallible;
+
+    fn set_enabled(&mut self, _enabled: bool) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
 impl PulseWakeup for () {
     type Error = Infallible;
 
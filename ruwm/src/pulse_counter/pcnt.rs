@@ -0,0 +1,459 @@
+use core::cell::Cell;
+use core::convert::Infallible;
+use core::fmt::Debug;
+use core::future::Future;
+
+use enumset::{enum_set, EnumSet, EnumSetType};
+
+use embassy_futures::select::{select3, Either3};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::{Duration, Timer};
+
+use channel_bridge::notification::Notification;
+
+use super::{PulseCounter, PulseWakeup};
+
+#[derive(Debug, EnumSetType)]
+pub enum CounterEvent {
+    /// The count reached the high limit and was reset to zero
+    Overflow,
+    /// The count reached the low limit and was reset to zero
+    Underflow,
+    /// The count reached one of the thresholds
+    Threshold,
+}
+
+/// A hardware pulse counter, modelled after the ESP32 PCNT unit.
+///
+/// The count is signed and wraps to zero on reaching either limit. Events are latched
+/// until taken, so only one overflow is known between two reads; the limit should be
+/// large enough for that not to matter.
+pub trait HardwareCounter {
+    type Error: Debug;
+
+    type WaitEventsFuture<'a>: Future<Output = Result<(), Self::Error>>
+    where
+        Self: 'a;
+
+    /// Ignores pulses shorter than `filter`
+    fn set_glitch_filter(&mut self, filter: Option<Duration>) -> Result<(), Self::Error>;
+
+    /// The count overflows at `limit` and underflows at `-limit`
+    fn set_limit(&mut self, limit: i16) -> Result<(), Self::Error>;
+
+    /// Raises a threshold event when the count reaches either of the values
+    fn set_thresholds(&mut self, low: Option<i16>, high: Option<i16>) -> Result<(), Self::Error>;
+
+    fn count(&mut self) -> Result<i16, Self::Error>;
+
+    /// Returns and clears the latched events
+    fn take_events(&mut self) -> Result<EnumSet<CounterEvent>, Self::Error>;
+
+    /// Resolves once there are latched events
+    fn wait_events(&mut self) -> Self::WaitEventsFuture<'_>;
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PcntConfig {
+    pub glitch_filter: Option<Duration>,
+    pub limit: i16,
+    /// How often the count is read when the pulses do not raise events
+    pub poll_interval: Duration,
+}
+
+impl PcntConfig {
+    pub const fn new() -> Self {
+        Self {
+            glitch_filter: Some(Duration::from_micros(10)),
+            limit: i16::MAX,
+            poll_interval: Duration::from_secs(2),
+        }
+    }
+}
+
+impl Default for PcntConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether each pulse raises an event, and thus wakes up the device.
+///
+/// Shared by the counter and its `PulseWakeup`, which the water meter takes separately.
+pub struct PcntWakeup {
+    enabled: Mutex<CriticalSectionRawMutex, Cell<bool>>,
+    changed: Notification,
+}
+
+impl PcntWakeup {
+    pub const fn new() -> Self {
+        Self {
+            enabled: Mutex::new(Cell::new(false)),
+            changed: Notification::new(),
+        }
+    }
+}
+
+impl Default for PcntWakeup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> PulseWakeup for &'a PcntWakeup {
+    type Error = Infallible;
+
+    fn set_enabled(&mut self, enabled: bool) -> Result<(), Self::Error> {
+        self.enabled.lock(|cell| cell.set(enabled));
+        self.changed.notify();
+
+        Ok(())
+    }
+}
+
+pub struct PcntPulseCounter<'a, C> {
+    counter: C,
+    wakeup: &'a PcntWakeup,
+    config: PcntConfig,
+    /// The pulses counted until the count last wrapped to zero and not yet taken
+    wrapped: i64,
+    /// The count when the pulses were last taken
+    taken: i16,
+}
+
+impl<'a, C> PcntPulseCounter<'a, C>
+where
+    C: HardwareCounter,
+{
+    pub fn new(
+        mut counter: C,
+        wakeup: &'a PcntWakeup,
+        config: PcntConfig,
+    ) -> Result<Self, C::Error> {
+        counter.set_glitch_filter(config.glitch_filter)?;
+        counter.set_limit(config.limit)?;
+        counter.take_events()?;
+
+        let taken = counter.count()?;
+
+        Ok(Self {
+            counter,
+            wakeup,
+            config,
+            wrapped: 0,
+            taken,
+        })
+    }
+
+    fn pulses(&mut self) -> Result<i64, C::Error> {
+        let events = self.counter.take_events()?;
+
+        if events.contains(CounterEvent::Overflow) {
+            self.wrapped += self.config.limit as i64;
+        }
+
+        if events.contains(CounterEvent::Underflow) {
+            self.wrapped -= self.config.limit as i64;
+        }
+
+        let count = self.counter.count()?;
+        let pulses = self.wrapped + count as i64 - self.taken as i64;
+
+        self.wrapped = 0;
+        self.taken = count;
+
+        Ok(pulses)
+    }
+
+    fn set_thresholds(&mut self) -> Result<(), C::Error> {
+        if self.wakeup.enabled.lock(Cell::get) {
+            // The next pulse in either direction raises an event, unless it wraps the count anyway
+            let limit = self.config.limit;
+
+            self.counter.set_thresholds(
+                Some(self.taken - 1).filter(|low| *low > -limit),
+                Some(self.taken + 1).filter(|high| *high < limit),
+            )
+        } else {
+            self.counter.set_thresholds(None, None)
+        }
+    }
+}
+
+impl<'a, C> PulseCounter for PcntPulseCounter<'a, C>
+where
+    C: HardwareCounter,
+{
+    type Error = C::Error;
+
+    type TakePulsesFuture<'b> = impl Future<Output = Result<i64, Self::Error>> + 'b where Self: 'b;
+
+    fn take_pulses(&mut self) -> Self::TakePulsesFuture<'_> {
+        async move {
+            loop {
+                let pulses = self.pulses()?;
+
+                if pulses != 0 {
+                    return Ok(pulses);
+                }
+
+                self.set_thresholds()?;
+
+                // A pulse counted while the thresholds were set raised no event
+                if self.counter.count()? != self.taken {
+                    continue;
+                }
+
+                if let Either3::First(result) = select3(
+                    self.counter.wait_events(),
+                    self.wakeup.changed.wait(),
+                    Timer::after(self.config.poll_interval),
+                )
+                .await
+                {
+                    result?;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct MockCounterState {
+    count: i16,
+    limit: i16,
+    low: Option<i16>,
+    high: Option<i16>,
+    glitch_filter: Option<Duration>,
+    events: EnumSet<CounterEvent>,
+}
+
+/// A counter fed by hand, for running the pulse counting on the host
+pub struct MockCounter {
+    state: Mutex<CriticalSectionRawMutex, Cell<MockCounterState>>,
+    events: Notification,
+}
+
+impl MockCounter {
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(Cell::new(MockCounterState {
+                count: 0,
+                limit: i16::MAX,
+                low: None,
+                high: None,
+                glitch_filter: None,
+                events: enum_set!(),
+            })),
+            events: Notification::new(),
+        }
+    }
+
+    /// Counts a pulse of the given width, unless the glitch filter drops it
+    pub fn pulse(&self, width: Duration, forward: bool) -> bool {
+        let counted = self.state.lock(|cell| {
+            let mut state = cell.get();
+
+            if state
+                .glitch_filter
+                .map(|filter| width < filter)
+                .unwrap_or(false)
+            {
+                return false;
+            }
+
+            state.count += if forward { 1 } else { -1 };
+
+            if state.count >= state.limit {
+                state.count = 0;
+                state.events |= CounterEvent::Overflow;
+            } else if state.count <= -state.limit {
+                state.count = 0;
+                state.events |= CounterEvent::Underflow;
+            } else if Some(state.count) == state.low || Some(state.count) == state.high {
+                state.events |= CounterEvent::Threshold;
+            }
+
+            cell.set(state);
+
+            true
+        });
+
+        if counted {
+            self.events.notify();
+        }
+
+        counted
+    }
+
+    fn update(&self, f: impl FnOnce(&mut MockCounterState)) {
+        self.state.lock(|cell| {
+            let mut state = cell.get();
+            f(&mut state);
+
+            cell.set(state);
+        });
+    }
+}
+
+impl Default for MockCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> HardwareCounter for &'a MockCounter {
+    type Error = Infallible;
+
+    type WaitEventsFuture<'b> = impl Future<Output = Result<(), Self::Error>> + 'b where Self: 'b;
+
+    fn set_glitch_filter(&mut self, filter: Option<Duration>) -> Result<(), Self::Error> {
+        self.update(|state| state.glitch_filter = filter);
+
+        Ok(())
+    }
+
+    fn set_limit(&mut self, limit: i16) -> Result<(), Self::Error> {
+        self.update(|state| {
+            state.limit = limit;
+            state.count = 0;
+        });
+
+        Ok(())
+    }
+
+    fn set_thresholds(&mut self, low: Option<i16>, high: Option<i16>) -> Result<(), Self::Error> {
+        self.update(|state| {
+            state.low = low;
+            state.high = high;
+        });
+
+        Ok(())
+    }
+
+    fn count(&mut self) -> Result<i16, Self::Error> {
+        Ok(self.state.lock(|cell| cell.get().count))
+    }
+
+    fn take_events(&mut self) -> Result<EnumSet<CounterEvent>, Self::Error> {
+        Ok(self.state.lock(|cell| {
+            let mut state = cell.get();
+            let events = state.events;
+
+            state.events = EnumSet::new();
+            cell.set(state);
+
+            events
+        }))
+    }
+
+    fn wait_events(&mut self) -> Self::WaitEventsFuture<'_> {
+        async move {
+            while self.state.lock(|cell| cell.get().events.is_empty()) {
+                self.events.wait().await;
+            }
+
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::join::join;
+    use embassy_futures::select::{select, Either};
+
+    use futures::executor::block_on;
+
+    use super::*;
+
+    const WIDTH: Duration = Duration::from_millis(1);
+
+    fn pulses(mock: &MockCounter, count: usize, forward: bool) {
+        for _ in 0..count {
+            assert!(mock.pulse(WIDTH, forward));
+        }
+    }
+
+    #[test]
+    fn accumulates_overflows() {
+        let mock = MockCounter::new();
+        let wakeup = PcntWakeup::new();
+
+        let mut counter = PcntPulseCounter::new(
+            &mock,
+            &wakeup,
+            PcntConfig {
+                limit: 10,
+                ..PcntConfig::new()
+            },
+        )
+        .unwrap();
+
+        pulses(&mock, 12, true);
+        assert_eq!(block_on(counter.take_pulses()), Ok(12));
+
+        // Wraps to zero from a count of 2
+        pulses(&mock, 9, true);
+        assert_eq!(block_on(counter.take_pulses()), Ok(9));
+
+        pulses(&mock, 3, false);
+        assert_eq!(block_on(counter.take_pulses()), Ok(-3));
+
+        // Wraps to zero from a count of -2
+        pulses(&mock, 9, false);
+        assert_eq!(block_on(counter.take_pulses()), Ok(-9));
+    }
+
+    #[test]
+    fn filters_glitches() {
+        let mock = MockCounter::new();
+        let wakeup = PcntWakeup::new();
+
+        let mut counter = PcntPulseCounter::new(&mock, &wakeup, PcntConfig::new()).unwrap();
+
+        assert!(!mock.pulse(Duration::from_micros(5), true));
+        assert!(mock.pulse(Duration::from_micros(20), true));
+        assert!(!mock.pulse(Duration::from_micros(9), false));
+
+        assert_eq!(block_on(counter.take_pulses()), Ok(1));
+    }
+
+    #[test]
+    fn wakes_up_on_threshold() {
+        let mock = MockCounter::new();
+        let wakeup = PcntWakeup::new();
+
+        // Only the events can end the wait
+        let mut counter = PcntPulseCounter::new(
+            &mock,
+            &wakeup,
+            PcntConfig {
+                poll_interval: Duration::from_secs(60 * 60),
+                ..PcntConfig::new()
+            },
+        )
+        .unwrap();
+
+        let pulse = || async {
+            Timer::after(Duration::from_millis(10)).await;
+            mock.pulse(WIDTH, false);
+        };
+
+        // Without the wakeup there are no thresholds, so the pulse raises no event
+        let result = block_on(select(
+            counter.take_pulses(),
+            join(pulse(), Timer::after(Duration::from_millis(100))),
+        ));
+
+        assert!(matches!(result, Either::Second(_)));
+        assert_eq!(block_on(counter.take_pulses()), Ok(-1));
+
+        (&wakeup).set_enabled(true).unwrap();
+
+        let (result, _) = block_on(join(counter.take_pulses(), pulse()));
+
+        assert_eq!(result, Ok(-1));
+    }
+}
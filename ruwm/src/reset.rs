@@ -0,0 +1,30 @@
+use log::warn;
+
+use crate::budget::{self, BudgetConfig};
+use crate::clock::{self, TimeZone};
+use crate::i18n::{self, Language};
+use crate::schedule::{self, ScheduleState};
+use crate::settings::{self, Settings};
+use crate::wifi::{self, WifiCommand};
+
+/// Restores the settings to their defaults and starts the Wi-Fi provisioning.
+///
+/// The water meter readings and statistics are kept, as they describe the installation
+/// rather than its configuration. The defaults are persisted by the usual persist tasks.
+pub fn factory_reset() {
+    warn!("Factory reset");
+
+    budget::COMMAND.signal(BudgetConfig::new());
+
+    schedule::STATE.update_with(|state| ScheduleState {
+        enabled: false,
+        rules: heapless::Vec::new(),
+        ..state
+    });
+
+    clock::TIME_ZONE.update(TimeZone::UTC);
+    i18n::LANGUAGE.update(Language::new());
+    settings::STATE.update(Settings::new());
+
+    wifi::COMMAND.signal(WifiCommand::Reprovision);
+}
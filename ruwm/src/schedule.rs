@@ -0,0 +1,452 @@
+use core::cell::RefCell;
+
+use log::{info, warn};
+
+use serde::Deserialize;
+
+use embassy_time::{Duration, Timer};
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, RawMutex};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::signal::Signal;
+
+use channel_bridge::notification::Notification;
+
+use crate::clock::{self, SECS_PER_DAY};
+use crate::state::State;
+use crate::storage::{self, Storage, Versioned};
+use crate::valve::{self, ValveCommand, ValveId, ValveSet};
+use crate::wm::{self, WaterMeterCommand};
+use crate::{emergency, leak_sensor};
+
+pub use crate::dto::schedule::*;
+
+const STORAGE_KEY: &str = "schedule";
+
+pub static STATE: State<ScheduleState> = State::new(
+    "SCHEDULE",
+    ScheduleState::new(),
+    &[
+        &crate::keepalive::NOTIF,
+        &crate::web::SCHEDULE_STATE_NOTIF,
+        &STATE_PERSIST_NOTIFY,
+    ],
+);
+
+pub static NEXT_ACTION: State<Option<ScheduledAction>> = State::new(
+    "NEXT SCHEDULED ACTION",
+    None,
+    &[&crate::screen::SCHEDULE_STATE_NOTIF],
+);
+
+pub(crate) static CLOCK_STATE_NOTIF: Notification = Notification::new();
+
+static STATE_PERSIST_NOTIFY: Notification = Notification::new();
+
+pub(crate) static COMMAND: Signal<CriticalSectionRawMutex, ScheduleCommand> = Signal::new();
+
+pub async fn process() {
+    loop {
+        if let Either::First(command) = select(
+            COMMAND.wait(),
+            select(
+                CLOCK_STATE_NOTIF.wait(),
+                Timer::after(Duration::from_secs(30)),
+            ),
+        )
+        .await
+        {
+            let local_now = clock::now().map(|now| clock::TIME_ZONE.get().to_local(now));
+
+            STATE.update_with(|state| apply(state, command, local_now));
+        }
+
+        // Rules are in local time, so nothing can be scheduled until the wall clock is known
+        if let Some(now) = clock::now() {
+            let local_now = clock::TIME_ZONE.get().to_local(now);
+
+            enforce(local_now);
+
+            NEXT_ACTION.update(STATE.get().next_action(local_now));
+        }
+    }
+}
+
+fn apply(
+    mut state: ScheduleState,
+    command: ScheduleCommand,
+    local_now: Option<u64>,
+) -> ScheduleState {
+    match command {
+        ScheduleCommand::Enable(enabled) => state.enabled = enabled,
+        ScheduleCommand::SetRules(rules) => state.rules = rules,
+        ScheduleCommand::AddRule(rule) => {
+            if state.rules.push(user_rule(rule)).is_err() {
+                warn!("No room left for a rule");
+            }
+        }
+        ScheduleCommand::UpdateRule(index, rule) => {
+            if let Some(existing) = state.rules.get_mut(index as usize) {
+                *existing = user_rule(rule);
+            } else {
+                warn!("No rule {}", index);
+            }
+        }
+        ScheduleCommand::RemoveRule(index) => {
+            if (index as usize) < state.rules.len() {
+                state.rules.remove(index as usize);
+            } else {
+                warn!("No rule {}", index);
+            }
+        }
+        ScheduleCommand::Vacation(days) => {
+            state
+                .rules
+                .retain(|rule| rule.source != RuleSource::Vacation);
+
+            if days > 0 {
+                if let Some(local_now) = local_now {
+                    let vacation = ScheduleRule {
+                        window: ScheduleWindow::Once {
+                            start: local_now,
+                            end: local_now + days as u64 * SECS_PER_DAY,
+                        },
+                        action: ScheduleAction::CloseValve,
+                        source: RuleSource::Vacation,
+                    };
+
+                    if state.rules.push(vacation).is_ok() {
+                        state.enabled = true;
+                    } else {
+                        warn!("No room left for a vacation rule");
+                    }
+                } else {
+                    warn!("Clock not synchronized, cannot start a vacation");
+                }
+            }
+        }
+    }
+
+    state
+}
+
+/// Rules edited by the user are theirs, even if they edit a vacation
+fn user_rule(rule: ScheduleRule) -> ScheduleRule {
+    ScheduleRule {
+        source: RuleSource::User,
+        ..rule
+    }
+}
+
+fn enforce(local_now: u64) {
+    let state = STATE.get();
+
+    let close_valve = state.is_active(ScheduleAction::CloseValve, local_now);
+    let arm_meter = state.is_active(ScheduleAction::ArmMeter, local_now);
+
+    let mut closed_valves = state.closed_valves;
+
+    if close_valve != state.valve_closed {
+        let valves = switched_valves(
+            &state,
+            close_valve,
+            valve::configured(),
+            emergency::closed_valves(),
+        );
+
+        if close_valve {
+            info!("Schedule: closing the valves");
+
+            for valve in valve::configured().filter(|valve| valves.contains(*valve)) {
+                valve::command(Some(valve), ValveCommand::Close);
+            }
+
+            closed_valves = valves;
+        } else if wm::STATES.iter().any(|state| state.get().leaking)
+            || leak_sensor::wet_sensor().is_some()
+        {
+            // The emergency closing the valves might not have happened yet, e.g. after a wakeup
+            info!("Schedule: leak or flood detected, keeping the valves closed");
+
+            closed_valves = ValveSet::empty();
+        } else {
+            info!("Schedule: opening the valves");
+
+            for valve in valve::configured().filter(|valve| valves.contains(*valve)) {
+                valve::command(Some(valve), ValveCommand::Open);
+            }
+
+            closed_valves = ValveSet::empty();
+        }
+    }
+
+    if arm_meter != state.meter_armed {
+        info!("Schedule: arming the meter: {}", arm_meter);
+
+        wm::command(
+            None,
+            if arm_meter {
+                WaterMeterCommand::Arm
+            } else {
+                WaterMeterCommand::Disarm
+            },
+        );
+    }
+
+    STATE.update(ScheduleState {
+        valve_closed: close_valve,
+        closed_valves,
+        meter_armed: arm_meter,
+        ..state
+    });
+}
+
+/// The valves to close as the window starts, or to reopen as it ends. Those closed by
+/// an emergency are left alone, and only those closed by the schedule are reopened.
+fn switched_valves(
+    state: &ScheduleState,
+    close_valve: bool,
+    configured: impl Iterator<Item = ValveId>,
+    emergency_closed: ValveSet,
+) -> ValveSet {
+    configured
+        .filter(|valve| !emergency_closed.contains(*valve))
+        .filter(|valve| close_valve || state.closed_valves.contains(*valve))
+        .fold(ValveSet::empty(), ValveSet::with)
+}
+
+#[derive(Deserialize)]
+struct ScheduleRuleV1 {
+    window: ScheduleWindow,
+    action: ScheduleAction,
+}
+
+#[derive(Deserialize)]
+struct ScheduleStateV1 {
+    enabled: bool,
+    rules: heapless::Vec<ScheduleRuleV1, MAX_RULES>,
+    valve_closed: bool,
+    meter_armed: bool,
+}
+
+#[derive(Deserialize)]
+struct ScheduleStateV2 {
+    enabled: bool,
+    rules: heapless::Vec<ScheduleRule, MAX_RULES>,
+    valve_closed: bool,
+    meter_armed: bool,
+}
+
+impl From<ScheduleStateV2> for ScheduleState {
+    fn from(state: ScheduleStateV2) -> Self {
+        Self {
+            enabled: state.enabled,
+            rules: state.rules,
+            valve_closed: state.valve_closed,
+            // All valves were closed by the schedule back then
+            closed_valves: if state.valve_closed {
+                ValveSet::all()
+            } else {
+                ValveSet::empty()
+            },
+            meter_armed: state.meter_armed,
+        }
+    }
+}
+
+impl From<ScheduleStateV1> for ScheduleStateV2 {
+    fn from(state: ScheduleStateV1) -> Self {
+        Self {
+            enabled: state.enabled,
+            // Vacations could not be told apart from the rules of the user, so keep them all
+            rules: state
+                .rules
+                .into_iter()
+                .map(|rule| ScheduleRule {
+                    window: rule.window,
+                    action: rule.action,
+                    source: RuleSource::User,
+                })
+                .collect(),
+            valve_closed: state.valve_closed,
+            meter_armed: state.meter_armed,
+        }
+    }
+}
+
+impl Versioned for ScheduleState {
+    const VERSION: u8 = 3;
+
+    fn migrate(version: u8, payload: &[u8]) -> Result<Option<Self>, postcard::Error> {
+        match version {
+            1 => storage::migrate_from::<ScheduleStateV1, ScheduleStateV2>(payload)
+                .map(|state| state.map(Into::into)),
+            2 => storage::migrate_from::<ScheduleStateV2, _>(payload),
+            _ => Ok(None),
+        }
+    }
+}
+
+pub fn restore(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>) {
+    if let Some(state) = storage::restore(storage, STORAGE_KEY) {
+        STATE.set(state);
+    }
+}
+
+pub async fn persist(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>) {
+    loop {
+        STATE_PERSIST_NOTIFY.wait().await;
+
+        storage::persist(storage, STORAGE_KEY, &STATE.get());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::string::ToString;
+
+    use crate::storage::testing::MemoryStorage;
+
+    use super::*;
+
+    const NIGHTS: ScheduleRule = ScheduleRule {
+        window: ScheduleWindow::Weekly {
+            weekdays: 31,
+            start: 22 * 60,
+            end: 6 * 60,
+        },
+        action: ScheduleAction::CloseValve,
+        source: RuleSource::User,
+    };
+
+    const TRIP: ScheduleRule = ScheduleRule {
+        window: ScheduleWindow::Once {
+            start: 1_700_000_000,
+            end: 1_700_086_400,
+        },
+        action: ScheduleAction::CloseValve,
+        source: RuleSource::User,
+    };
+
+    #[test]
+    fn migrates_v1() {
+        let storage =
+            MemoryStorage::with(STORAGE_KEY, include_bytes!("../fixtures/schedule-v1.bin"));
+
+        let state = storage::load::<_, ScheduleState>(&storage, STORAGE_KEY)
+            .unwrap()
+            .unwrap();
+
+        assert!(state.enabled);
+        assert_eq!(state.rules, [NIGHTS, TRIP]);
+        assert!(state.valve_closed);
+        assert_eq!(state.closed_valves, ValveSet::all());
+        assert!(!state.meter_armed);
+    }
+
+    #[test]
+    fn migrates_v2() {
+        let storage =
+            MemoryStorage::with(STORAGE_KEY, include_bytes!("../fixtures/schedule-v2.bin"));
+
+        let state = storage::load::<_, ScheduleState>(&storage, STORAGE_KEY)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            state.rules,
+            [
+                NIGHTS,
+                ScheduleRule {
+                    source: RuleSource::Vacation,
+                    ..TRIP
+                }
+            ]
+        );
+        assert!(state.valve_closed);
+        assert_eq!(state.closed_valves, ValveSet::all());
+    }
+
+    #[test]
+    fn keeps_emergency_closes() {
+        let configured = || IntoIterator::into_iter([0, 1, 3]);
+
+        // The budget closed the first valve before the window started
+        let budget = ValveSet::empty().with(0);
+
+        let closing = switched_valves(&ScheduleState::new(), true, configured(), budget);
+
+        assert_eq!(closing, ValveSet::empty().with(1).with(3));
+
+        let state = ScheduleState {
+            valve_closed: true,
+            closed_valves: closing,
+            ..ScheduleState::new()
+        };
+
+        assert_eq!(
+            switched_valves(&state, false, configured(), budget),
+            closing
+        );
+
+        // A flood closed the last valve within the window
+        let flood = budget.with(3);
+
+        assert_eq!(
+            switched_valves(&state, false, configured(), flood),
+            ValveSet::empty().with(1)
+        );
+    }
+
+    #[test]
+    fn vacation_keeps_rules_of_user() {
+        let now = 1_700_100_000;
+
+        let state = apply(
+            ScheduleState::new(),
+            ScheduleCommand::AddRule(TRIP),
+            Some(now),
+        );
+        let state = apply(state, ScheduleCommand::Vacation(3), Some(now));
+
+        assert_eq!(state.rules.len(), 2);
+        assert_eq!(state.rules[1].source, RuleSource::Vacation);
+
+        let state = apply(state, ScheduleCommand::Vacation(0), Some(now));
+
+        assert_eq!(state.rules, [TRIP]);
+    }
+
+    #[test]
+    fn edits_rules() {
+        let state = apply(ScheduleState::new(), ScheduleCommand::AddRule(NIGHTS), None);
+        let state = apply(state, ScheduleCommand::AddRule(NIGHTS), None);
+        let state = apply(state, ScheduleCommand::UpdateRule(1, TRIP), None);
+
+        assert_eq!(state.rules, [NIGHTS, TRIP]);
+
+        let state = apply(state, ScheduleCommand::RemoveRule(0), None);
+        let state = apply(state, ScheduleCommand::RemoveRule(5), None);
+
+        assert_eq!(state.rules, [TRIP]);
+    }
+
+    #[test]
+    fn parses_rules() {
+        assert_eq!("close weekly 31 22:00-06:00".parse(), Ok(NIGHTS));
+        assert_eq!("close once 1700000000-1700086400".parse(), Ok(TRIP));
+
+        assert_eq!(NIGHTS.to_string(), "close weekly 31 22:00-06:00");
+        assert_eq!(TRIP.to_string(), "close once 1700000000-1700086400");
+
+        assert!("close weekly 128 22:00-06:00"
+            .parse::<ScheduleRule>()
+            .is_err());
+        assert!("close weekly 31 24:00-06:00"
+            .parse::<ScheduleRule>()
+            .is_err());
+        assert!("arm once 5-5".parse::<ScheduleRule>().is_err());
+        assert!("open once 1-2".parse::<ScheduleRule>().is_err());
+    }
+}
@@ -1,5 +1,7 @@
 use core::cell::RefCell;
+use core::convert::Infallible;
 use core::fmt::Debug;
+use core::future::pending;
 
 use serde::{Deserialize, Serialize};
 
@@ -7,9 +9,11 @@ use log::trace;
 
 use enumset::{enum_set, EnumSet, EnumSetType};
 
-use embassy_futures::select::select_array;
+use embassy_futures::select::{select, select3, select_array, Either, Either3};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
 
 use gfx_xtra::draw_target::Flushable;
 
@@ -17,15 +21,26 @@ use embedded_svc::executor::asynch::Unblocker;
 
 use channel_bridge::notification::Notification;
 
-use crate::battery::{self, BatteryState};
+use crate::alert::{self, Alert, AlertCommand};
+use crate::battery::{self, BatteryState, VoltageHistory};
+use crate::budget::{self, BudgetState};
+use crate::button::{self, Button, Gesture};
 use crate::keepalive::{self, RemainingTime};
+use crate::mqtt::{self, MqttState};
+use crate::pairing::{self, PairingCode};
+use crate::reset;
+use crate::schedule::{self, ScheduledAction};
 use crate::screen::shapes::util::clear;
-use crate::valve::{self, ValveState};
-use crate::wm::{self, WaterMeterState};
+use crate::settings;
+use crate::update::{self, UpdateState};
+use crate::valve::{self, ValveCommand, ValveState, MAIN_VALVE};
+use crate::wifi::{self, WifiState};
+use crate::wm::{self, MeterId, WaterMeterState};
+use crate::wm_stats::{self, WaterMeterStatsState};
 
 pub use shapes::Color;
 
-use self::pages::{Battery, Summary};
+use self::pages::{Advanced, Battery, History, Network, Settings, Stats, Summary};
 use self::shapes::Action;
 
 mod pages;
@@ -34,7 +49,12 @@ mod shapes;
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 enum Page {
     Summary = 0,
-    Battery = 1,
+    Stats = 1,
+    History = 2,
+    Network = 3,
+    Settings = 4,
+    Battery = 5,
+    Advanced = 6,
 }
 
 impl Page {
@@ -45,13 +65,23 @@ impl Page {
     pub fn prev(&self) -> Self {
         match self {
             Self::Summary => Self::Battery,
-            Self::Battery => Self::Summary,
+            Self::Stats => Self::Summary,
+            Self::History => Self::Stats,
+            Self::Network => Self::History,
+            Self::Settings => Self::Network,
+            Self::Advanced => Self::Settings,
+            Self::Battery => Self::Advanced,
         }
     }
 
     pub fn next(&self) -> Self {
         match self {
-            Self::Summary => Self::Battery,
+            Self::Summary => Self::Stats,
+            Self::Stats => Self::History,
+            Self::History => Self::Network,
+            Self::Network => Self::Settings,
+            Self::Settings => Self::Advanced,
+            Self::Advanced => Self::Battery,
             Self::Battery => Self::Summary,
         }
     }
@@ -59,7 +89,29 @@ impl Page {
     pub fn actions(&self) -> EnumSet<Action> {
         let actions = match self {
             Self::Summary => Action::OpenValve | Action::CloseValve | Action::Arm | Action::Disarm,
-            Self::Battery => EnumSet::empty(),
+            Self::Settings => {
+                Action::CheckForUpdate
+                    | Action::Update
+                    | Action::Pair
+                    | Action::Provision
+                    | Action::Reprovision
+                    | Action::ChangeLanguage
+                    | Action::EditBudget
+            }
+            Self::Advanced => {
+                Action::EditLeakThreshold
+                    | Action::EditFlashWriteCycle
+                    | Action::EditKeepaliveTimeout
+                    | Action::EditBatteryChemistry
+                    | Action::EditBatteryDividerRatio
+                    | Action::EditBatteryLowVoltage
+                    | Action::EditBatteryCriticalVoltage
+                    | Action::EditBatteryMaxVoltage
+                    | Action::EditBatteryHysteresis
+                    | Action::EditValveTurnTicks
+                    | Action::EditOutageValveClose
+            }
+            Self::Stats | Self::History | Self::Network | Self::Battery => EnumSet::empty(),
         };
 
         let mut actions = actions.intersection(Action::active());
@@ -78,21 +130,115 @@ impl Default for Page {
     }
 }
 
+/// Controls the brightness of the display backlight
+pub trait Backlight {
+    type Error: Debug;
+
+    /// Sets the brightness in percents, with 0 turning the backlight off
+    fn set_brightness(&mut self, percentage: u8) -> Result<(), Self::Error>;
+}
+
+impl<T> Backlight for &mut T
+where
+    T: Backlight,
+{
+    type Error = T::Error;
+
+    fn set_brightness(&mut self, percentage: u8) -> Result<(), Self::Error> {
+        (*self).set_brightness(percentage)
+    }
+}
+
+impl Backlight for () {
+    type Error = Infallible;
+
+    fn set_brightness(&mut self, _percentage: u8) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// When to dim and blank the screen. Both timeouts are counted since the last button press or alert
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PowerConfig {
+    pub dim_timeout: Duration,
+    pub off_timeout: Duration,
+    pub dimmed_brightness: u8,
+}
+
+impl PowerConfig {
+    pub const fn new() -> Self {
+        Self {
+            dim_timeout: Duration::from_secs(20),
+            off_timeout: Duration::from_secs(60),
+            dimmed_brightness: 20,
+        }
+    }
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ScreenPower {
+    On,
+    Dimmed,
+    Off,
+}
+
+impl ScreenPower {
+    pub const fn new() -> Self {
+        Self::On
+    }
+
+    pub fn brightness(&self, config: &PowerConfig) -> u8 {
+        match self {
+            Self::On => 100,
+            Self::Dimmed => config.dimmed_brightness,
+            Self::Off => 0,
+        }
+    }
+}
+
+impl Default for ScreenPower {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Debug, EnumSetType)]
 pub enum DataSource {
     Page,
+    Power,
     Valve,
     WM,
     WMStats,
     Battery,
     RemainingTime,
+    Schedule,
+    Budget,
+    Wifi,
+    Mqtt,
+    Update,
+    Pairing,
+    Alert,
+    Settings,
 }
 
 #[derive(Default, Clone, Debug, Eq, PartialEq)]
 pub struct ScreenState {
     changeset: EnumSet<DataSource>,
     active_page: Page,
+    /// The meter shown by the Summary, Stats and History pages
+    meter: MeterId,
     page_actions: Option<(EnumSet<Action>, Action)>,
+    power: ScreenPower,
+    alert: Option<Alert>,
+    blink: bool,
+    /// The value being edited with the action which started editing it, not yet saved
+    editing: Option<(Action, u64)>,
 }
 
 impl ScreenState {
@@ -100,25 +246,44 @@ impl ScreenState {
         Self {
             changeset: enum_set!(
                 DataSource::Page
+                    | DataSource::Power
                     | DataSource::Valve
                     | DataSource::WM
                     | DataSource::WMStats
                     | DataSource::Battery
                     | DataSource::RemainingTime
+                    | DataSource::Schedule
+                    | DataSource::Budget
+                    | DataSource::Wifi
+                    | DataSource::Mqtt
+                    | DataSource::Update
+                    | DataSource::Pairing
+                    | DataSource::Alert
+                    | DataSource::Settings
             ),
             active_page: Page::new(),
+            meter: 0,
             page_actions: None,
+            power: ScreenPower::new(),
+            alert: None,
+            blink: false,
+            editing: None,
         }
     }
 
     pub fn valve(&self) -> Option<Option<ValveState>> {
         self.changed([DataSource::Valve, DataSource::Page])
-            .then(|| valve::STATE.get())
+            .then(|| valve::STATES[MAIN_VALVE as usize].get())
     }
 
     pub fn wm(&self) -> Option<WaterMeterState> {
         self.changed([DataSource::WM, DataSource::Page])
-            .then(|| wm::STATE.get())
+            .then(|| wm::STATES[self.meter as usize].get())
+    }
+
+    pub fn wm_stats(&self) -> Option<WaterMeterStatsState> {
+        self.changed([DataSource::WMStats, DataSource::Page])
+            .then(|| wm_stats::STATES[self.meter as usize].get())
     }
 
     pub fn battery(&self) -> Option<BatteryState> {
@@ -126,11 +291,93 @@ impl ScreenState {
             .then(|| battery::STATE.get())
     }
 
+    pub fn battery_history(&self) -> Option<VoltageHistory> {
+        self.changed([DataSource::Battery, DataSource::Page])
+            .then(|| battery::HISTORY.get())
+    }
+
     pub fn remaining_time(&self) -> Option<RemainingTime> {
         self.changed([DataSource::RemainingTime, DataSource::Page])
             .then(|| keepalive::STATE.get())
     }
 
+    // The next action and the budget share one status line, so both are redrawn together
+
+    pub fn next_action(&self) -> Option<Option<ScheduledAction>> {
+        self.changed([DataSource::Schedule, DataSource::Budget, DataSource::Page])
+            .then(|| schedule::NEXT_ACTION.get())
+    }
+
+    pub fn budget(&self) -> Option<BudgetState> {
+        self.changed([DataSource::Schedule, DataSource::Budget, DataSource::Page])
+            .then(|| budget::STATE.get())
+    }
+
+    // The Network page lists both, so they are redrawn together
+
+    pub fn wifi(&self) -> Option<WifiState> {
+        self.changed([DataSource::Wifi, DataSource::Mqtt, DataSource::Page])
+            .then(|| wifi::STATE.get())
+    }
+
+    pub fn mqtt(&self) -> Option<MqttState> {
+        self.changed([DataSource::Wifi, DataSource::Mqtt, DataSource::Page])
+            .then(|| mqtt::STATE.get())
+    }
+
+    pub fn update(&self) -> Option<UpdateState> {
+        self.changed([DataSource::Update, DataSource::Page])
+            .then(|| update::STATE.get())
+    }
+
+    pub fn pairing(&self) -> Option<Option<PairingCode>> {
+        self.changed([DataSource::Pairing, DataSource::Page])
+            .then(|| pairing::STATE.get())
+    }
+
+    pub fn settings(&self) -> Option<settings::Settings> {
+        self.changed([DataSource::Settings, DataSource::Page])
+            .then(|| settings::STATE.get())
+    }
+
+    /// The meter, if the device counts more than one
+    pub fn named_meter(&self) -> Option<MeterId> {
+        (wm::meters() > 1).then(|| self.meter)
+    }
+
+    // The Summary, Stats and History pages are shown once per meter
+
+    fn prev_page(&mut self) {
+        match self.active_page {
+            Page::Summary if wm::configured().any(|meter| meter < self.meter) => {
+                self.meter = wm::configured()
+                    .filter(|meter| *meter < self.meter)
+                    .last()
+                    .unwrap();
+                self.active_page = Page::History;
+            }
+            Page::Network => {
+                self.meter = wm::configured().last().unwrap_or(0);
+                self.active_page = Page::History;
+            }
+            page => self.active_page = page.prev(),
+        }
+    }
+
+    fn next_page(&mut self) {
+        match self.active_page {
+            Page::History if wm::configured().any(|meter| meter > self.meter) => {
+                self.meter = wm::configured().find(|meter| *meter > self.meter).unwrap();
+                self.active_page = Page::Summary;
+            }
+            Page::Battery => {
+                self.meter = wm::configured().next().unwrap_or(0);
+                self.active_page = Page::Summary;
+            }
+            page => self.active_page = page.next(),
+        }
+    }
+
     fn changed<const N: usize>(&self, changes: [DataSource; N]) -> bool {
         changes
             .iter()
@@ -149,76 +396,271 @@ pub(crate) static BATTERY_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static MQTT_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static WIFI_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static REMAINING_TIME_NOTIF: Notification = Notification::new();
+pub(crate) static SCHEDULE_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static BUDGET_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static UPDATE_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static PAIRING_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static ALERTS_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static LANGUAGE_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static SETTINGS_STATE_NOTIF: Notification = Notification::new();
+
+pub(crate) static GESTURE: Signal<CriticalSectionRawMutex, Gesture> = Signal::new();
 
 static DRAW_REQUEST_NOTIF: Notification = Notification::new();
 
 static STATE: Mutex<CriticalSectionRawMutex, RefCell<ScreenState>> =
     Mutex::new(RefCell::new(ScreenState::new()));
 
-#[allow(clippy::too_many_arguments)]
-pub async fn process() {
+const BLINK_PERIOD: Duration = Duration::from_millis(500);
+
+pub async fn process(mut backlight: impl Backlight, config: PowerConfig) {
+    let mut last_activity = Instant::now();
+
+    backlight
+        .set_brightness(ScreenPower::On.brightness(&config))
+        .unwrap();
+
     loop {
-        let (_future, index) = select_array([
-            BUTTON1_PRESSED_NOTIF.wait(),
-            BUTTON2_PRESSED_NOTIF.wait(),
-            BUTTON3_PRESSED_NOTIF.wait(),
-            VALVE_STATE_NOTIF.wait(),
-            WM_STATE_NOTIF.wait(),
-            BATTERY_STATE_NOTIF.wait(),
-            REMAINING_TIME_NOTIF.wait(),
-        ])
-        .await;
+        let (power, shown_alert) = STATE.lock(|screen_state| {
+            let screen_state = screen_state.borrow();
+
+            (screen_state.power, screen_state.alert)
+        });
+
+        let timer = match power {
+            ScreenPower::On => {
+                futures::future::Either::Left(Timer::at(last_activity + config.dim_timeout))
+            }
+            ScreenPower::Dimmed => {
+                futures::future::Either::Left(Timer::at(last_activity + config.off_timeout))
+            }
+            ScreenPower::Off => futures::future::Either::Right(pending()),
+        };
+
+        let blink_timer = if shown_alert.is_some() {
+            futures::future::Either::Left(Timer::after(BLINK_PERIOD))
+        } else {
+            futures::future::Either::Right(pending())
+        };
+
+        let (index, blink, gesture) = match select(
+            select_array([
+                BUTTON1_PRESSED_NOTIF.wait(),
+                BUTTON2_PRESSED_NOTIF.wait(),
+                BUTTON3_PRESSED_NOTIF.wait(),
+                VALVE_STATE_NOTIF.wait(),
+                WM_STATE_NOTIF.wait(),
+                WM_STATS_STATE_NOTIF.wait(),
+                BATTERY_STATE_NOTIF.wait(),
+                REMAINING_TIME_NOTIF.wait(),
+                SCHEDULE_STATE_NOTIF.wait(),
+                BUDGET_STATE_NOTIF.wait(),
+                WIFI_STATE_NOTIF.wait(),
+                MQTT_STATE_NOTIF.wait(),
+                UPDATE_STATE_NOTIF.wait(),
+                PAIRING_STATE_NOTIF.wait(),
+                ALERTS_STATE_NOTIF.wait(),
+                LANGUAGE_STATE_NOTIF.wait(),
+                SETTINGS_STATE_NOTIF.wait(),
+            ]),
+            select3(timer, blink_timer, GESTURE.wait()),
+        )
+        .await
+        {
+            Either::First((_, index)) => (Some(index), false, None),
+            Either::Second(Either3::First(_)) => (None, false, None),
+            Either::Second(Either3::Second(_)) => (None, true, None),
+            Either::Second(Either3::Third(gesture)) => (None, false, Some(gesture)),
+        };
+
+        let now = Instant::now();
+
+        // Taken on every iteration, so that steps rolled while not editing are not carried over
+        let rolled_steps = button::take_rolled_steps();
+
+        let pressed = matches!(index, Some(0..=2));
+
+        let top_alert = alert::STATE.get().top().copied();
+
+        // An unacknowledged alert keeps the screen on
+        if pressed || gesture.is_some() || top_alert.is_some() {
+            last_activity = now;
+        }
+
+        let new_power = if now >= last_activity + config.off_timeout {
+            ScreenPower::Off
+        } else if now >= last_activity + config.dim_timeout {
+            ScreenPower::Dimmed
+        } else {
+            ScreenPower::On
+        };
+
+        if new_power != power {
+            backlight
+                .set_brightness(new_power.brightness(&config))
+                .unwrap();
+        }
+
+        // A press or a gesture while the screen is dimmed or blank only wakes it up
+        let index = index.filter(|_| !pressed || power == ScreenPower::On);
+        let gesture = gesture.filter(|_| power == ScreenPower::On);
 
         {
             STATE.lock(|screen_state| {
                 let mut screen_state = screen_state.borrow_mut();
 
-                match index {
-                    0 => {
-                        if let Some((actions, action)) = screen_state.page_actions {
-                            screen_state.page_actions =
-                                action.prev(&actions).map(|action| (actions, action));
-                        } else {
-                            screen_state.active_page = screen_state.active_page.prev();
-                        }
-
+                if new_power != screen_state.power {
+                    if screen_state.power == ScreenPower::Off {
                         screen_state.changeset.insert(DataSource::Page);
                     }
-                    1 => {
-                        if let Some((actions, action)) = screen_state.page_actions {
-                            screen_state.page_actions =
-                                action.next(&actions).map(|action| (actions, action));
-                        } else {
-                            screen_state.active_page = screen_state.active_page.next();
-                        }
 
-                        screen_state.changeset.insert(DataSource::Page);
-                    }
-                    2 => {
-                        if let Some((_, action)) = screen_state.page_actions {
+                    screen_state.power = new_power;
+                    screen_state.changeset.insert(DataSource::Power);
+                }
+
+                if top_alert != screen_state.alert {
+                    // Erase the previous alert, or the page under the overlay
+                    screen_state.alert = top_alert;
+                    screen_state.blink = false;
+                    screen_state.changeset.insert(DataSource::Page);
+                } else if blink {
+                    screen_state.blink = !screen_state.blink;
+                    screen_state.changeset.insert(DataSource::Alert);
+                }
+
+                // Holding button3 closes the valve, holding it even longer resets the settings.
+                // Pressing button1 and button2 together returns to the Summary page.
+                if let Some(gesture) = gesture {
+                    match gesture {
+                        Gesture::Hold(Button::Button3) => {
                             screen_state.page_actions = None;
-                            action.trigger();
-                        } else {
-                            let actions = screen_state.active_page.actions();
-                            screen_state.page_actions =
-                                Action::first(&actions).map(|action| (actions, action));
+                            valve::command(None, ValveCommand::Close);
                         }
-
-                        screen_state.changeset.insert(DataSource::Page);
-                    }
-                    3 => {
-                        screen_state.changeset.insert(DataSource::Valve);
-                    }
-                    4 => {
-                        screen_state.changeset.insert(DataSource::WM);
-                    }
-                    5 => {
-                        screen_state.changeset.insert(DataSource::Battery);
+                        Gesture::LongHold(Button::Button3) => reset::factory_reset(),
+                        Gesture::Combo => {
+                            screen_state.page_actions = None;
+                            screen_state.editing = None;
+                            screen_state.active_page = Page::Summary;
+                            screen_state.meter = wm::configured().next().unwrap_or(0);
+                        }
+                        _ => (),
                     }
-                    6 => {
-                        screen_state.changeset.insert(DataSource::RemainingTime);
+
+                    screen_state.changeset.insert(DataSource::Page);
+                }
+
+                if let Some(index) = index {
+                    match index {
+                        // While an alert is shown, only its acknowledgement is possible
+                        0 | 1 if screen_state.alert.is_some() => (),
+                        2 if screen_state.alert.is_some() => alert::COMMAND
+                            .signal(AlertCommand::Acknowledge(screen_state.alert.unwrap().kind)),
+                        // While editing, button1 and button2 (or the roller) change the value,
+                        // and button3 saves it
+                        0 | 1 if screen_state.editing.is_some() => {
+                            let steps = rolled_steps.max(1) as u64;
+
+                            screen_state.editing = screen_state.editing.map(|(action, value)| {
+                                let (min, max) = action.edited_range();
+
+                                let value = if index == 0 {
+                                    value.saturating_sub(steps)
+                                } else {
+                                    value.saturating_add(steps)
+                                };
+
+                                (action, value.clamp(min, max))
+                            });
+                        }
+                        2 if screen_state.editing.is_some() => {
+                            if let Some((action, value)) = screen_state.editing.take() {
+                                action.save(value);
+                            }
+
+                            screen_state.changeset.insert(DataSource::Page);
+                        }
+                        0 => {
+                            if let Some((actions, action)) = screen_state.page_actions {
+                                screen_state.page_actions =
+                                    action.prev(&actions).map(|action| (actions, action));
+                            } else {
+                                screen_state.prev_page();
+                            }
+
+                            screen_state.changeset.insert(DataSource::Page);
+                        }
+                        1 => {
+                            if let Some((actions, action)) = screen_state.page_actions {
+                                screen_state.page_actions =
+                                    action.next(&actions).map(|action| (actions, action));
+                            } else {
+                                screen_state.next_page();
+                            }
+
+                            screen_state.changeset.insert(DataSource::Page);
+                        }
+                        2 => {
+                            if let Some((_, action)) = screen_state.page_actions {
+                                screen_state.page_actions = None;
+
+                                if let Some(value) = action.edited_value() {
+                                    screen_state.editing = Some((action, value));
+                                } else {
+                                    action.trigger();
+                                }
+                            } else {
+                                let actions = screen_state.active_page.actions();
+                                screen_state.page_actions =
+                                    Action::first(&actions).map(|action| (actions, action));
+                            }
+
+                            screen_state.changeset.insert(DataSource::Page);
+                        }
+                        3 => {
+                            screen_state.changeset.insert(DataSource::Valve);
+                        }
+                        4 => {
+                            screen_state.changeset.insert(DataSource::WM);
+                        }
+                        5 => {
+                            screen_state.changeset.insert(DataSource::WMStats);
+                        }
+                        6 => {
+                            screen_state.changeset.insert(DataSource::Battery);
+                        }
+                        7 => {
+                            screen_state.changeset.insert(DataSource::RemainingTime);
+                        }
+                        8 => {
+                            screen_state.changeset.insert(DataSource::Schedule);
+                        }
+                        9 => {
+                            screen_state.changeset.insert(DataSource::Budget);
+                        }
+                        10 => {
+                            screen_state.changeset.insert(DataSource::Wifi);
+                        }
+                        11 => {
+                            screen_state.changeset.insert(DataSource::Mqtt);
+                        }
+                        12 => {
+                            screen_state.changeset.insert(DataSource::Update);
+                        }
+                        13 => {
+                            screen_state.changeset.insert(DataSource::Pairing);
+                        }
+                        14 => {
+                            screen_state.changeset.insert(DataSource::Alert);
+                        }
+                        15 => {
+                            // All texts change, so redraw everything
+                            screen_state.changeset.insert(DataSource::Page);
+                        }
+                        16 => {
+                            screen_state.changeset.insert(DataSource::Settings);
+                        }
+                        _ => unreachable!(),
                     }
-                    _ => unreachable!(),
                 }
             });
         }
@@ -274,6 +716,16 @@ where
 {
     trace!("DRAWING: {:?}", screen_state);
 
+    if screen_state.power == ScreenPower::Off {
+        // Blank the screen, as not all displays have a backlight which can be turned off
+        if screen_state.changeset.contains(DataSource::Power) {
+            clear(&display.bounding_box(), &mut display)?;
+            display.flush()?;
+        }
+
+        return Ok(display);
+    }
+
     let page_changed = screen_state.changeset.contains(DataSource::Page);
 
     if page_changed {
@@ -287,10 +739,45 @@ where
             screen_state.valve().as_ref(),
             screen_state.wm().as_ref(),
             screen_state.battery().as_ref(),
+            screen_state.wifi().as_ref(),
+            screen_state.mqtt().as_ref(),
             screen_state.remaining_time().as_ref(),
+            screen_state.next_action().as_ref(),
+            screen_state.budget().as_ref(),
         )?,
-        Page::Battery => {
-            Battery::draw(&mut display, page_changed, screen_state.battery().as_ref())?
+        Page::Stats => Stats::draw(
+            &mut display,
+            page_changed,
+            screen_state.named_meter(),
+            screen_state.wm_stats().as_ref(),
+        )?,
+        Page::History => History::draw(
+            &mut display,
+            page_changed,
+            screen_state.named_meter(),
+            screen_state.wm_stats().as_ref(),
+        )?,
+        Page::Network => Network::draw(
+            &mut display,
+            page_changed,
+            screen_state.wifi().as_ref(),
+            screen_state.mqtt().as_ref(),
+        )?,
+        Page::Settings => Settings::draw(
+            &mut display,
+            page_changed,
+            screen_state.update().as_ref(),
+            screen_state.pairing().as_ref(),
+            screen_state.wifi().as_ref(),
+        )?,
+        Page::Battery => Battery::draw(
+            &mut display,
+            page_changed,
+            screen_state.battery().as_ref(),
+            screen_state.battery_history().as_ref(),
+        )?,
+        Page::Advanced => {
+            Advanced::draw(&mut display, page_changed, screen_state.settings().as_ref())?
         }
     }
 
@@ -298,6 +785,15 @@ where
         pages::actions::draw(&mut display, actions, action)?;
     }
 
+    if let Some((action, value)) = screen_state.editing {
+        pages::editor::draw(&mut display, action.text(), &action.format_value(value))?;
+    }
+
+    // The page may have drawn over the overlay, so it is always redrawn
+    if let Some(alert) = screen_state.alert {
+        pages::alert::draw(&mut display, &alert, screen_state.blink)?;
+    }
+
     display.flush()?;
 
     Ok(display)
@@ -1,15 +1,25 @@
+pub use advanced::*;
 pub use battery::*;
 use embedded_graphics::{
     draw_target::Cropped,
     prelude::{DrawTarget, DrawTargetExt, Size},
     primitives::Rectangle,
 };
+pub use network::*;
+pub use settings::*;
+pub use stats::*;
 pub use summary::*;
 
 use super::{shapes::Textbox, Color};
 
 pub mod actions;
+mod advanced;
+pub mod alert;
 mod battery;
+pub mod editor;
+mod network;
+mod settings;
+mod stats;
 mod summary;
 
 pub fn with_title<'a, T>(
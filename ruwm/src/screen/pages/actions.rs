@@ -32,7 +32,13 @@ where
         ..Default::default()
     };
 
-    let actions_shape_size = Size::new(bbox.size.width - 10, actions_shape.preferred_size().height);
+    let actions_shape_size = Size::new(
+        bbox.size.width - 10,
+        actions_shape
+            .preferred_size()
+            .height
+            .min(bbox.size.height - 10),
+    );
 
     let mut target = target.cropped(&Rectangle::new(
         Point::new(
@@ -0,0 +1,77 @@
+use core::fmt::Write;
+
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::prelude::{DrawTargetExt, Point};
+use embedded_graphics::primitives::Rectangle;
+
+use crate::i18n::{self, Message};
+use crate::screen::shapes::{self, Color};
+use crate::settings::{Setting, Settings};
+
+use super::with_title;
+
+pub struct Advanced;
+
+impl Advanced {
+    pub fn draw<T>(
+        target: &mut T,
+        page_changed: bool,
+        settings: Option<&Settings>,
+    ) -> Result<(), T::Error>
+    where
+        T: DrawTarget<Color = Color>,
+    {
+        let mut target = with_title(target, page_changed, i18n::text(Message::Advanced))?;
+
+        if let Some(settings) = settings {
+            let bbox = target.bounding_box();
+
+            // All settings have to fit on the page
+            let font = if bbox.size.width <= 128 {
+                profont::PROFONT_7_POINT
+            } else {
+                profont::PROFONT_14_POINT
+            };
+
+            let chars = (bbox.size.width / font.character_size.width) as usize;
+
+            let line_height =
+                (font.character_size.height + 1).min(bbox.size.height / Setting::ALL.len() as u32);
+
+            for (index, setting) in Setting::ALL.iter().enumerate() {
+                let mut line = heapless::String::<64>::new();
+
+                write!(
+                    &mut line,
+                    "{:<12} {:>7}",
+                    i18n::text(setting.message()),
+                    setting.format(settings.get(*setting))
+                )
+                .unwrap();
+
+                // Pad with spaces to erase the longer text drawn previously
+                while line.chars().count() < chars && line.push(' ').is_ok() {}
+
+                let line = shapes::Textbox {
+                    text: &line,
+                    color: Color::White,
+                    font,
+                    padding: 1,
+                    outline: 0,
+                    strikethrough: false,
+                    ..Default::default()
+                };
+
+                line.draw(&mut target.cropped(&Rectangle::new(
+                    Point::new(
+                        bbox.top_left.x,
+                        bbox.top_left.y + (line_height * index as u32) as i32,
+                    ),
+                    line.preferred_size(),
+                )))?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,50 @@
+use embedded_graphics::{
+    prelude::{DrawTarget, DrawTargetExt, Point, Size},
+    primitives::Rectangle,
+};
+
+use crate::alert::Alert;
+use crate::leak_sensor;
+use crate::screen::{shapes::AlertBox, Color};
+
+pub fn draw<T>(target: &mut T, alert: &Alert, blink: bool) -> Result<(), T::Error>
+where
+    T: DrawTarget<Color = Color>,
+{
+    let bbox = target.bounding_box();
+
+    let Size { width, .. } = bbox.size;
+
+    let font = if width <= 128 {
+        profont::PROFONT_9_POINT
+    } else {
+        profont::PROFONT_18_POINT
+    };
+
+    let source = alert
+        .sensor
+        .and_then(leak_sensor::config)
+        .map(|config| config.name);
+
+    let alert_shape = AlertBox {
+        kind: alert.kind,
+        source,
+        inverted: blink,
+        font,
+        ..Default::default()
+    };
+
+    let alert_shape_size = Size::new(bbox.size.width - 10, alert_shape.preferred_size().height);
+
+    let mut target = target.cropped(&Rectangle::new(
+        Point::new(
+            (bbox.size.width as i32 - alert_shape_size.width as i32) / 2,
+            (bbox.size.height as i32 - alert_shape_size.height as i32) / 2,
+        ),
+        alert_shape_size,
+    ));
+
+    alert_shape.draw(&mut target)?;
+
+    Ok(())
+}
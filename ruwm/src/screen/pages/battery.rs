@@ -1,6 +1,13 @@
+use core::fmt::Write;
+
 use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::mono_font::MonoFont;
+use embedded_graphics::prelude::{DrawTargetExt, Point, Size};
+use embedded_graphics::primitives::Rectangle;
 
-use crate::battery::BatteryState;
+use crate::battery::{BatteryState, VoltageHistory};
+use crate::clock;
+use crate::i18n::{self, Message};
 use crate::screen::shapes::{self, Color};
 
 use super::with_title;
@@ -12,20 +19,119 @@ impl Battery {
         target: &mut T,
         page_changed: bool,
         state: Option<&BatteryState>,
+        history: Option<&VoltageHistory>,
     ) -> Result<(), T::Error>
     where
         T: DrawTarget<Color = Color>,
     {
-        let mut target = with_title(target, page_changed, "Battery")?;
+        let mut target = with_title(target, page_changed, i18n::text(Message::Battery))?;
 
         if let Some(state) = state {
+            let bbox = target.bounding_box();
+
+            let (font, max_days) = if bbox.size.width <= 128 {
+                (profont::PROFONT_9_POINT, 3)
+            } else {
+                (profont::PROFONT_14_POINT, 7)
+            };
+
+            let line_height = font.character_size.height + 2;
+
+            // The most recent days, below the battery and above the time to empty
+            let days = history
+                .map(|history| {
+                    let skip = history.days.len().saturating_sub(max_days);
+
+                    &history.days[skip..]
+                })
+                .unwrap_or(&[]);
+
+            let lines = days.len() as u32 + 1;
+
             shapes::Battery {
-                charged_percentage: state.percentage(),
+                charged_percentage: state.charge,
+                level: state.level,
                 ..Default::default()
             }
-            .draw(&mut target)?;
+            .draw(&mut target.cropped(&Rectangle::new(
+                bbox.top_left,
+                Size::new(bbox.size.width, bbox.size.height - line_height * lines),
+            )))?;
+
+            let language = i18n::LANGUAGE.get();
+
+            for (index, range) in days.iter().enumerate() {
+                let mut line = heapless::String::<64>::new();
+
+                write!(
+                    &mut line,
+                    "{} {}-{}mV",
+                    language.weekday(clock::weekday(range.day)),
+                    range.min,
+                    range.max
+                )
+                .unwrap();
+
+                draw_line(
+                    &mut target,
+                    &font,
+                    &line,
+                    bbox.top_left.y
+                        + (bbox.size.height - line_height * (lines - index as u32)) as i32,
+                )?;
+            }
+
+            let mut line = heapless::String::<64>::new();
+
+            if let Some(secs) = state.time_to_empty {
+                write!(
+                    &mut line,
+                    "{} {}h {:02}m",
+                    i18n::text(Message::TimeToEmpty),
+                    secs / 3600,
+                    secs / 60 % 60
+                )
+                .unwrap();
+            }
+
+            draw_line(
+                &mut target,
+                &font,
+                &line,
+                bbox.top_left.y + (bbox.size.height - line_height) as i32,
+            )?;
         }
 
         Ok(())
     }
 }
+
+fn draw_line<T>(target: &mut T, font: &MonoFont, text: &str, y: i32) -> Result<(), T::Error>
+where
+    T: DrawTarget<Color = Color>,
+{
+    let bbox = target.bounding_box();
+
+    let mut line = heapless::String::<64>::new();
+    line.push_str(text).unwrap();
+
+    let chars = (bbox.size.width / font.character_size.width) as usize;
+
+    // Pad with spaces to erase the longer text drawn previously
+    while line.chars().count() < chars && line.push(' ').is_ok() {}
+
+    let line = shapes::Textbox {
+        text: &line,
+        color: Color::White,
+        font: *font,
+        padding: 1,
+        outline: 0,
+        strikethrough: false,
+        ..Default::default()
+    };
+
+    line.draw(&mut target.cropped(&Rectangle::new(
+        Point::new(bbox.top_left.x, y),
+        line.preferred_size(),
+    )))
+}
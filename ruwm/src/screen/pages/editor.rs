@@ -0,0 +1,48 @@
+use core::fmt::Write;
+
+use embedded_graphics::{
+    prelude::{DrawTarget, DrawTargetExt, Point, Size},
+    primitives::Rectangle,
+};
+
+use crate::screen::{shapes::Editor, Color};
+
+pub fn draw<T>(target: &mut T, label: &str, value: &str) -> Result<(), T::Error>
+where
+    T: DrawTarget<Color = Color>,
+{
+    let bbox = target.bounding_box();
+
+    let Size { width, .. } = bbox.size;
+
+    let font = if width <= 128 {
+        profont::PROFONT_9_POINT
+    } else {
+        profont::PROFONT_18_POINT
+    };
+
+    let mut value_buf = heapless::String::<32>::new();
+
+    write!(&mut value_buf, "< {} >", value).unwrap();
+
+    let editor_shape = Editor {
+        label,
+        value: &value_buf,
+        font,
+        ..Default::default()
+    };
+
+    let editor_shape_size = Size::new(bbox.size.width - 10, editor_shape.preferred_size().height);
+
+    let mut target = target.cropped(&Rectangle::new(
+        Point::new(
+            (bbox.size.width as i32 - editor_shape_size.width as i32) / 2,
+            (bbox.size.height as i32 - editor_shape_size.height as i32) / 2,
+        ),
+        editor_shape_size,
+    ));
+
+    editor_shape.draw(&mut target)?;
+
+    Ok(())
+}
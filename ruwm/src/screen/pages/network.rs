@@ -0,0 +1,119 @@
+use core::fmt::Write;
+
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::prelude::{DrawTargetExt, Point};
+use embedded_graphics::primitives::Rectangle;
+
+use crate::clock;
+use crate::i18n::{self, Message};
+use crate::mqtt::MqttState;
+use crate::screen::shapes::{self, Color};
+use crate::wifi::WifiState;
+
+use super::with_title;
+
+pub struct Network;
+
+impl Network {
+    pub fn draw<T>(
+        target: &mut T,
+        page_changed: bool,
+        wifi_state: Option<&WifiState>,
+        mqtt_state: Option<&MqttState>,
+    ) -> Result<(), T::Error>
+    where
+        T: DrawTarget<Color = Color>,
+    {
+        let mut target = with_title(target, page_changed, i18n::text(Message::Network))?;
+
+        if let (Some(wifi_state), Some(mqtt_state)) = (wifi_state, mqtt_state) {
+            let bbox = target.bounding_box();
+
+            let font = if bbox.size.width <= 128 {
+                profont::PROFONT_9_POINT
+            } else {
+                profont::PROFONT_14_POINT
+            };
+
+            let chars = (bbox.size.width / font.character_size.width) as usize;
+
+            let mut lines: [heapless::String<64>; 5] = Default::default();
+
+            write!(&mut lines[0], "SSID {}", wifi_state.ssid).unwrap();
+
+            if let Some(ip) = wifi_state.ip {
+                write!(
+                    &mut lines[1],
+                    "IP   {}.{}.{}.{}",
+                    ip[0], ip[1], ip[2], ip[3]
+                )
+                .unwrap();
+            } else {
+                write!(&mut lines[1], "IP   -").unwrap();
+            }
+
+            if let Some(rssi) = wifi_state.rssi {
+                write!(&mut lines[2], "RSSI {} dBm", rssi).unwrap();
+            } else {
+                write!(&mut lines[2], "RSSI -").unwrap();
+            }
+
+            let broker = mqtt_state.broker.as_str();
+            let broker = broker.split("://").last().unwrap_or(broker);
+
+            let _ = write!(&mut lines[3], "MQTT {}", broker);
+
+            if let Some(last_publish_secs) = mqtt_state.last_publish_secs {
+                let time = clock::TIME_ZONE.get().local(last_publish_secs);
+
+                write!(
+                    &mut lines[4],
+                    "Pub  {:02}:{:02}:{:02}",
+                    time.hour, time.minute, time.second
+                )
+                .unwrap();
+            } else {
+                write!(&mut lines[4], "Pub  -").unwrap();
+            }
+
+            let mut y_offs = bbox.top_left.y;
+
+            for (index, line) in lines.iter_mut().enumerate() {
+                line.truncate(chars);
+
+                // Pad with spaces to erase the longer text drawn previously
+                while line.len() < chars && line.push(' ').is_ok() {}
+
+                let connected = match index {
+                    0..=2 => wifi_state.connected,
+                    _ => mqtt_state.connected,
+                };
+
+                let line = shapes::Textbox {
+                    text: line,
+                    color: if connected.unwrap_or(false) {
+                        Color::White
+                    } else {
+                        Color::Gray
+                    },
+                    font,
+                    padding: 1,
+                    outline: 0,
+                    strikethrough: false,
+                    ..Default::default()
+                };
+
+                let line_size = line.preferred_size();
+
+                line.draw(&mut target.cropped(&Rectangle::new(
+                    Point::new(bbox.top_left.x, y_offs),
+                    line_size,
+                )))?;
+
+                y_offs += line_size.height as i32;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+use core::fmt::Write;
+
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::prelude::{DrawTargetExt, Point};
+use embedded_graphics::primitives::Rectangle;
+
+use embassy_time::Instant;
+
+use crate::i18n::{self, Message};
+use crate::pairing::PairingCode;
+use crate::screen::shapes::{self, Color};
+use crate::update::UpdateState;
+use crate::wifi::{WifiState, PROVISIONING_SSID};
+
+use super::with_title;
+
+pub struct Settings;
+
+impl Settings {
+    pub fn draw<T>(
+        target: &mut T,
+        page_changed: bool,
+        update_state: Option<&UpdateState>,
+        pairing: Option<&Option<PairingCode>>,
+        wifi_state: Option<&WifiState>,
+    ) -> Result<(), T::Error>
+    where
+        T: DrawTarget<Color = Color>,
+    {
+        let mut target = with_title(target, page_changed, i18n::text(Message::Settings))?;
+
+        let bbox = target.bounding_box();
+
+        let font = if bbox.size.width <= 128 {
+            profont::PROFONT_9_POINT
+        } else {
+            profont::PROFONT_14_POINT
+        };
+
+        let chars = (bbox.size.width / font.character_size.width) as usize;
+
+        // Each line is only redrawn when the state it shows has changed
+        let mut lines: [Option<(heapless::String<64>, Color)>; 4] = Default::default();
+
+        if let Some(update_state) = update_state {
+            let (status, color) = match update_state {
+                UpdateState::Idle => ("-", Color::White),
+                UpdateState::Checking => ("checking", Color::White),
+                UpdateState::UpToDate => ("up to date", Color::White),
+                UpdateState::Available(_) => ("available", Color::Yellow),
+                UpdateState::Updating(_) => ("updating", Color::White),
+                UpdateState::Complete => ("on restart", Color::Green),
+                UpdateState::Failed => ("failed", Color::Red),
+            };
+
+            let mut line = heapless::String::new();
+            write!(&mut line, "Update {}", status).unwrap();
+
+            lines[0] = Some((line, color));
+
+            let mut line = heapless::String::new();
+
+            match update_state {
+                UpdateState::Available(version) => {
+                    let _ = write!(&mut line, "       {}", version);
+                }
+                UpdateState::Updating(percentage) => {
+                    // A text progress bar, followed by the percentage
+                    let width = chars.saturating_sub(6);
+                    let done = width * *percentage as usize / 100;
+
+                    for index in 0..width {
+                        let _ = line.push(if index < done { '#' } else { '.' });
+                    }
+
+                    let _ = write!(&mut line, " {:>3}%", percentage);
+                }
+                _ => (),
+            }
+
+            lines[1] = Some((line, Color::White));
+        }
+
+        if let Some(pairing) = pairing {
+            let mut line = heapless::String::new();
+
+            let color = match pairing.filter(|pairing| pairing.is_valid(Instant::now())) {
+                Some(pairing) => {
+                    write!(
+                        &mut line,
+                        "Pair   {:03} {:03}",
+                        pairing.code / 1000,
+                        pairing.code % 1000
+                    )
+                    .unwrap();
+
+                    Color::Green
+                }
+                None => {
+                    write!(&mut line, "Pair   -").unwrap();
+
+                    Color::White
+                }
+            };
+
+            lines[2] = Some((line, color));
+        }
+
+        if let Some(wifi_state) = wifi_state {
+            let mut line = heapless::String::new();
+
+            let color = if wifi_state.provisioning {
+                write!(&mut line, "AP     {}", PROVISIONING_SSID).unwrap();
+
+                Color::Green
+            } else {
+                write!(&mut line, "AP     -").unwrap();
+
+                Color::White
+            };
+
+            lines[3] = Some((line, color));
+        }
+
+        let line_height = font.character_size.height + 2;
+
+        for (index, line) in lines.iter_mut().enumerate() {
+            if let Some((line, color)) = line {
+                line.truncate(chars);
+
+                // Pad with spaces to erase the longer text drawn previously
+                while line.len() < chars && line.push(' ').is_ok() {}
+
+                let line = shapes::Textbox {
+                    text: line,
+                    color: *color,
+                    font,
+                    padding: 1,
+                    outline: 0,
+                    strikethrough: false,
+                    ..Default::default()
+                };
+
+                line.draw(&mut target.cropped(&Rectangle::new(
+                    Point::new(
+                        bbox.top_left.x,
+                        bbox.top_left.y + (line_height * index as u32) as i32,
+                    ),
+                    line.preferred_size(),
+                )))?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use core::fmt::Write;
+
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::prelude::{DrawTargetExt, Point};
+use embedded_graphics::primitives::Rectangle;
+
+use crate::i18n::{self, Message};
+use crate::screen::shapes::{self, Color};
+use crate::wm::MeterId;
+use crate::wm_stats::{WaterMeterStatsState, DURATIONS, FLOW_STATS_INSTANCES};
+
+use super::with_title;
+
+const LABELS: [&str; FLOW_STATS_INSTANCES] = ["5m", "30m", "1h", "6h", "12h", "1d", "7d", "30d"];
+
+pub struct Stats;
+
+impl Stats {
+    pub fn draw<T>(
+        target: &mut T,
+        page_changed: bool,
+        meter: Option<MeterId>,
+        state: Option<&WaterMeterStatsState>,
+    ) -> Result<(), T::Error>
+    where
+        T: DrawTarget<Color = Color>,
+    {
+        let mut target = with_title(target, page_changed, &title(Message::Stats, meter))?;
+
+        if let Some(state) = state {
+            let bbox = target.bounding_box();
+
+            let font = if bbox.size.width <= 128 {
+                profont::PROFONT_9_POINT
+            } else {
+                profont::PROFONT_14_POINT
+            };
+
+            let mut y_offs = bbox.top_left.y;
+
+            // Each line: the window, the consumption so far and the one of the previous window
+            for (index, label) in LABELS.iter().enumerate() {
+                let mut text_buf = heapless::String::<20>::new();
+
+                let current = state.consumption(DURATIONS[index]);
+                let previous = state.measurements[index].map(|measurement| {
+                    measurement
+                        .start()
+                        .statistics(measurement.end().edges_count)
+                });
+
+                write!(&mut text_buf, "{:>3}", label).unwrap();
+
+                for value in [current, previous] {
+                    if let Some(value) = value {
+                        write!(&mut text_buf, " {:>7}", value).unwrap();
+                    } else {
+                        write!(&mut text_buf, " {:>7}", "-").unwrap();
+                    }
+                }
+
+                let line = shapes::Textbox {
+                    text: &text_buf,
+                    color: Color::White,
+                    font,
+                    padding: 1,
+                    outline: 0,
+                    strikethrough: false,
+                    ..Default::default()
+                };
+
+                let line_size = line.preferred_size();
+
+                line.draw(&mut target.cropped(&Rectangle::new(
+                    Point::new(bbox.top_left.x, y_offs),
+                    line_size,
+                )))?;
+
+                y_offs += line_size.height as i32;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub struct History;
+
+impl History {
+    pub fn draw<T>(
+        target: &mut T,
+        page_changed: bool,
+        meter: Option<MeterId>,
+        state: Option<&WaterMeterStatsState>,
+    ) -> Result<(), T::Error>
+    where
+        T: DrawTarget<Color = Color>,
+    {
+        let mut target = with_title(target, page_changed, &title(Message::Last24h, meter))?;
+
+        if let Some(state) = state {
+            shapes::BarChart {
+                values: &state.history,
+                ..Default::default()
+            }
+            .draw(&mut target)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The meter is only named when the device counts more than one
+fn title(message: Message, meter: Option<MeterId>) -> heapless::String<32> {
+    let mut title = heapless::String::new();
+
+    write!(&mut title, "{}", i18n::text(message)).unwrap();
+
+    if let Some(meter) = meter {
+        write!(&mut title, " #{}", meter).unwrap();
+    }
+
+    title
+}
@@ -9,9 +9,15 @@ use embedded_graphics::{
 use gfx_xtra::draw_target::{DrawTargetExt2, RotateAngle};
 
 use crate::battery::BatteryState;
+use crate::budget::{BudgetLevel, BudgetState};
+use crate::clock::DateTime;
+use crate::i18n::{self, Message};
 use crate::keepalive::RemainingTime;
+use crate::mqtt::MqttState;
+use crate::schedule::ScheduledAction;
 use crate::screen::shapes::{self, BatteryChargedText, Color};
 use crate::valve::ValveState;
+use crate::wifi::WifiState;
 use crate::wm::WaterMeterState;
 
 pub struct Summary;
@@ -23,15 +29,24 @@ impl Summary {
         valve_state: Option<&Option<ValveState>>,
         wm_state: Option<&WaterMeterState>,
         battery_state: Option<&BatteryState>,
+        wifi_state: Option<&WifiState>,
+        mqtt_state: Option<&MqttState>,
         remaining_time_state: Option<&RemainingTime>,
+        next_action_state: Option<&Option<ScheduledAction>>,
+        budget_state: Option<&BudgetState>,
     ) -> Result<(), D::Error>
     where
         D: DrawTarget<Color = Color>,
     {
         let bbox = target.bounding_box();
 
-        let top_height = Self::draw_top_status_line(target, battery_state)?;
-        let bottom_height = Self::draw_bottom_status_line(target, remaining_time_state)?;
+        let top_height = Self::draw_top_status_line(target, battery_state, wifi_state, mqtt_state)?;
+        let bottom_height = Self::draw_bottom_status_line(
+            target,
+            remaining_time_state,
+            next_action_state,
+            budget_state,
+        )?;
 
         let content_rect = Rectangle::new(
             bbox.top_left + Size::new(0, top_height + 5),
@@ -46,6 +61,8 @@ impl Summary {
     fn draw_top_status_line<D>(
         target: &mut D,
         battery_state: Option<&BatteryState>,
+        wifi_state: Option<&WifiState>,
+        mqtt_state: Option<&MqttState>,
     ) -> Result<u32, D::Error>
     where
         D: DrawTarget<Color = Color>,
@@ -65,39 +82,46 @@ impl Summary {
         let y_offs = bbox.top_left.y;
 
         let status_wifi_size = Size::new(status_height * 3 / 4, status_height);
-        let status_wifi = shapes::Wifi {
-            padding: 1,
-            outline: 1,
-            strength: None, //Some(60),
-            ..Default::default()
-        };
+        if let Some(wifi_state) = wifi_state {
+            let status_wifi = shapes::Wifi {
+                padding: 1,
+                outline: 1,
+                strength: wifi_state.strength(),
+            };
 
-        status_wifi.draw(&mut target.cropped(&Rectangle::new(
-            Point::new(x_offs, y_offs),
-            status_wifi_size,
-        )))?;
+            status_wifi.draw(&mut target.cropped(&Rectangle::new(
+                Point::new(x_offs, y_offs),
+                status_wifi_size,
+            )))?;
+        }
 
         x_offs += (status_wifi_size.width + status_padding) as i32;
 
-        let status_mqtt = shapes::Textbox {
-            text: "MQTT",
-            font: status_font,
-            padding: 1,
-            outline: 0,
-            strikethrough: false,
-            ..Default::default()
-        };
+        if let Some(mqtt_state) = mqtt_state {
+            let connected = mqtt_state.connected.unwrap_or(false);
 
-        status_mqtt.draw(&mut target.cropped(&Rectangle::new(
-            Point::new(x_offs, y_offs),
-            status_mqtt.preferred_size(),
-        )))?;
+            let status_mqtt = shapes::Textbox {
+                text: "MQTT",
+                color: if connected { Color::Green } else { Color::Gray },
+                font: status_font,
+                padding: 1,
+                outline: if connected { 0 } else { 1 },
+                strikethrough: !connected,
+                ..Default::default()
+            };
+
+            status_mqtt.draw(&mut target.cropped(&Rectangle::new(
+                Point::new(x_offs, y_offs),
+                status_mqtt.preferred_size(),
+            )))?;
+        }
 
         //x_offs += (status_mqtt.preferred_size().width + status_padding) as i32;
 
         let status_battery_size = Size::new(status_height * 2, status_height);
         let status_battery = shapes::Battery {
-            charged_percentage: battery_state.and_then(|battery_state| battery_state.percentage()),
+            charged_percentage: battery_state.and_then(|battery_state| battery_state.charge),
+            level: battery_state.and_then(|battery_state| battery_state.level),
             text: BatteryChargedText::No,
             cathode: Size::new(status_height / 2, status_height / 4),
             padding: 1,
@@ -121,14 +145,22 @@ impl Summary {
 
         x_right_offs -= status_padding as i32;
 
+        let powered = i18n::text(Message::PoweredShort);
+
+        // Blank the label with as many spaces as it has characters
+        let mut blank = heapless::String::<8>::new();
+        for _ in powered.chars() {
+            let _ = blank.push(' ');
+        }
+
         let status_power = shapes::Textbox {
             text: if battery_state
                 .and_then(|battery_state| battery_state.powered)
                 .unwrap_or(false)
             {
-                "PWR"
+                powered
             } else {
-                "   "
+                &blank
             },
             color: Color::Green,
             font: status_font,
@@ -212,6 +244,8 @@ impl Summary {
     fn draw_bottom_status_line<D>(
         target: &mut D,
         remaining_time: Option<&RemainingTime>,
+        next_action: Option<&Option<ScheduledAction>>,
+        budget: Option<&BudgetState>,
     ) -> Result<u32, D::Error>
     where
         D: DrawTarget<Color = Color>,
@@ -226,6 +260,73 @@ impl Summary {
             (profont::PROFONT_14_POINT, 20, 2)
         };
 
+        if let (Some(next_action), Some(budget)) = (next_action, budget) {
+            let language = i18n::LANGUAGE.get();
+
+            let mut text_buf = heapless::String::<48>::new();
+
+            // Budget alerts take precedence over the next scheduled action
+            let color = match budget.level {
+                BudgetLevel::Warning => {
+                    write!(
+                        &mut text_buf,
+                        "{} {}%",
+                        Message::Budget.text(language),
+                        budget.percentage().unwrap_or(0)
+                    )
+                    .unwrap();
+
+                    Color::Yellow
+                }
+                BudgetLevel::Exceeded => {
+                    write!(&mut text_buf, "{}", Message::BudgetExceeded.text(language)).unwrap();
+
+                    Color::Red
+                }
+                BudgetLevel::Normal => {
+                    if let Some(next_action) = next_action {
+                        let time = DateTime::from_secs(next_action.time);
+
+                        write!(
+                            &mut text_buf,
+                            "{} {} {:02}:{:02}",
+                            next_action.message().text(language),
+                            language.weekday(time.weekday),
+                            time.hour,
+                            time.minute
+                        )
+                        .unwrap();
+                    }
+
+                    Color::Blue
+                }
+            };
+
+            // Pad with spaces to erase the longer text drawn previously
+            while text_buf.chars().count() < 16 && text_buf.push(' ').is_ok() {}
+
+            let status_info = shapes::Textbox {
+                text: &text_buf,
+                color,
+                font: status_font,
+                padding: 1,
+                outline: 0,
+                strikethrough: false,
+                ..Default::default()
+            };
+
+            let status_info_size = status_info.preferred_size();
+
+            status_info.draw(&mut target.cropped(&Rectangle::new(
+                bbox.top_left
+                    + Size::new(
+                        0,
+                        bbox.size.height - status_height - status_padding - status_info_size.height,
+                    ),
+                status_info_size,
+            )))?;
+        }
+
         if let Some(remaining_time) = remaining_time {
             let mut status_rt = shapes::Textbox {
                 text: "            ",
@@ -239,11 +340,31 @@ impl Summary {
 
             let status_rt_size = status_rt.preferred_size();
 
-            let mut text_buf = heapless::String::<12>::new();
+            let mut text_buf = heapless::String::<32>::new();
             status_rt.text = match remaining_time {
                 RemainingTime::Indefinite => status_rt.text,
+                RemainingTime::Leased(leases) => {
+                    for (index, lease) in leases.iter().enumerate() {
+                        let separator = if index > 0 { "," } else { "" };
+
+                        if write!(&mut text_buf, "{}{}", separator, lease.name()).is_err() {
+                            break;
+                        }
+                    }
+
+                    // Pad with spaces to erase the longer text drawn previously
+                    while text_buf.chars().count() < 12 && text_buf.push(' ').is_ok() {}
+
+                    &text_buf
+                }
                 RemainingTime::Duration(duration) => {
-                    write!(&mut text_buf, "Sleep in {}s", min(duration.as_secs(), 99)).unwrap();
+                    write!(
+                        &mut text_buf,
+                        "{} {}s",
+                        i18n::text(Message::SleepIn),
+                        min(duration.as_secs(), 99)
+                    )
+                    .unwrap();
 
                     &text_buf
                 }
@@ -255,6 +376,6 @@ impl Summary {
             )))?;
         }
 
-        Ok(status_height)
+        Ok(status_height * 2 + status_padding)
     }
 }
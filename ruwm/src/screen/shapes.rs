@@ -3,14 +3,20 @@ use embedded_graphics::pixelcolor::{BinaryColor, Rgb565, Rgb888};
 use embedded_graphics::prelude::{PixelColor, RgbColor};
 
 pub use actions::*;
+pub use alert::*;
+pub use bar_chart::*;
 pub use battery::*;
+pub use editor::*;
 pub use textbox::*;
 pub use valve::*;
 pub use wifi::*;
 pub use wm::*;
 
 mod actions;
+mod alert;
+mod bar_chart;
 mod battery;
+mod editor;
 mod textbox;
 mod valve;
 mod wifi;
@@ -97,7 +103,7 @@ impl From<Color> for Rgb888 {
 
 pub mod util {
     use embedded_graphics::draw_target::Cropped;
-    use embedded_graphics::mono_font::{MonoFont, MonoTextStyleBuilder};
+    use embedded_graphics::mono_font::{iso_8859_1, iso_8859_5, MonoFont, MonoTextStyleBuilder};
     use embedded_graphics::prelude::{DrawTarget, DrawTargetExt, Point, Size};
     use embedded_graphics::primitives::{Primitive, PrimitiveStyle, Rectangle};
     use embedded_graphics::text::{Alignment, Baseline, Text, TextStyle, TextStyleBuilder};
@@ -105,6 +111,30 @@ pub mod util {
 
     use super::Color;
 
+    const LATIN1_FONTS: [MonoFont<'static>; 9] = [
+        iso_8859_1::FONT_10X20,
+        iso_8859_1::FONT_9X18,
+        iso_8859_1::FONT_9X15,
+        iso_8859_1::FONT_8X13,
+        iso_8859_1::FONT_7X13,
+        iso_8859_1::FONT_6X12,
+        iso_8859_1::FONT_6X10,
+        iso_8859_1::FONT_5X8,
+        iso_8859_1::FONT_4X6,
+    ];
+
+    const CYRILLIC_FONTS: [MonoFont<'static>; 9] = [
+        iso_8859_5::FONT_10X20,
+        iso_8859_5::FONT_9X18,
+        iso_8859_5::FONT_9X15,
+        iso_8859_5::FONT_8X13,
+        iso_8859_5::FONT_7X13,
+        iso_8859_5::FONT_6X12,
+        iso_8859_5::FONT_6X10,
+        iso_8859_5::FONT_5X8,
+        iso_8859_5::FONT_4X6,
+    ];
+
     pub fn clear<T>(area: &Rectangle, target: &mut T) -> Result<(), T::Error>
     where
         T: DrawTarget<Color = Color>,
@@ -149,7 +179,7 @@ pub mod util {
         T: DrawTarget<Color = Color>,
     {
         let character_style = MonoTextStyleBuilder::new()
-            .font(font)
+            .font(fallback_font(font, text).unwrap_or(font))
             .text_color(color)
             .build();
 
@@ -165,6 +195,29 @@ pub mod util {
         Ok(())
     }
 
+    /// ProFont only covers ASCII, so texts with umlauts or Cyrillic letters are drawn with
+    /// the largest ISO 8859 font which fits in the character cell of `font` instead
+    pub fn fallback_font(font: &MonoFont<'_>, text: &str) -> Option<&'static MonoFont<'static>> {
+        if text.is_ascii() {
+            return None;
+        }
+
+        let fonts: &'static [MonoFont<'static>] =
+            if text.chars().any(|ch| ('\u{400}'..='\u{4ff}').contains(&ch)) {
+                &CYRILLIC_FONTS
+            } else {
+                &LATIN1_FONTS
+            };
+
+        fonts
+            .iter()
+            .find(|fallback| {
+                fallback.character_size.width <= font.character_size.width
+                    && fallback.character_size.height <= font.character_size.height
+            })
+            .or_else(|| fonts.last())
+    }
+
     pub fn to_str(mut num: u64, buf: &mut [u8]) -> usize {
         let mut len = buf.len();
 
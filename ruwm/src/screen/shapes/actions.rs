@@ -1,3 +1,4 @@
+use core::fmt::Write;
 use core::str;
 
 use embedded_graphics::draw_target::DrawTarget;
@@ -5,10 +6,15 @@ use embedded_graphics::mono_font::*;
 use embedded_graphics::prelude::{OriginDimensions, Point, Size};
 use embedded_graphics::primitives::Rectangle;
 use enumset::{EnumSet, EnumSetType};
-use valve::{ValveCommand, ValveState};
+use valve::{ValveCommand, ValveState, MAIN_VALVE};
 
+use crate::budget::{self, BudgetConfig};
 use crate::dto::water_meter::WaterMeterCommand;
-use crate::{valve, wm};
+use crate::i18n::{self, Message};
+use crate::settings::{self, Setting};
+use crate::update::{UpdateCommand, UpdateState};
+use crate::wifi::WifiCommand;
+use crate::{pairing, update, valve, wifi, wm};
 
 use super::util::{clear_cropped, fill, text};
 use super::Color;
@@ -25,21 +31,111 @@ pub enum Action {
     Pair,
     Provision,
     Reprovision,
+    ChangeLanguage,
+    EditBudget,
+    EditLeakThreshold,
+    EditFlashWriteCycle,
+    EditKeepaliveTimeout,
+    EditBatteryChemistry,
+    EditBatteryDividerRatio,
+    EditBatteryLowVoltage,
+    EditBatteryCriticalVoltage,
+    EditBatteryMaxVoltage,
+    EditBatteryHysteresis,
+    EditValveTurnTicks,
+    EditOutageValveClose,
 }
 
 impl Action {
     pub fn text(&self) -> &'static str {
+        i18n::text(match self {
+            Self::Dismiss => Message::Dismiss,
+            Self::OpenValve => Message::OpenValve,
+            Self::CloseValve => Message::CloseValve,
+            Self::Arm => Message::Arm,
+            Self::Disarm => Message::Disarm,
+            Self::CheckForUpdate => Message::CheckForUpdate,
+            Self::Update => Message::Update,
+            Self::Pair => Message::Pair,
+            Self::Provision => Message::Provision,
+            Self::Reprovision => Message::Reprovision,
+            Self::ChangeLanguage => Message::ChangeLanguage,
+            Self::EditBudget => Message::EditBudget,
+            Self::EditLeakThreshold => Message::LeakThreshold,
+            Self::EditFlashWriteCycle => Message::FlashWriteCycle,
+            Self::EditKeepaliveTimeout => Message::KeepaliveTimeout,
+            Self::EditBatteryChemistry => Message::BatteryChemistry,
+            Self::EditBatteryDividerRatio => Message::BatteryDividerRatio,
+            Self::EditBatteryLowVoltage => Message::BatteryLowVoltage,
+            Self::EditBatteryCriticalVoltage => Message::BatteryCriticalVoltage,
+            Self::EditBatteryMaxVoltage => Message::BatteryMaxVoltage,
+            Self::EditBatteryHysteresis => Message::BatteryHysteresis,
+            Self::EditValveTurnTicks => Message::ValveTurnTicks,
+            Self::EditOutageValveClose => Message::OutageValveClose,
+        })
+    }
+
+    /// The setting edited by this action, if any
+    pub fn setting(&self) -> Option<Setting> {
+        match self {
+            Self::EditLeakThreshold => Some(Setting::LeakThreshold),
+            Self::EditFlashWriteCycle => Some(Setting::FlashWriteCycle),
+            Self::EditKeepaliveTimeout => Some(Setting::KeepaliveTimeout),
+            Self::EditBatteryChemistry => Some(Setting::BatteryChemistry),
+            Self::EditBatteryDividerRatio => Some(Setting::BatteryDividerRatio),
+            Self::EditBatteryLowVoltage => Some(Setting::BatteryLowVoltage),
+            Self::EditBatteryCriticalVoltage => Some(Setting::BatteryCriticalVoltage),
+            Self::EditBatteryMaxVoltage => Some(Setting::BatteryMaxVoltage),
+            Self::EditBatteryHysteresis => Some(Setting::BatteryHysteresis),
+            Self::EditValveTurnTicks => Some(Setting::ValveTurnTicks),
+            Self::EditOutageValveClose => Some(Setting::OutageValveClose),
+            _ => None,
+        }
+    }
+
+    /// For actions which edit a value rather than being triggered: the current value
+    pub fn edited_value(&self) -> Option<u64> {
         match self {
-            Self::Dismiss => "Dismiss",
-            Self::OpenValve => "Open Valve",
-            Self::CloseValve => "Close Valve",
-            Self::Arm => "Arm",
-            Self::Disarm => "Disarm",
-            Self::CheckForUpdate => "Check for Update",
-            Self::Update => "Update",
-            Self::Pair => "Pair",
-            Self::Provision => "Provision",
-            Self::Reprovision => "Reprovision",
+            Self::EditBudget => Some(budget::STATE.get().config.limit),
+            _ => self
+                .setting()
+                .map(|setting| settings::STATE.get().get(setting) as u64),
+        }
+    }
+
+    /// The minimum and maximum of the edited value, both inclusive
+    pub fn edited_range(&self) -> (u64, u64) {
+        match self.setting() {
+            Some(setting) => {
+                let (min, max) = setting.range();
+
+                (min as u64, max as u64)
+            }
+            None => (0, u64::MAX),
+        }
+    }
+
+    /// The edited value as shown to the user
+    pub fn format_value(&self, value: u64) -> heapless::String<16> {
+        match self.setting() {
+            Some(setting) => setting.format(value as u32),
+            None => {
+                let mut text = heapless::String::new();
+                let _ = write!(&mut text, "{}", value);
+
+                text
+            }
+        }
+    }
+
+    pub fn save(&self, value: u64) {
+        if let Some(setting) = self.setting() {
+            settings::set(setting, value as u32);
+        } else if *self == Self::EditBudget {
+            budget::COMMAND.signal(BudgetConfig {
+                limit: value,
+                ..budget::STATE.get().config
+            });
         }
     }
 
@@ -85,7 +181,8 @@ impl Action {
     pub fn active() -> EnumSet<Self> {
         let mut actions = EnumSet::empty();
 
-        let valve_state = valve::STATE.get();
+        // The Summary page shows the main valve, so only that one is opened and closed
+        let valve_state = valve::STATES[MAIN_VALVE as usize].get();
 
         if !matches!(
             valve_state,
@@ -101,29 +198,77 @@ impl Action {
             actions |= Action::CloseValve;
         }
 
-        let wm_state = wm::STATE.get();
+        // Arming and disarming applies to all meters
+        let armed = || wm::configured().map(|meter| wm::STATES[meter as usize].get().armed);
 
-        if !wm_state.armed {
+        if armed().any(|armed| !armed) {
             actions |= Action::Arm;
-        } else {
+        }
+
+        if armed().any(|armed| armed) {
             actions |= Action::Disarm;
         }
 
+        let update_state = update::STATE.get();
+
+        if !update_state.is_busy() {
+            actions |= Action::CheckForUpdate;
+        }
+
+        if matches!(update_state, UpdateState::Available(_)) {
+            actions |= Action::Update;
+        }
+
+        actions |= Action::Pair;
+
+        if !wifi::STATE.get().provisioning {
+            actions |= Action::Provision | Action::Reprovision;
+        }
+
+        actions |= Action::ChangeLanguage | Action::EditBudget;
+        actions |= Action::EditLeakThreshold
+            | Action::EditFlashWriteCycle
+            | Action::EditKeepaliveTimeout
+            | Action::EditBatteryChemistry
+            | Action::EditBatteryDividerRatio
+            | Action::EditBatteryLowVoltage
+            | Action::EditBatteryCriticalVoltage
+            | Action::EditBatteryMaxVoltage
+            | Action::EditBatteryHysteresis
+            | Action::EditValveTurnTicks
+            | Action::EditOutageValveClose;
+
         actions
     }
 
     pub fn trigger(&self) {
         match self {
-            Self::OpenValve => valve::COMMAND.signal(ValveCommand::Open),
-            Self::CloseValve => valve::COMMAND.signal(ValveCommand::Close),
-            Self::Arm => wm::COMMAND.signal(WaterMeterCommand::Arm),
-            Self::Disarm => wm::COMMAND.signal(WaterMeterCommand::Disarm),
-            // Self::CheckForUpdate => "Check for Update",
-            // Self::Update => "Update",
-            // Self::Pair => "Pair",
-            // Self::Provision => "Provision",
-            // Self::Reprovision => "Reprovision",
-            _ => {}
+            Self::OpenValve => valve::command(Some(MAIN_VALVE), ValveCommand::Open),
+            Self::CloseValve => valve::command(Some(MAIN_VALVE), ValveCommand::Close),
+            Self::Arm => wm::command(None, WaterMeterCommand::Arm),
+            Self::Disarm => wm::command(None, WaterMeterCommand::Disarm),
+            Self::CheckForUpdate => update::COMMAND.signal(UpdateCommand::Check),
+            Self::Update => update::COMMAND.signal(UpdateCommand::Update),
+            Self::Pair => pairing::generate(),
+            Self::Provision => wifi::COMMAND.signal(WifiCommand::Provision),
+            Self::Reprovision => wifi::COMMAND.signal(WifiCommand::Reprovision),
+            Self::ChangeLanguage => {
+                i18n::LANGUAGE.update_with(|language| language.next());
+            }
+            // Editing happens on the screen itself, see `screen::process`
+            Self::Dismiss
+            | Self::EditBudget
+            | Self::EditLeakThreshold
+            | Self::EditFlashWriteCycle
+            | Self::EditKeepaliveTimeout
+            | Self::EditBatteryChemistry
+            | Self::EditBatteryDividerRatio
+            | Self::EditBatteryLowVoltage
+            | Self::EditBatteryCriticalVoltage
+            | Self::EditBatteryMaxVoltage
+            | Self::EditBatteryHysteresis
+            | Self::EditValveTurnTicks
+            | Self::EditOutageValveClose => {}
         }
     }
 }
@@ -153,7 +298,7 @@ impl<'a> Actions<'a> {
         let width = self
             .enabled
             .iter()
-            .map(|action| action.text().len())
+            .map(|action| action.text().chars().count())
             .max()
             .unwrap_or(0) as u32;
         let height = self.font.character_size.height * self.enabled.len() as u32;
@@ -178,7 +323,20 @@ impl<'a> Actions<'a> {
 
         fill(&bbox, Color::LightBlue, target)?;
 
-        for (line, action) in self.enabled.iter().enumerate() {
+        // Scroll the actions if they don't fit, so that the selected one is always visible
+        let lines = (bbox.size.height.saturating_sub(self.outline * 2)
+            / self.font.character_size.height)
+            .max(1) as usize;
+
+        let selected = self
+            .enabled
+            .iter()
+            .position(|action| action == self.selected)
+            .unwrap_or(0);
+
+        let first = (selected + 1).saturating_sub(lines);
+
+        for (line, action) in self.enabled.iter().skip(first).take(lines).enumerate() {
             if self.selected == action {
                 fill(
                     &Rectangle::new(
@@ -0,0 +1,139 @@
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::mono_font::*;
+use embedded_graphics::prelude::{OriginDimensions, Point, Size};
+use embedded_graphics::text::{Alignment, Baseline, TextStyleBuilder};
+
+use crate::alert::AlertKind;
+use crate::i18n::{self, Message};
+
+use super::util::{clear_cropped, draw, fill, text};
+use super::Color;
+
+pub struct AlertBox<'a> {
+    pub kind: AlertKind,
+    /// Where the alert comes from, e.g. the name of a leak sensor
+    pub source: Option<&'a str>,
+    /// Whether the box is drawn inverted, so that alternating it blinks
+    pub inverted: bool,
+    pub padding: u32,
+    pub outline: u32,
+    pub font: MonoFont<'a>,
+}
+
+impl<'a> AlertBox<'a> {
+    pub const fn new() -> Self {
+        Self {
+            kind: AlertKind::Leak,
+            source: None,
+            inverted: false,
+            padding: 2,
+            outline: 2,
+            font: profont::PROFONT_18_POINT,
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self.kind {
+            AlertKind::Leak | AlertKind::Flood | AlertKind::ValveFault | AlertKind::Backflow => {
+                Color::Red
+            }
+            AlertKind::SensorFault | AlertKind::LowBattery => Color::Yellow,
+            AlertKind::WifiLost => Color::LightBlue,
+        }
+    }
+
+    pub fn text(&self) -> &'static str {
+        i18n::text(self.kind.message())
+    }
+
+    pub fn preferred_size(&self) -> Size {
+        let source_chars = self.source.map(|source| source.chars().count());
+
+        let chars = self
+            .text()
+            .chars()
+            .count()
+            .max(source_chars.unwrap_or(0))
+            .max(i18n::text(Message::Acknowledge).chars().count());
+
+        let lines = if self.source.is_some() { 3 } else { 2 };
+
+        let width = self.font.character_size.width * chars as u32;
+        let height = self.font.character_size.height * lines;
+
+        Size::new(width, height)
+            + Size::new(self.padding, self.padding) * 2
+            + Size::new(self.outline, self.outline) * 2
+    }
+
+    pub fn draw<T>(&self, target: &mut T) -> Result<(), T::Error>
+    where
+        T: DrawTarget<Color = Color>,
+    {
+        self.draw_shape(&mut clear_cropped(target, self.padding)?)
+    }
+
+    fn draw_shape<T>(&self, target: &mut T) -> Result<(), T::Error>
+    where
+        T: DrawTarget<Color = Color> + OriginDimensions,
+    {
+        let bbox = target.bounding_box();
+
+        let (background, foreground) = if self.inverted {
+            (Color::Black, self.color())
+        } else {
+            (self.color(), Color::Black)
+        };
+
+        fill(&bbox, background, target)?;
+        draw(&bbox, self.color(), self.outline, target)?;
+
+        let text_style = TextStyleBuilder::new()
+            .baseline(Baseline::Top)
+            .alignment(Alignment::Center)
+            .build();
+
+        let center = bbox.top_left.x + bbox.size.width as i32 / 2;
+        let line_height = self.font.character_size.height as i32;
+        let mut top = bbox.top_left.y + self.outline as i32;
+
+        text(
+            &self.font,
+            target,
+            Point::new(center, top),
+            self.text(),
+            foreground,
+            Some(text_style),
+        )?;
+
+        if let Some(source) = self.source {
+            top += line_height;
+
+            text(
+                &self.font,
+                target,
+                Point::new(center, top),
+                source,
+                foreground,
+                Some(text_style),
+            )?;
+        }
+
+        text(
+            &self.font,
+            target,
+            Point::new(center, top + line_height),
+            i18n::text(Message::Acknowledge),
+            foreground,
+            Some(text_style),
+        )?;
+
+        Ok(())
+    }
+}
+
+impl<'a> Default for AlertBox<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
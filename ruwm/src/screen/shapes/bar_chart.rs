@@ -0,0 +1,81 @@
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::prelude::{OriginDimensions, Point, Size};
+use embedded_graphics::primitives::Rectangle;
+
+use super::util::{clear_cropped, draw, fill};
+use super::Color;
+
+#[derive(Clone, Debug)]
+pub struct BarChart<'a> {
+    pub padding: u32,
+    pub outline: u32,
+    pub spacing: u32,
+    pub color: Color,
+    pub values: &'a [u64],
+}
+
+impl<'a> BarChart<'a> {
+    pub const fn new() -> Self {
+        Self {
+            padding: 2,
+            outline: 1,
+            spacing: 1,
+            color: Color::LightBlue,
+            values: &[],
+        }
+    }
+
+    pub fn draw<T>(&self, target: &mut T) -> Result<(), T::Error>
+    where
+        T: DrawTarget<Color = Color>,
+    {
+        self.draw_shape(&mut clear_cropped(target, self.padding)?)
+    }
+
+    fn draw_shape<T>(&self, target: &mut T) -> Result<(), T::Error>
+    where
+        T: DrawTarget<Color = Color> + OriginDimensions,
+    {
+        let bbox = target.bounding_box();
+
+        if self.outline > 0 {
+            draw(&bbox, Color::White, self.outline, target)?;
+        }
+
+        if self.values.is_empty() {
+            return Ok(());
+        }
+
+        let width = bbox.size.width.saturating_sub(self.outline * 2);
+        let height = bbox.size.height.saturating_sub(self.outline * 2);
+
+        let bar_width = (width / self.values.len() as u32).saturating_sub(self.spacing);
+        let max = self.values.iter().copied().max().unwrap_or(0).max(1);
+
+        for (index, value) in self.values.iter().enumerate() {
+            let bar_height = (height as u64 * *value / max) as u32;
+
+            if bar_width > 0 && bar_height > 0 {
+                fill(
+                    &Rectangle::new(
+                        Point::new(
+                            (self.outline + index as u32 * (bar_width + self.spacing)) as _,
+                            (self.outline + height - bar_height) as _,
+                        ),
+                        Size::new(bar_width, bar_height),
+                    ),
+                    self.color,
+                    target,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Default for BarChart<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
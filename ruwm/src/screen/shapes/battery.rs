@@ -6,6 +6,8 @@ use embedded_graphics::prelude::{OriginDimensions, Point, Size};
 use embedded_graphics::primitives::Rectangle;
 use embedded_graphics::text::{Alignment, Baseline, TextStyleBuilder};
 
+use crate::battery::BatteryLevel;
+
 use super::util::{clear, clear_cropped, fill, text, to_str};
 use super::Color;
 
@@ -26,6 +28,7 @@ pub struct Battery<'a> {
     pub text: BatteryChargedText,
     pub font: MonoFont<'a>,
     pub charged_percentage: Option<u8>,
+    pub level: Option<BatteryLevel>,
 }
 
 impl<'a> Battery<'a> {
@@ -39,6 +42,7 @@ impl<'a> Battery<'a> {
             text: BatteryChargedText::Xor,
             font: profont::PROFONT_24_POINT,
             charged_percentage: Some(100),
+            level: None,
         }
     }
 
@@ -64,10 +68,11 @@ impl<'a> Battery<'a> {
         };
 
         let charged_color = if let Some(percentage) = self.charged_percentage {
-            if percentage < self.percentage_threhsold {
-                Color::Red
-            } else {
-                Color::Green
+            match self.level {
+                Some(BatteryLevel::Critical) => Color::Red,
+                Some(BatteryLevel::Low) => Color::Yellow,
+                _ if percentage < self.percentage_threhsold => Color::Red,
+                _ => Color::Green,
             }
         } else {
             Color::Yellow
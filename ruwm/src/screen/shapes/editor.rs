@@ -0,0 +1,108 @@
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::mono_font::*;
+use embedded_graphics::prelude::{OriginDimensions, Point, Size};
+use embedded_graphics::text::{Alignment, Baseline, TextStyleBuilder};
+
+use crate::i18n::{self, Message};
+
+use super::util::{clear_cropped, draw, text};
+use super::Color;
+
+/// A value being edited with the buttons: its label, the value itself and how to save it
+pub struct Editor<'a> {
+    pub label: &'a str,
+    pub value: &'a str,
+    pub color: Color,
+    pub padding: u32,
+    pub outline: u32,
+    pub font: MonoFont<'a>,
+}
+
+impl<'a> Editor<'a> {
+    pub const fn new() -> Self {
+        Self {
+            label: "???",
+            value: "???",
+            color: Color::Yellow,
+            padding: 2,
+            outline: 2,
+            font: profont::PROFONT_18_POINT,
+        }
+    }
+
+    pub fn preferred_size(&self) -> Size {
+        let chars = self
+            .label
+            .chars()
+            .count()
+            .max(self.value.chars().count())
+            .max(i18n::text(Message::Save).chars().count());
+
+        let width = self.font.character_size.width * chars as u32;
+        let height = self.font.character_size.height * 3;
+
+        Size::new(width, height)
+            + Size::new(self.padding, self.padding) * 2
+            + Size::new(self.outline, self.outline) * 2
+    }
+
+    pub fn draw<T>(&self, target: &mut T) -> Result<(), T::Error>
+    where
+        T: DrawTarget<Color = Color>,
+    {
+        self.draw_shape(&mut clear_cropped(target, self.padding)?)
+    }
+
+    fn draw_shape<T>(&self, target: &mut T) -> Result<(), T::Error>
+    where
+        T: DrawTarget<Color = Color> + OriginDimensions,
+    {
+        let bbox = target.bounding_box();
+
+        draw(&bbox, self.color, self.outline, target)?;
+
+        let text_style = TextStyleBuilder::new()
+            .baseline(Baseline::Top)
+            .alignment(Alignment::Center)
+            .build();
+
+        let center = bbox.top_left.x + bbox.size.width as i32 / 2;
+        let top = bbox.top_left.y + self.outline as i32;
+        let line_height = self.font.character_size.height as i32;
+
+        text(
+            &self.font,
+            target,
+            Point::new(center, top),
+            self.label,
+            self.color,
+            Some(text_style),
+        )?;
+
+        text(
+            &self.font,
+            target,
+            Point::new(center, top + line_height),
+            self.value,
+            Color::White,
+            Some(text_style),
+        )?;
+
+        text(
+            &self.font,
+            target,
+            Point::new(center, top + line_height * 2),
+            i18n::text(Message::Save),
+            self.color,
+            Some(text_style),
+        )?;
+
+        Ok(())
+    }
+}
+
+impl<'a> Default for Editor<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
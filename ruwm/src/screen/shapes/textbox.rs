@@ -33,7 +33,8 @@ impl<'a> Textbox<'a> {
     }
 
     pub fn preferred_size(&self) -> Size {
-        let width = self.font.character_size.width * self.text.len() as u32 + self.padding * 2;
+        let width =
+            self.font.character_size.width * self.text.chars().count() as u32 + self.padding * 2;
         let height = self.font.character_size.height + self.padding * 2;
 
         Size::new(width, height)
@@ -0,0 +1,169 @@
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+
+use serde::Deserialize;
+
+use channel_bridge::notification::Notification;
+
+use crate::state::State;
+use crate::storage::{self, Storage, Versioned};
+
+pub use crate::dto::settings::*;
+
+const STORAGE_KEY: &str = "settings";
+
+pub static STATE: State<Settings> = State::new(
+    "SETTINGS",
+    Settings::new(),
+    &[&crate::screen::SETTINGS_STATE_NOTIF, &STATE_PERSIST_NOTIFY],
+);
+
+static STATE_PERSIST_NOTIFY: Notification = Notification::new();
+
+/// Changes a single setting, clamping the value to its range
+pub fn set(setting: Setting, value: u32) {
+    STATE.update_with(|mut settings| {
+        settings.set(setting, value);
+
+        settings
+    });
+}
+
+#[derive(Deserialize)]
+struct SettingsV1 {
+    leak_threshold: u32,
+    flash_write_cycle: u32,
+    keepalive_timeout: u32,
+    battery_low_voltage: u32,
+    battery_max_voltage: u32,
+    valve_turn_ticks: u32,
+}
+
+impl From<SettingsV1> for SettingsV2 {
+    fn from(settings: SettingsV1) -> Self {
+        let defaults = Settings::new();
+
+        Self {
+            leak_threshold: settings.leak_threshold,
+            flash_write_cycle: settings.flash_write_cycle,
+            keepalive_timeout: settings.keepalive_timeout,
+            battery_chemistry: defaults.battery_chemistry,
+            battery_divider_ratio: defaults.battery_divider_ratio,
+            battery_low_voltage: settings.battery_low_voltage,
+            battery_critical_voltage: defaults.battery_critical_voltage,
+            battery_max_voltage: settings.battery_max_voltage,
+            battery_hysteresis: defaults.battery_hysteresis,
+            valve_turn_ticks: settings.valve_turn_ticks,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SettingsV2 {
+    leak_threshold: u32,
+    flash_write_cycle: u32,
+    keepalive_timeout: u32,
+    battery_chemistry: u32,
+    battery_divider_ratio: u32,
+    battery_low_voltage: u32,
+    battery_critical_voltage: u32,
+    battery_max_voltage: u32,
+    battery_hysteresis: u32,
+    valve_turn_ticks: u32,
+}
+
+impl From<SettingsV2> for Settings {
+    fn from(settings: SettingsV2) -> Self {
+        Self {
+            leak_threshold: settings.leak_threshold,
+            flash_write_cycle: settings.flash_write_cycle,
+            keepalive_timeout: settings.keepalive_timeout,
+            battery_chemistry: settings.battery_chemistry,
+            battery_divider_ratio: settings.battery_divider_ratio,
+            battery_low_voltage: settings.battery_low_voltage,
+            battery_critical_voltage: settings.battery_critical_voltage,
+            battery_max_voltage: settings.battery_max_voltage,
+            battery_hysteresis: settings.battery_hysteresis,
+            valve_turn_ticks: settings.valve_turn_ticks,
+            ..Self::new()
+        }
+    }
+}
+
+impl Versioned for Settings {
+    const VERSION: u8 = 3;
+
+    fn migrate(version: u8, payload: &[u8]) -> Result<Option<Self>, postcard::Error> {
+        match version {
+            1 => storage::migrate_from::<SettingsV1, SettingsV2>(payload)
+                .map(|settings| settings.map(Into::into)),
+            2 => storage::migrate_from::<SettingsV2, _>(payload),
+            _ => Ok(None),
+        }
+    }
+}
+
+pub fn restore(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>) {
+    if let Some(settings) = storage::restore(storage, STORAGE_KEY) {
+        STATE.set(settings);
+    }
+}
+
+pub async fn persist(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>) {
+    loop {
+        STATE_PERSIST_NOTIFY.wait().await;
+
+        storage::persist(storage, STORAGE_KEY, &STATE.get());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::testing::MemoryStorage;
+
+    use super::*;
+
+    #[test]
+    fn migrates_v1() {
+        let storage =
+            MemoryStorage::with(STORAGE_KEY, include_bytes!("../fixtures/settings-v1.bin"));
+
+        assert_eq!(
+            storage::load::<_, Settings>(&storage, STORAGE_KEY).unwrap(),
+            Some(Settings {
+                leak_threshold: 5,
+                flash_write_cycle: 20,
+                keepalive_timeout: 60,
+                battery_low_voltage: 3000,
+                battery_max_voltage: 3300,
+                valve_turn_ticks: 30,
+                ..Settings::new()
+            })
+        );
+    }
+
+    #[test]
+    fn migrates_v2() {
+        let storage =
+            MemoryStorage::with(STORAGE_KEY, include_bytes!("../fixtures/settings-v2.bin"));
+
+        assert_eq!(
+            storage::load::<_, Settings>(&storage, STORAGE_KEY).unwrap(),
+            Some(Settings {
+                leak_threshold: 5,
+                flash_write_cycle: 20,
+                keepalive_timeout: 60,
+                battery_chemistry: 1,
+                battery_divider_ratio: 2000,
+                battery_low_voltage: 3000,
+                battery_critical_voltage: 2800,
+                battery_max_voltage: 3300,
+                battery_hysteresis: 50,
+                valve_turn_ticks: 30,
+                outage_valve_close: 0,
+            })
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::{Duration, Instant};
+
+use crate::battery::{self, BatteryLevel};
+use crate::wm;
+
+/// What keeps the device awake for longer and makes it wake up sooner
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Activity {
+    Flow = 0,
+    MqttCommand = 1,
+    User = 2,
+}
+
+/// How long to stay awake and how long to sleep, depending on what is going on
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SleepPolicy {
+    /// Counted since the last notable state change
+    pub awake: Duration,
+    pub user_awake: Duration,
+    pub mqtt_command_awake: Duration,
+    /// Flow and activities older than that no longer count
+    pub recent: Duration,
+    pub flow_wake_interval: Duration,
+    pub armed_wake_interval: Duration,
+    pub idle_wake_interval: Duration,
+    /// The wake interval is multiplied by that for a low battery, and by its square for a critical one
+    pub low_battery_factor: u32,
+}
+
+impl SleepPolicy {
+    pub const fn new() -> Self {
+        Self {
+            awake: Duration::from_secs(20),
+            user_awake: Duration::from_secs(60),
+            mqtt_command_awake: Duration::from_secs(30),
+            recent: Duration::from_secs(5 * 60),
+            flow_wake_interval: Duration::from_secs(10),
+            armed_wake_interval: Duration::from_secs(30),
+            idle_wake_interval: Duration::from_secs(5 * 60),
+            low_battery_factor: 4,
+        }
+    }
+
+    pub fn plan(&self, inputs: &SleepInputs) -> SleepPlan {
+        let recent =
+            |since: Option<Duration>| since.map(|since| since <= self.recent).unwrap_or(false);
+
+        let awake = if inputs.powered {
+            None
+        } else {
+            let mut awake = self.awake;
+
+            if recent(inputs.since_user_activity) {
+                awake = awake.max(self.user_awake);
+            }
+
+            if recent(inputs.since_mqtt_command) {
+                awake = awake.max(self.mqtt_command_awake);
+            }
+
+            if inputs.battery_level == Some(BatteryLevel::Critical) {
+                awake = awake.min(self.awake);
+            }
+
+            Some(awake)
+        };
+
+        let wake_interval = if recent(inputs.since_flow) {
+            self.flow_wake_interval
+        } else if inputs.armed {
+            self.armed_wake_interval
+        } else {
+            self.idle_wake_interval
+        };
+
+        let factor = match inputs.battery_level {
+            Some(BatteryLevel::Low) => self.low_battery_factor,
+            Some(BatteryLevel::Critical) => self.low_battery_factor * self.low_battery_factor,
+            _ => 1,
+        };
+
+        SleepPlan {
+            awake,
+            wake_interval: wake_interval * factor.max(1),
+        }
+    }
+}
+
+impl Default for SleepPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Everything the policy decides on, so that it can be fed with made up values as well
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
+pub struct SleepInputs {
+    pub battery_level: Option<BatteryLevel>,
+    pub powered: bool,
+    pub armed: bool,
+    pub since_flow: Option<Duration>,
+    pub since_mqtt_command: Option<Duration>,
+    pub since_user_activity: Option<Duration>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SleepPlan {
+    /// How long to stay awake, or `None` to not sleep at all
+    pub awake: Option<Duration>,
+    /// How long to sleep before the timer wakes the device up; the pins can wake it up earlier
+    pub wake_interval: Duration,
+}
+
+static LAST_ACTIVITY: Mutex<CriticalSectionRawMutex, Cell<[Option<Instant>; 3]>> =
+    Mutex::new(Cell::new([None; 3]));
+
+/// Records the activity, which also restarts the awake time
+pub fn mark(activity: Activity) {
+    LAST_ACTIVITY.lock(|last| {
+        let mut activities = last.get();
+        activities[activity as usize] = Some(Instant::now());
+
+        last.set(activities);
+    });
+
+    crate::keepalive::NOTIF.notify();
+}
+
+/// The inputs of the policy, as per the current state of the device
+pub fn inputs() -> SleepInputs {
+    let now = Instant::now();
+    let last = LAST_ACTIVITY.lock(Cell::get);
+    let since = |activity: Activity| last[activity as usize].map(|at| now - at);
+
+    let battery = battery::STATE.get();
+
+    SleepInputs {
+        battery_level: battery.level,
+        powered: battery.powered.unwrap_or(false),
+        armed: wm::STATES.iter().any(|state| state.get().armed),
+        since_flow: since(Activity::Flow),
+        since_mqtt_command: since(Activity::MqttCommand),
+        since_user_activity: since(Activity::User),
+    }
+}
+
+pub fn plan(policy: &SleepPolicy) -> SleepPlan {
+    policy.plan(&inputs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn secs(secs: u64) -> Option<Duration> {
+        Some(Duration::from_secs(secs))
+    }
+
+    const NONE: SleepInputs = SleepInputs {
+        battery_level: None,
+        powered: false,
+        armed: false,
+        since_flow: None,
+        since_mqtt_command: None,
+        since_user_activity: None,
+    };
+
+    #[test]
+    fn plans() {
+        let cases = [
+            // Nothing going on
+            (NONE, secs(20), 300),
+            // Powered, never sleeps
+            (
+                SleepInputs {
+                    powered: true,
+                    since_user_activity: secs(10),
+                    ..NONE
+                },
+                None,
+                300,
+            ),
+            // Activities extend the awake time while they are recent
+            (
+                SleepInputs {
+                    since_user_activity: secs(10),
+                    ..NONE
+                },
+                secs(60),
+                300,
+            ),
+            (
+                SleepInputs {
+                    since_mqtt_command: secs(10),
+                    ..NONE
+                },
+                secs(30),
+                300,
+            ),
+            (
+                SleepInputs {
+                    since_user_activity: secs(10),
+                    since_mqtt_command: secs(10),
+                    ..NONE
+                },
+                secs(60),
+                300,
+            ),
+            (
+                SleepInputs {
+                    since_user_activity: secs(600),
+                    since_mqtt_command: secs(600),
+                    ..NONE
+                },
+                secs(20),
+                300,
+            ),
+            // Flow beats armed, which beats idle
+            (
+                SleepInputs {
+                    since_flow: secs(10),
+                    armed: true,
+                    ..NONE
+                },
+                secs(20),
+                10,
+            ),
+            (
+                SleepInputs {
+                    armed: true,
+                    ..NONE
+                },
+                secs(20),
+                30,
+            ),
+            (
+                SleepInputs {
+                    since_flow: secs(600),
+                    ..NONE
+                },
+                secs(20),
+                300,
+            ),
+            // Battery levels
+            (
+                SleepInputs {
+                    battery_level: Some(BatteryLevel::Normal),
+                    since_user_activity: secs(10),
+                    ..NONE
+                },
+                secs(60),
+                300,
+            ),
+            (
+                SleepInputs {
+                    battery_level: Some(BatteryLevel::Low),
+                    since_user_activity: secs(10),
+                    armed: true,
+                    ..NONE
+                },
+                secs(60),
+                30 * 4,
+            ),
+            (
+                SleepInputs {
+                    battery_level: Some(BatteryLevel::Critical),
+                    since_user_activity: secs(10),
+                    since_mqtt_command: secs(10),
+                    ..NONE
+                },
+                secs(20),
+                300 * 16,
+            ),
+        ];
+
+        let policy = SleepPolicy::new();
+
+        for (inputs, awake, wake_interval) in IntoIterator::into_iter(cases) {
+            assert_eq!(
+                policy.plan(&inputs),
+                SleepPlan {
+                    awake,
+                    wake_interval: Duration::from_secs(wake_interval),
+                },
+                "{:?}",
+                inputs
+            );
+        }
+    }
+}
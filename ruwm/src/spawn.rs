@@ -1,5 +1,9 @@
+use core::cell::RefCell;
 use core::fmt::Debug;
 
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+
 use embedded_hal::adc;
 use embedded_hal::digital::v2::{InputPin, OutputPin};
 
@@ -13,36 +17,49 @@ use edge_executor::*;
 
 use channel_bridge::asynch::*;
 
-use valve::ValveState;
-use wm_stats::WaterMeterStatsState;
-
-use crate::button::{self, PressedLevel};
+use crate::battery::BatteryConfig;
+use crate::button::{self, ButtonConfig, PressedLevel};
+use crate::clock::{self, Clock};
+use crate::emergency::{self, EmergencyPolicy};
+use crate::leak_sensor::{self, LeakSensorConfig, LeakSensorId};
 use crate::mqtt::MqttCommand;
 use crate::pulse_counter::{PulseCounter, PulseWakeup};
-use crate::screen::Color;
+use crate::screen::{Backlight, Color, PowerConfig};
+use crate::sleep::SleepPolicy;
+use crate::storage::Storage;
+use crate::update::{self, Ota};
+use crate::valve::{self, ValveConfig, ValveId};
 use crate::web::{self, WebEvent, WebRequest};
-use crate::wm::{self, WaterMeterState};
-use crate::{battery, emergency, keepalive, mqtt, screen, wm_stats, ws};
-use crate::{valve, wifi};
+use crate::wifi::{self, WifiInfo};
+use crate::wm::{self, MeterConfig, MeterId};
+use crate::{
+    alert, battery, budget, i18n, keepalive, mqtt, power, schedule, screen, settings, wm_stats, ws,
+};
 
 pub fn high_prio<'a, ADC, BP, const C: usize, M>(
     executor: &mut Executor<'a, C, M, Local>,
     tasks: &mut heapless::Vec<Task<()>, C>,
-    valve_power_pin: impl OutputPin<Error = impl Debug + 'a> + 'a,
-    valve_open_pin: impl OutputPin<Error = impl Debug + 'a> + 'a,
-    valve_close_pin: impl OutputPin<Error = impl Debug + 'a> + 'a,
-    valve_persister: impl FnMut(Option<ValveState>) + 'a,
-    pulse_counter: impl PulseCounter + 'a,
-    pulse_wakeup: impl PulseWakeup + 'a,
-    wm_persister: impl FnMut(WaterMeterState) + 'a,
-    wm_stats_persister: impl FnMut(WaterMeterStatsState) + 'a,
+    valves: impl IntoIterator<
+        Item = (
+            impl OutputPin<Error = impl Debug + 'a> + 'a,
+            impl OutputPin<Error = impl Debug + 'a> + 'a,
+            impl OutputPin<Error = impl Debug + 'a> + 'a,
+            ValveConfig,
+        ),
+    >,
+    emergency_policy: EmergencyPolicy,
+    storage: &'a Mutex<impl RawMutex + 'a, RefCell<impl Storage + 'a>>,
+    meters: impl IntoIterator<Item = (impl PulseCounter + 'a, impl PulseWakeup + 'a, MeterConfig)>,
     battery_voltage: impl adc::OneShot<ADC, u16, BP> + 'a,
     battery_pin: BP,
     power_pin: impl InputPin + 'a,
+    battery_config: BatteryConfig,
     roller: bool,
     button1_pin: impl InputPin<Error = impl Debug + 'a> + 'a,
     button2_pin: impl InputPin<Error = impl Debug + 'a> + 'a,
     button3_pin: impl InputPin<Error = impl Debug + 'a> + 'a,
+    button_config: ButtonConfig,
+    sleep_policy: SleepPolicy,
 ) -> Result<(), SpawnError>
 where
     M: Monitor + Default,
@@ -50,39 +67,67 @@ where
     BP: adc::Channel<ADC> + 'a,
 {
     executor
-        .spawn_local_collect(valve::process(), tasks)?
+        .spawn_local_collect(valve::persist(storage), tasks)?
+        .spawn_local_collect(wm::persist(storage), tasks)?
+        .spawn_local_collect(wm_stats::persist(storage), tasks)?
+        .spawn_local_collect(clock::persist(storage), tasks)?
+        .spawn_local_collect(schedule::persist(storage), tasks)?
+        .spawn_local_collect(budget::persist(storage), tasks)?
+        .spawn_local_collect(i18n::persist(storage), tasks)?
+        .spawn_local_collect(settings::persist(storage), tasks)?
         .spawn_local_collect(
-            valve::spin(valve_power_pin, valve_open_pin, valve_close_pin),
+            battery::process(battery_voltage, battery_pin, power_pin, battery_config),
             tasks,
         )?
-        .spawn_local_collect(valve::persist(valve_persister), tasks)?
-        .spawn_local_collect(wm::process(pulse_counter, pulse_wakeup), tasks)?
-        .spawn_local_collect(wm::persist(wm_persister), tasks)?
-        .spawn_local_collect(wm_stats::persist(wm_stats_persister), tasks)?
-        .spawn_local_collect(
-            battery::process(battery_voltage, battery_pin, power_pin),
+        .spawn_local_collect(battery::persist(storage), tasks)?
+        .spawn_local_collect(power::process(), tasks)?
+        .spawn_local_collect(power::persist(storage), tasks)?
+        .spawn_local_collect(emergency::process(emergency_policy), tasks)?
+        .spawn_local_collect(keepalive::process(sleep_policy), tasks)?;
+
+    // The valves and the meters are numbered in the order they are given
+    for (valve, (power_pin, open_pin, close_pin, config)) in valves.into_iter().enumerate() {
+        valve::configure(valve as ValveId, config);
+
+        executor.spawn_local_collect(
+            valve::process(valve as ValveId, power_pin, open_pin, close_pin, config),
             tasks,
-        )?
-        .spawn_local_collect(
-            button::button3_process(button3_pin, PressedLevel::Low),
+        )?;
+    }
+
+    // Only now it is known which valves keep their position
+    valve::restore(storage);
+
+    for (meter, (pulse_counter, pulse_wakeup, config)) in meters.into_iter().enumerate() {
+        executor.spawn_local_collect(
+            wm::process(meter as MeterId, pulse_counter, pulse_wakeup, config),
             tasks,
-        )?
-        .spawn_local_collect(emergency::process(), tasks)?
-        .spawn_local_collect(keepalive::process(), tasks)?;
+        )?;
+    }
 
     if roller {
         executor.spawn_local_collect(
-            button::button1_button2_roller_process(button1_pin, button2_pin),
+            button::button1_button2_roller_process(
+                button1_pin,
+                button2_pin,
+                button3_pin,
+                PressedLevel::Low,
+                button_config,
+            ),
             tasks,
         )?;
     } else {
         executor
             .spawn_local_collect(
-                button::button1_process(button1_pin, PressedLevel::Low),
+                button::button1_process(button1_pin, PressedLevel::Low, button_config),
                 tasks,
             )?
             .spawn_local_collect(
-                button::button2_process(button2_pin, PressedLevel::Low),
+                button::button2_process(button2_pin, PressedLevel::Low, button_config),
+                tasks,
+            )?
+            .spawn_local_collect(
+                button::button3_process(button3_pin, PressedLevel::Low, button_config),
                 tasks,
             )?;
     }
@@ -90,11 +135,32 @@ where
     Ok(())
 }
 
+/// The probes are the input pins of the sensors configured with `LeakSensorInput::Probe`
+pub fn leak_sensors<'a, const C: usize, M>(
+    executor: &mut Executor<'a, C, M, Local>,
+    tasks: &mut heapless::Vec<Task<()>, C>,
+    sensors: &[LeakSensorConfig],
+    probes: impl IntoIterator<Item = (LeakSensorId, impl InputPin + 'a)>,
+) -> Result<(), SpawnError>
+where
+    M: Monitor + Default,
+{
+    leak_sensor::configure(sensors);
+
+    for (sensor, pin) in probes {
+        executor.spawn_local_collect(leak_sensor::process_probe(sensor, pin), tasks)?;
+    }
+
+    Ok(())
+}
+
 pub fn mid_prio<'a, const C: usize, M, D>(
     executor: &mut Executor<'a, C, M, Local>,
     tasks: &mut heapless::Vec<Task<()>, C>,
     display: D,
-    wm_flash: impl FnMut(WaterMeterState) + 'a,
+    backlight: impl Backlight + 'a,
+    screen_power: PowerConfig,
+    clock: impl Clock + 'a,
 ) -> Result<(), SpawnError>
 where
     M: Monitor + Default,
@@ -102,10 +168,13 @@ where
     D::Error: Debug,
 {
     executor
+        .spawn_local_collect(clock::process(clock), tasks)?
         .spawn_local_collect(wm_stats::process(), tasks)?
-        .spawn_local_collect(screen::process(), tasks)?
-        .spawn_local_collect(screen::run_draw(display), tasks)?
-        .spawn_local_collect(wm::flash(wm_flash), tasks)?;
+        .spawn_local_collect(schedule::process(), tasks)?
+        .spawn_local_collect(budget::process(), tasks)?
+        .spawn_local_collect(alert::process(), tasks)?
+        .spawn_local_collect(screen::process(backlight, screen_power), tasks)?
+        .spawn_local_collect(screen::run_draw(display), tasks)?;
 
     Ok(())
 }
@@ -114,13 +183,27 @@ pub fn wifi<'a, const C: usize, M, D>(
     executor: &mut Executor<'a, C, M, Local>,
     tasks: &mut heapless::Vec<Task<()>, C>,
     wifi: impl WifiTrait + 'a,
+    wifi_info: impl WifiInfo + 'a,
     wifi_notif: impl Receiver<Data = D> + 'a,
 ) -> Result<(), SpawnError>
 where
     M: Monitor + Default,
     D: 'a,
 {
-    executor.spawn_local_collect(wifi::process(wifi, wifi_notif), tasks)?;
+    executor.spawn_local_collect(wifi::process(wifi, wifi_info, wifi_notif), tasks)?;
+
+    Ok(())
+}
+
+pub fn update<'a, const C: usize, M>(
+    executor: &mut Executor<'a, C, M, Local>,
+    tasks: &mut heapless::Vec<Task<()>, C>,
+    ota: impl Ota + 'a,
+) -> Result<(), SpawnError>
+where
+    M: Monitor + Default,
+{
+    executor.spawn_local_collect(update::process(ota), tasks)?;
 
     Ok(())
 }
@@ -129,12 +212,16 @@ pub fn mqtt_send<'a, const L: usize, const C: usize, M>(
     executor: &mut Executor<'a, C, M, Local>,
     tasks: &mut heapless::Vec<Task<()>, C>,
     mqtt_topic_prefix: &'a str,
+    mqtt_broker: &'a str,
     mqtt_client: impl Client + Publish + 'a,
 ) -> Result<(), SpawnError>
 where
     M: Monitor + Default,
 {
-    executor.spawn_local_collect(mqtt::send::<L>(mqtt_topic_prefix, mqtt_client), tasks)?;
+    executor.spawn_local_collect(
+        mqtt::send::<L>(mqtt_topic_prefix, mqtt_broker, mqtt_client),
+        tasks,
+    )?;
 
     Ok(())
 }
@@ -0,0 +1,629 @@
+use core::cell::RefCell;
+use core::fmt::{Debug, Write};
+
+use log::{info, warn};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+
+use crate::error;
+
+pub const RECORD_MAX_LEN: usize = 512;
+
+const VERSION_LEN: usize = 1;
+const CRC_LEN: usize = 4;
+
+pub trait Storage {
+    type Error: Debug;
+
+    fn load<'a>(&self, key: &str, buf: &'a mut [u8]) -> Result<Option<&'a [u8]>, Self::Error>;
+
+    fn store(&mut self, key: &str, data: &[u8]) -> Result<(), Self::Error>;
+
+    fn remove(&mut self, key: &str) -> Result<(), Self::Error>;
+}
+
+impl<T> Storage for &mut T
+where
+    T: Storage,
+{
+    type Error = T::Error;
+
+    fn load<'a>(&self, key: &str, buf: &'a mut [u8]) -> Result<Option<&'a [u8]>, Self::Error> {
+        (**self).load(key, buf)
+    }
+
+    fn store(&mut self, key: &str, data: &[u8]) -> Result<(), Self::Error> {
+        (*self).store(key, data)
+    }
+
+    fn remove(&mut self, key: &str) -> Result<(), Self::Error> {
+        (*self).remove(key)
+    }
+}
+
+pub trait Versioned: Serialize + DeserializeOwned {
+    const VERSION: u8;
+
+    /// Decodes a payload persisted with an older `version` of the type.
+    ///
+    /// Types bumping their `VERSION` should keep the previous layouts around and
+    /// convert them here (see `migrate_from`). Returning `Ok(None)` drops the record.
+    fn migrate(version: u8, payload: &[u8]) -> Result<Option<Self>, postcard::Error> {
+        let _ = payload;
+
+        warn!("No migration from version {} to {}", version, Self::VERSION);
+
+        Ok(None)
+    }
+}
+
+pub fn migrate_from<O, T>(payload: &[u8]) -> Result<Option<T>, postcard::Error>
+where
+    O: DeserializeOwned + Into<T>,
+{
+    postcard::from_bytes::<O>(payload).map(|old| Some(old.into()))
+}
+
+#[derive(Debug)]
+pub enum StorageError<E> {
+    Storage(E),
+    Serde(postcard::Error),
+    Corrupted,
+}
+
+impl<E> From<postcard::Error> for StorageError<E> {
+    fn from(e: postcard::Error) -> Self {
+        Self::Serde(e)
+    }
+}
+
+pub fn load<S, T>(storage: &S, key: &str) -> Result<Option<T>, StorageError<S::Error>>
+where
+    S: Storage,
+    T: Versioned,
+{
+    let mut buf = [0_u8; RECORD_MAX_LEN];
+
+    if let Some(data) = storage.load(key, &mut buf).map_err(StorageError::Storage)? {
+        let (version, payload) = decode(data)?;
+
+        if version == T::VERSION {
+            Ok(Some(postcard::from_bytes(payload)?))
+        } else if version < T::VERSION {
+            info!(
+                "Migrating record {} from version {} to {}",
+                key,
+                version,
+                T::VERSION
+            );
+
+            T::migrate(version, payload).map_err(StorageError::Serde)
+        } else {
+            warn!(
+                "Record {} has version {}, newer than {}, ignoring",
+                key,
+                version,
+                T::VERSION
+            );
+
+            Ok(None)
+        }
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn store<S, T>(storage: &mut S, key: &str, value: &T) -> Result<(), StorageError<S::Error>>
+where
+    S: Storage,
+    T: Versioned,
+{
+    let mut buf = [0_u8; RECORD_MAX_LEN];
+
+    let len = encode(T::VERSION, value, &mut buf)?;
+
+    storage
+        .store(key, &buf[..len])
+        .map_err(StorageError::Storage)
+}
+
+pub fn restore<T>(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>, key: &str) -> Option<T>
+where
+    T: Versioned,
+{
+    storage
+        .lock(|storage| error::check!(load(&*storage.borrow(), key)))
+        .ok()
+        .flatten()
+}
+
+/// Moves a record written without a version tag and CRC (as done by firmware
+/// predating the versioned records) from `legacy_key` to `key`.
+pub fn restore_legacy<T>(
+    storage: &Mutex<impl RawMutex, RefCell<impl Storage>>,
+    legacy_key: &str,
+    key: &str,
+) -> Option<T>
+where
+    T: Versioned,
+{
+    storage
+        .lock(|storage| error::check!(migrate_legacy(&mut *storage.borrow_mut(), legacy_key, key)))
+        .ok()
+        .flatten()
+}
+
+fn migrate_legacy<S, T>(
+    storage: &mut S,
+    legacy_key: &str,
+    key: &str,
+) -> Result<Option<T>, StorageError<S::Error>>
+where
+    S: Storage,
+    T: Versioned,
+{
+    let mut buf = [0_u8; RECORD_MAX_LEN];
+
+    let data = if let Some(data) = storage
+        .load(legacy_key, &mut buf)
+        .map_err(StorageError::Storage)?
+    {
+        data
+    } else {
+        return Ok(None);
+    };
+
+    // Legacy records are unversioned and have the layout of the first version
+    let value = if T::VERSION == 1 {
+        postcard::from_bytes::<T>(data)?
+    } else if let Some(value) = T::migrate(1, data)? {
+        value
+    } else {
+        return Ok(None);
+    };
+
+    info!("Migrating legacy record {} to {}", legacy_key, key);
+
+    store(storage, key, &value)?;
+    storage.remove(legacy_key).map_err(StorageError::Storage)?;
+
+    Ok(Some(value))
+}
+
+pub fn persist<T>(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>, key: &str, value: &T)
+where
+    T: Versioned,
+{
+    error::log_err!(storage.lock(|storage| store(&mut *storage.borrow_mut(), key, value)));
+}
+
+fn encode<T>(version: u8, value: &T, buf: &mut [u8]) -> Result<usize, postcard::Error>
+where
+    T: Serialize,
+{
+    let payload_end = buf.len() - CRC_LEN;
+
+    buf[0] = version;
+
+    let len = VERSION_LEN + postcard::to_slice(value, &mut buf[VERSION_LEN..payload_end])?.len();
+
+    let crc = crc32(&buf[..len]);
+    buf[len..len + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+
+    Ok(len + CRC_LEN)
+}
+
+fn decode<E>(data: &[u8]) -> Result<(u8, &[u8]), StorageError<E>> {
+    if data.len() < VERSION_LEN + CRC_LEN {
+        return Err(StorageError::Corrupted);
+    }
+
+    let (record, crc) = data.split_at(data.len() - CRC_LEN);
+
+    if crc32(record).to_le_bytes() != crc {
+        return Err(StorageError::Corrupted);
+    }
+
+    Ok((record[0], &record[VERSION_LEN..]))
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0_u32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct CounterEntry {
+    seq: u32,
+    value: u64,
+}
+
+impl Versioned for CounterEntry {
+    const VERSION: u8 = 1;
+}
+
+/// An append-only log of a monotonic counter, spread over `N` storage slots.
+///
+/// Every append goes to the next slot, so the flash wear is distributed over all slots
+/// and an interrupted write can only corrupt the newest entry, never the previous one.
+pub struct CounterLog<'a, const N: usize> {
+    prefix: &'a str,
+    seq: u32,
+    value: Option<u64>,
+}
+
+impl<'a, const N: usize> CounterLog<'a, N> {
+    pub const fn new(prefix: &'a str) -> Self {
+        Self {
+            prefix,
+            seq: 0,
+            value: None,
+        }
+    }
+
+    pub fn value(&self) -> Option<u64> {
+        self.value
+    }
+
+    pub fn load<S>(&mut self, storage: &S) -> Result<Option<u64>, StorageError<S::Error>>
+    where
+        S: Storage,
+    {
+        let mut latest: Option<CounterEntry> = None;
+
+        for slot in 0..N {
+            match load::<_, CounterEntry>(storage, &self.key(slot)) {
+                Ok(Some(entry)) => {
+                    if latest.map(|latest| entry.seq > latest.seq).unwrap_or(true) {
+                        latest = Some(entry);
+                    }
+                }
+                Ok(None) => (),
+                Err(StorageError::Storage(e)) => return Err(StorageError::Storage(e)),
+                Err(e) => warn!(
+                    "Skipping slot {} of counter log {}: {:?}",
+                    slot, self.prefix, e
+                ),
+            }
+        }
+
+        self.seq = latest.map(|entry| entry.seq).unwrap_or(0);
+        self.value = latest.map(|entry| entry.value);
+
+        Ok(self.value)
+    }
+
+    pub fn append<S>(&mut self, storage: &mut S, value: u64) -> Result<(), StorageError<S::Error>>
+    where
+        S: Storage,
+    {
+        if self.value == Some(value) {
+            return Ok(());
+        }
+
+        let seq = self.seq.wrapping_add(1);
+
+        store(
+            storage,
+            &self.key(seq as usize % N),
+            &CounterEntry { seq, value },
+        )?;
+
+        self.seq = seq;
+        self.value = Some(value);
+
+        Ok(())
+    }
+
+    fn key(&self, slot: usize) -> heapless::String<16> {
+        let mut key = heapless::String::new();
+
+        write!(&mut key, "{}-{}", self.prefix, slot).unwrap();
+
+        key
+    }
+}
+
+#[cfg(feature = "std")]
+pub mod file {
+    extern crate std;
+
+    use std::fs;
+    use std::io;
+    use std::path::PathBuf;
+
+    use super::Storage;
+
+    /// A storage keeping every key in a separate file under a root directory.
+    /// Meant for running the persistence logic on the host.
+    pub struct FileStorage {
+        root: PathBuf,
+    }
+
+    impl FileStorage {
+        pub fn new(root: impl Into<PathBuf>) -> io::Result<Self> {
+            let root = root.into();
+
+            fs::create_dir_all(&root)?;
+
+            Ok(Self { root })
+        }
+    }
+
+    impl Storage for FileStorage {
+        type Error = io::Error;
+
+        fn load<'a>(&self, key: &str, buf: &'a mut [u8]) -> Result<Option<&'a [u8]>, Self::Error> {
+            match fs::read(self.root.join(key)) {
+                Ok(data) => {
+                    if data.len() > buf.len() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "Record does not fit in the buffer",
+                        ));
+                    }
+
+                    buf[..data.len()].copy_from_slice(&data);
+
+                    Ok(Some(&buf[..data.len()]))
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            }
+        }
+
+        fn store(&mut self, key: &str, data: &[u8]) -> Result<(), Self::Error> {
+            let path = self.root.join(key);
+            let tmp_path = path.with_extension("tmp");
+
+            fs::write(&tmp_path, data)?;
+            fs::rename(tmp_path, path)
+        }
+
+        fn remove(&mut self, key: &str) -> Result<(), Self::Error> {
+            match fs::remove_file(self.root.join(key)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            }
+        }
+    }
+}
+
+/// A storage keeping the records in memory, for the host tests
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::collections::BTreeMap;
+    use std::string::{String, ToString};
+    use std::vec::Vec;
+
+    use super::Storage;
+
+    #[derive(Default)]
+    pub struct MemoryStorage(BTreeMap<String, Vec<u8>>);
+
+    impl MemoryStorage {
+        /// A storage holding only the given record, e.g. a fixture of an older version
+        pub fn with(key: &str, data: &[u8]) -> Self {
+            let mut storage = Self::default();
+            storage.store(key, data).unwrap();
+
+            storage
+        }
+
+        pub fn get(&self, key: &str) -> Option<&[u8]> {
+            self.0.get(key).map(Vec::as_slice)
+        }
+    }
+
+    impl Storage for MemoryStorage {
+        type Error = ();
+
+        fn load<'a>(&self, key: &str, buf: &'a mut [u8]) -> Result<Option<&'a [u8]>, Self::Error> {
+            match self.0.get(key) {
+                Some(data) if data.len() > buf.len() => Err(()),
+                Some(data) => {
+                    buf[..data.len()].copy_from_slice(data);
+
+                    Ok(Some(&buf[..data.len()]))
+                }
+                None => Ok(None),
+            }
+        }
+
+        fn store(&mut self, key: &str, data: &[u8]) -> Result<(), Self::Error> {
+            self.0.insert(key.to_string(), data.to_vec());
+
+            Ok(())
+        }
+
+        fn remove(&mut self, key: &str) -> Result<(), Self::Error> {
+            self.0.remove(key);
+
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::testing::MemoryStorage;
+    use super::*;
+
+    /// The sequence number and the value of the slot
+    fn entry(storage: &MemoryStorage, key: &str) -> Option<(u32, u64)> {
+        load::<_, CounterEntry>(storage, key)
+            .unwrap()
+            .map(|entry| (entry.seq, entry.value))
+    }
+
+    #[test]
+    fn counter_log_rotates_over_slots() {
+        let mut storage = MemoryStorage::default();
+        let mut log = CounterLog::<4>::new("log");
+
+        for value in 1..=6 {
+            log.append(&mut storage, value).unwrap();
+        }
+
+        // The sequence numbers start at 1, so the first entry goes to slot 1
+        assert_eq!(entry(&storage, "log-0"), Some((4, 4)));
+        assert_eq!(entry(&storage, "log-1"), Some((5, 5)));
+        assert_eq!(entry(&storage, "log-2"), Some((6, 6)));
+        assert_eq!(entry(&storage, "log-3"), Some((3, 3)));
+        assert_eq!(entry(&storage, "log-4"), None);
+
+        assert_eq!(CounterLog::<4>::new("log").load(&storage).unwrap(), Some(6));
+    }
+
+    #[test]
+    fn counter_log_skips_unchanged_values() {
+        let mut storage = MemoryStorage::default();
+        let mut log = CounterLog::<4>::new("log");
+
+        log.append(&mut storage, 7).unwrap();
+        log.append(&mut storage, 7).unwrap();
+
+        assert_eq!(entry(&storage, "log-1"), Some((1, 7)));
+        assert_eq!(entry(&storage, "log-2"), None);
+    }
+
+    #[test]
+    fn counter_log_recovers_from_torn_write() {
+        let mut storage = MemoryStorage::default();
+        let mut log = CounterLog::<4>::new("log");
+
+        for value in 1..=3 {
+            log.append(&mut storage, value).unwrap();
+        }
+
+        // A power loss in the middle of writing the newest entry
+        let torn: Vec<u8> = storage.get("log-3").unwrap().to_vec();
+        storage.store("log-3", &torn[..torn.len() - 2]).unwrap();
+
+        let mut log = CounterLog::<4>::new("log");
+        assert_eq!(log.load(&storage).unwrap(), Some(2));
+
+        // The torn slot is the next one to be written
+        log.append(&mut storage, 4).unwrap();
+
+        assert_eq!(entry(&storage, "log-3"), Some((3, 4)));
+        assert_eq!(CounterLog::<4>::new("log").load(&storage).unwrap(), Some(4));
+    }
+
+    #[test]
+    fn counter_log_recovers_from_crc_failure() {
+        let mut storage = MemoryStorage::default();
+        let mut log = CounterLog::<4>::new("log");
+
+        for value in 1..=3 {
+            log.append(&mut storage, value * 10).unwrap();
+        }
+
+        let mut corrupted: Vec<u8> = storage.get("log-3").unwrap().to_vec();
+        corrupted[VERSION_LEN] ^= 0xff;
+        storage.store("log-3", &corrupted).unwrap();
+
+        assert!(matches!(
+            load::<_, CounterEntry>(&storage, "log-3"),
+            Err(StorageError::Corrupted)
+        ));
+        assert_eq!(
+            CounterLog::<4>::new("log").load(&storage).unwrap(),
+            Some(20)
+        );
+    }
+
+    #[test]
+    fn counter_log_without_valid_slots_is_empty() {
+        let mut storage = MemoryStorage::default();
+
+        storage.store("log-0", &[0xff; 3]).unwrap();
+
+        assert_eq!(CounterLog::<4>::new("log").load(&storage).unwrap(), None);
+    }
+
+    #[test]
+    fn decodes_fixture() {
+        let storage = MemoryStorage::with("entry", include_bytes!("../fixtures/counter-v1.bin"));
+
+        assert_eq!(
+            load::<_, CounterEntry>(&storage, "entry").unwrap(),
+            Some(CounterEntry { seq: 3, value: 77 })
+        );
+    }
+
+    #[test]
+    fn rejects_crc_mismatch() {
+        let mut data = include_bytes!("../fixtures/counter-v1.bin").to_vec();
+        let last = data.len() - 1;
+        data[last] ^= 0x01;
+
+        let storage = MemoryStorage::with("entry", &data);
+
+        assert!(matches!(
+            load::<_, CounterEntry>(&storage, "entry"),
+            Err(StorageError::Corrupted)
+        ));
+    }
+
+    #[test]
+    fn ignores_unknown_versions() {
+        // Older, without a migration
+        let storage = MemoryStorage::with("entry", include_bytes!("../fixtures/counter-v0.bin"));
+        assert_eq!(load::<_, CounterEntry>(&storage, "entry").unwrap(), None);
+
+        // Newer, as written by a later firmware before a downgrade
+        let storage = MemoryStorage::with("entry", include_bytes!("../fixtures/counter-v9.bin"));
+        assert_eq!(load::<_, CounterEntry>(&storage, "entry").unwrap(), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn file_storage_round_trip() {
+        use std::fs;
+
+        use super::file::FileStorage;
+
+        let root = std::env::temp_dir().join(std::format!("ruwm-storage-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        let mut storage = FileStorage::new(&root).unwrap();
+        let value = CounterEntry { seq: 3, value: 77 };
+
+        assert_eq!(load::<_, CounterEntry>(&storage, "entry").unwrap(), None);
+
+        store(&mut storage, "entry", &value).unwrap();
+        assert_eq!(
+            load::<_, CounterEntry>(&storage, "entry").unwrap(),
+            Some(value)
+        );
+
+        // A record larger than the buffer of the caller is an error rather than a panic
+        let mut buf = [0_u8; 4];
+        assert!(storage.load("entry", &mut buf).is_err());
+
+        storage.remove("entry").unwrap();
+        assert_eq!(load::<_, CounterEntry>(&storage, "entry").unwrap(), None);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,145 @@
+use core::fmt::Debug;
+
+use log::{error, info};
+
+use embassy_futures::yield_now;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+
+use crate::keepalive::{self, Lease};
+use crate::state::State;
+
+pub const VERSION_MAX_LEN: usize = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UpdateCommand {
+    Check,
+    Update,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UpdateState {
+    Idle,
+    Checking,
+    UpToDate,
+    Available(heapless::String<VERSION_MAX_LEN>),
+    Updating(u8),
+    Complete,
+    Failed,
+}
+
+impl UpdateState {
+    pub fn is_busy(&self) -> bool {
+        matches!(self, Self::Checking | Self::Updating(_))
+    }
+}
+
+/// A source of firmware images.
+///
+/// The operations are blocking, so the update task should be spawned on an executor
+/// where stalling the other tasks for the duration of the download is acceptable.
+pub trait Ota {
+    type Error: Debug;
+
+    /// Returns the version of the available firmware, if it is newer than the running one
+    fn check(&mut self) -> Result<Option<heapless::String<VERSION_MAX_LEN>>, Self::Error>;
+
+    /// Downloads and activates the available firmware, reporting the progress in percents.
+    /// The new firmware is booted on the next restart or wakeup.
+    fn update(&mut self, progress: &mut dyn FnMut(u8)) -> Result<(), Self::Error>;
+}
+
+impl<T> Ota for &mut T
+where
+    T: Ota,
+{
+    type Error = T::Error;
+
+    fn check(&mut self) -> Result<Option<heapless::String<VERSION_MAX_LEN>>, Self::Error> {
+        (*self).check()
+    }
+
+    fn update(&mut self, progress: &mut dyn FnMut(u8)) -> Result<(), Self::Error> {
+        (*self).update(progress)
+    }
+}
+
+impl Ota for () {
+    type Error = core::convert::Infallible;
+
+    fn check(&mut self) -> Result<Option<heapless::String<VERSION_MAX_LEN>>, Self::Error> {
+        Ok(None)
+    }
+
+    fn update(&mut self, _progress: &mut dyn FnMut(u8)) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+pub static STATE: State<UpdateState> = State::new(
+    "UPDATE",
+    UpdateState::Idle,
+    &[&crate::keepalive::NOTIF, &crate::screen::UPDATE_STATE_NOTIF],
+);
+
+pub(crate) static COMMAND: Signal<CriticalSectionRawMutex, UpdateCommand> = Signal::new();
+
+pub async fn process(mut ota: impl Ota) {
+    loop {
+        let command = COMMAND.wait().await;
+
+        keepalive::acquire(Lease::Update, None);
+
+        run(&mut ota, command).await;
+
+        keepalive::release(Lease::Update);
+    }
+}
+
+/// Checking and updating block, so yield before each of them for the lease and the
+/// new state to take effect first
+async fn run(ota: &mut impl Ota, command: UpdateCommand) {
+    STATE.update(UpdateState::Checking);
+    yield_now().await;
+
+    let version = match ota.check() {
+        Ok(Some(version)) => version,
+        Ok(None) => {
+            STATE.update(UpdateState::UpToDate);
+            return;
+        }
+        Err(err) => {
+            error!("Checking for update failed: {:?}", err);
+
+            STATE.update(UpdateState::Failed);
+            return;
+        }
+    };
+
+    info!("Firmware {} is available", version);
+
+    if command == UpdateCommand::Check {
+        STATE.update(UpdateState::Available(version));
+        return;
+    }
+
+    STATE.update(UpdateState::Updating(0));
+    yield_now().await;
+
+    let result = ota.update(&mut |percentage| {
+        STATE.update(UpdateState::Updating(percentage));
+    });
+
+    match result {
+        Ok(()) => {
+            info!("Firmware {} installed", version);
+
+            STATE.update(UpdateState::Complete);
+        }
+        Err(err) => {
+            error!("Update failed: {:?}", err);
+
+            STATE.update(UpdateState::Failed);
+        }
+    }
+}
@@ -1,10 +1,12 @@
-use core::fmt::Debug;
+use core::cell::{Cell, RefCell};
+use core::fmt::{Debug, Write};
 use core::future::pending;
 
 use embassy_time::{Duration, Timer};
 
 use embassy_futures::select::{select, Either};
-use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, RawMutex};
+use embassy_sync::blocking_mutex::Mutex;
 use embassy_sync::signal::Signal;
 
 use embedded_hal::blocking::delay::DelayMs;
@@ -12,19 +14,24 @@ use embedded_hal::digital::v2::OutputPin;
 
 use channel_bridge::notification::Notification;
 
+use crate::keepalive::{self, Lease};
+use crate::settings;
 use crate::state::State;
+use crate::storage::{self, Storage, Versioned};
 
 pub use crate::dto::valve::*;
 
-pub const TURN_TICKS: usize = 20;
 pub const TICK_DELAY: Duration = Duration::from_secs(1);
 
-pub static STATE: State<Option<ValveState>> = State::new(
+const STORAGE_KEY: &str = "valve";
+
+const VALVE_STATE: State<Option<ValveState>> = State::new(
     "VALVE",
     None,
     &[
         &crate::keepalive::NOTIF,
         &crate::emergency::VALVE_STATE_NOTIF,
+        &crate::alert::VALVE_STATE_NOTIF,
         &crate::screen::VALVE_STATE_NOTIF,
         &crate::mqtt::VALVE_STATE_NOTIF,
         &crate::web::VALVE_STATE_NOTIF,
@@ -32,13 +39,87 @@ pub static STATE: State<Option<ValveState>> = State::new(
     ],
 );
 
+const VALVE_COMMAND: Signal<CriticalSectionRawMutex, ValveCommand> = Signal::new();
+const VALVE_SPIN_WORKING: Signal<CriticalSectionRawMutex, Option<u8>> = Signal::new();
+
+/// Indexed by `ValveId`
+pub static STATES: [State<Option<ValveState>>; MAX_VALVES] = [VALVE_STATE; MAX_VALVES];
+
 static STATE_PERSIST_NOTIFY: Notification = Notification::new();
 
-pub(crate) static COMMAND: Signal<CriticalSectionRawMutex, ValveCommand> = Signal::new();
+static COMMANDS: [Signal<CriticalSectionRawMutex, ValveCommand>; MAX_VALVES] =
+    [VALVE_COMMAND; MAX_VALVES];
+
+static SPIN_COMMANDS: [Signal<CriticalSectionRawMutex, ValveCommand>; MAX_VALVES] =
+    [VALVE_COMMAND; MAX_VALVES];
+static SPIN_WORKING: [Signal<CriticalSectionRawMutex, Option<u8>>; MAX_VALVES] =
+    [VALVE_SPIN_WORKING; MAX_VALVES];
+
+/// The configurations of the valves being controlled
+static CONFIGS: Mutex<CriticalSectionRawMutex, Cell<[Option<ValveConfig>; MAX_VALVES]>> =
+    Mutex::new(Cell::new([None; MAX_VALVES]));
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ValveConfig {
+    /// The ticks a full turn takes; the valve turn ticks setting if `None`
+    pub turn_ticks: Option<u32>,
+    /// Whether the position is kept over restarts
+    pub persist: bool,
+}
+
+impl ValveConfig {
+    pub const fn new() -> Self {
+        Self {
+            turn_ticks: None,
+            persist: true,
+        }
+    }
+}
+
+impl Default for ValveConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The number of valves being controlled
+pub fn valves() -> usize {
+    CONFIGS.lock(|configs| configs.get().iter().flatten().count())
+}
+
+/// The ids of the valves being controlled, which need not be consecutive
+pub fn configured() -> impl Iterator<Item = ValveId> {
+    let configs = CONFIGS.lock(Cell::get);
+
+    (0..MAX_VALVES)
+        .filter(move |valve| configs[*valve].is_some())
+        .map(|valve| valve as ValveId)
+}
 
-static SPIN_COMMAND: Signal<CriticalSectionRawMutex, ValveCommand> = Signal::new();
-static SPIN_WORKING: Signal<CriticalSectionRawMutex, Option<u8>> = Signal::new();
+pub fn config(valve: ValveId) -> Option<ValveConfig> {
+    CONFIGS.lock(|configs| configs.get()[valve as usize])
+}
+
+/// Registers the valve before it is processed, so that it takes commands and gets restored
+pub fn configure(valve: ValveId, config: ValveConfig) {
+    CONFIGS.lock(|configs| {
+        let mut all = configs.get();
+        all[valve as usize] = Some(config);
+
+        configs.set(all);
+    });
+}
 
+/// Sends the command to the given valve, or to all valves if `None`
+pub fn command(valve: Option<ValveId>, command: ValveCommand) {
+    for index in configured() {
+        if valve.map(|valve| valve == index).unwrap_or(true) {
+            COMMANDS[index as usize].signal(command);
+        }
+    }
+}
+
+/// Closes the main valve synchronously, before the valves are configured
 pub fn emergency_close(
     power_pin: &mut impl OutputPin<Error = impl Debug>,
     open_pin: &mut impl OutputPin<Error = impl Debug>,
@@ -49,36 +130,60 @@ pub fn emergency_close(
 
     start_spin(Some(ValveCommand::Close), power_pin, open_pin, close_pin);
 
-    delay.delay_ms((TICK_DELAY.as_secs() * 1000 * TURN_TICKS as u64) as u32);
+    let turn_ticks = settings::STATE.get().valve_turn_ticks;
+
+    delay.delay_ms(TICK_DELAY.as_secs() as u32 * 1000 * turn_ticks);
 
     start_spin(None, power_pin, open_pin, close_pin);
 
     log::error!("End: emergency closing valve due to ULP wakeup");
 }
 
-pub async fn process() {
+pub async fn process(
+    valve: ValveId,
+    power_pin: impl OutputPin<Error = impl Debug>,
+    open_pin: impl OutputPin<Error = impl Debug>,
+    close_pin: impl OutputPin<Error = impl Debug>,
+    config: ValveConfig,
+) {
+    select(
+        process_commands(valve),
+        spin(valve, power_pin, open_pin, close_pin, config),
+    )
+    .await;
+}
+
+async fn process_commands(valve: ValveId) {
+    let valve_state = &STATES[valve as usize];
+    let spin_command = &SPIN_COMMANDS[valve as usize];
+
     loop {
         let current_state = {
-            match select(COMMAND.wait(), SPIN_WORKING.wait()).await {
+            match select(
+                COMMANDS[valve as usize].wait(),
+                SPIN_WORKING[valve as usize].wait(),
+            )
+            .await
+            {
                 Either::First(command) => match command {
                     ValveCommand::Open => {
-                        let state = STATE.get();
+                        let state = valve_state.get();
 
                         if !matches!(state, Some(ValveState::Open) | Some(ValveState::Opening(_))) {
-                            SPIN_COMMAND.signal(ValveCommand::Open);
+                            spin_command.signal(ValveCommand::Open);
                             Some(ValveState::Opening(0))
                         } else {
                             state
                         }
                     }
                     ValveCommand::Close => {
-                        let state = STATE.get();
+                        let state = valve_state.get();
 
                         if !matches!(
                             state,
                             Some(ValveState::Closed) | Some(ValveState::Closing(_))
                         ) {
-                            SPIN_COMMAND.signal(ValveCommand::Close);
+                            spin_command.signal(ValveCommand::Close);
                             Some(ValveState::Closing(0))
                         } else {
                             state
@@ -86,7 +191,7 @@ pub async fn process() {
                     }
                 },
                 Either::Second(progress) => {
-                    let state = STATE.get();
+                    let state = valve_state.get();
 
                     if let Some(progress) = progress {
                         match state {
@@ -105,16 +210,19 @@ pub async fn process() {
             }
         };
 
-        STATE.update(current_state);
+        valve_state.update(current_state);
     }
 }
 
-pub async fn spin(
+async fn spin(
+    valve: ValveId,
     mut power_pin: impl OutputPin<Error = impl Debug>,
     mut open_pin: impl OutputPin<Error = impl Debug>,
     mut close_pin: impl OutputPin<Error = impl Debug>,
+    config: ValveConfig,
 ) {
     let mut current_command: Option<ValveCommand> = None;
+    let mut turn_ticks: usize = 0;
     let mut remaining_ticks: usize = 0;
 
     loop {
@@ -125,7 +233,7 @@ pub async fn spin(
             &mut close_pin,
         );
 
-        let command = SPIN_COMMAND.wait();
+        let command = SPIN_COMMANDS[valve as usize].wait();
 
         let timer = if current_command.is_some() {
             futures::future::Either::Left(Timer::after(TICK_DELAY))
@@ -135,18 +243,27 @@ pub async fn spin(
 
         match select(command, timer).await {
             Either::First(command) => {
+                if current_command.is_none() {
+                    keepalive::acquire(Lease::ValveTurn, None);
+                }
+
                 current_command = Some(command);
-                remaining_ticks = TURN_TICKS;
+                turn_ticks = config
+                    .turn_ticks
+                    .unwrap_or_else(|| settings::STATE.get().valve_turn_ticks)
+                    as usize;
+                remaining_ticks = turn_ticks;
             }
             Either::Second(_) => {
                 if remaining_ticks > 0 {
                     remaining_ticks -= 1;
                 } else {
                     current_command = None;
+                    keepalive::release(Lease::ValveTurn);
                 }
 
-                SPIN_WORKING.signal(if remaining_ticks > 0 {
-                    Some((100 - remaining_ticks * 100 / TURN_TICKS) as u8)
+                SPIN_WORKING[valve as usize].signal(if remaining_ticks > 0 {
+                    Some((100 - remaining_ticks * 100 / turn_ticks) as u8)
                 } else {
                     None
                 });
@@ -180,10 +297,69 @@ fn start_spin(
     };
 }
 
-pub async fn persist(mut persister: impl FnMut(Option<ValveState>)) {
+impl Versioned for Option<ValveState> {
+    const VERSION: u8 = 1;
+}
+
+/// The first valve keeps the key of the single valve devices
+fn key(valve: ValveId) -> heapless::String<16> {
+    let mut key = heapless::String::new();
+
+    if valve == 0 {
+        key.push_str(STORAGE_KEY).unwrap();
+    } else {
+        write!(&mut key, "{}-{}", STORAGE_KEY, valve).unwrap();
+    }
+
+    key
+}
+
+/// Restores the configured valves which keep their position over restarts
+pub fn restore(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>) {
+    for (valve, valve_state) in STATES.iter().enumerate() {
+        if config(valve as ValveId).map(|config| config.persist) != Some(true) {
+            continue;
+        }
+
+        if let Some(state) = storage::restore(storage, &key(valve as ValveId)) {
+            valve_state.set(state);
+        }
+    }
+}
+
+pub async fn persist(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>) {
+    let mut persisted: [Option<Option<ValveState>>; MAX_VALVES] = [None; MAX_VALVES];
+
     loop {
         STATE_PERSIST_NOTIFY.wait().await;
 
-        persister(STATE.get());
+        for valve in configured() {
+            let state = STATES[valve as usize].get();
+
+            if config(valve).map(|config| config.persist) == Some(true)
+                && persisted[valve as usize] != Some(state)
+            {
+                storage::persist(storage, &key(valve), &state);
+
+                persisted[valve as usize] = Some(state);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::testing::MemoryStorage;
+
+    use super::*;
+
+    #[test]
+    fn decodes_v1() {
+        let storage = MemoryStorage::with(STORAGE_KEY, include_bytes!("../fixtures/valve-v1.bin"));
+
+        assert_eq!(
+            storage::load::<_, Option<ValveState>>(&storage, STORAGE_KEY).unwrap(),
+            Some(Some(ValveState::Closing(40)))
+        );
     }
 }
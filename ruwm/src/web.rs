@@ -3,7 +3,7 @@ use core::cell::Cell;
 use embassy_sync::signal::Signal;
 use log::info;
 
-use embassy_futures::select::{select, select4};
+use embassy_futures::select::{select, select3, select4};
 use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex, RawMutex};
 use embassy_sync::blocking_mutex::Mutex;
 use embassy_sync::mutex::Mutex as AsyncMutex;
@@ -13,20 +13,37 @@ use edge_frame::dto::Role;
 use channel_bridge::asynch::*;
 use channel_bridge::notification::Notification;
 
+use crate::alert;
 use crate::battery;
+use crate::budget;
+use crate::clock;
+use crate::i18n;
+use crate::keepalive::{self, Lease};
+use crate::leak_sensor::{self, LeakSensorId};
+use crate::pairing;
+use crate::power;
+use crate::schedule;
 use crate::state::State;
-use crate::valve;
+use crate::valve::{self, ValveId};
 use crate::wm;
 
 pub use crate::dto::web::*;
 
 pub(crate) static VALVE_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static WM_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static WM_HEALTH_NOTIF: Notification = Notification::new();
+pub(crate) static LEAK_SENSOR_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static WM_STATS_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static BATTERY_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static BATTERY_HISTORY_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static REMAINING_TIME_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static MQTT_STATE_NOTIF: Notification = Notification::new();
 pub(crate) static WIFI_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static SCHEDULE_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static BUDGET_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static ALERTS_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static LANGUAGE_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static POWER_STATE_NOTIF: Notification = Notification::new();
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 enum AuthEvent {
@@ -56,7 +73,15 @@ where
         receiver,
         &VALVE_STATE_NOTIF,
         &WM_STATE_NOTIF,
+        &WM_HEALTH_NOTIF,
+        &LEAK_SENSOR_STATE_NOTIF,
         &BATTERY_STATE_NOTIF,
+        &BATTERY_HISTORY_STATE_NOTIF,
+        &SCHEDULE_STATE_NOTIF,
+        &BUDGET_STATE_NOTIF,
+        &ALERTS_STATE_NOTIF,
+        &LANGUAGE_STATE_NOTIF,
+        &POWER_STATE_NOTIF,
     )
     .await
     .unwrap();
@@ -67,7 +92,15 @@ pub async fn handle<S, R>(
     receiver: R,
     valve_state_notif: &Notification,
     wm_state_notif: &Notification,
+    wm_health_notif: &Notification,
+    leak_sensor_state_notif: &Notification,
     battery_state_notif: &Notification,
+    battery_history_state_notif: &Notification,
+    schedule_state_notif: &Notification,
+    budget_state_notif: &Notification,
+    alerts_state_notif: &Notification,
+    language_state_notif: &Notification,
+    power_state_notif: &Notification,
 ) -> Result<(), R::Error>
 where
     S: Sender<Data = WebEvent>,
@@ -80,22 +113,100 @@ where
 
     auth_signal.signal(AuthEvent::Connected);
 
+    let _lease = keepalive::hold(Lease::WebSession);
+
     select(
         receive(receiver, &role, &auth_signal),
         select4(
             process_auth_event(&sender, &auth_signal),
-            process_state_update(&sender, &role, &valve::STATE, valve_state_notif, |state| {
-                WebEvent::ValveState(state)
-            }),
-            process_state_update(&sender, &role, &wm::STATE, wm_state_notif, |state| {
-                WebEvent::WaterMeterState(state)
-            }),
-            process_state_update(
+            process_states_update(
                 &sender,
                 &role,
-                &battery::STATE,
-                battery_state_notif,
-                |state| WebEvent::BatteryState(state),
+                &valve::STATES,
+                valve::configured,
+                valve_state_notif,
+                |valve, state| WebEvent::ValveState(valve, state),
+            ),
+            select3(
+                process_states_update(
+                    &sender,
+                    &role,
+                    &wm::STATES,
+                    wm::configured,
+                    wm_state_notif,
+                    |meter, state| WebEvent::WaterMeterState(meter, state),
+                ),
+                process_states_update(
+                    &sender,
+                    &role,
+                    &wm::HEALTH,
+                    wm::configured,
+                    wm_health_notif,
+                    |meter, health| WebEvent::SensorHealth(meter, health),
+                ),
+                process_states_update(
+                    &sender,
+                    &role,
+                    &leak_sensor::STATES,
+                    || 0..leak_sensor::sensors() as u8,
+                    leak_sensor_state_notif,
+                    |sensor, state| WebEvent::LeakSensorState(sensor, state),
+                ),
+            ),
+            select4(
+                select(
+                    process_state_update(
+                        &sender,
+                        &role,
+                        &battery::STATE,
+                        battery_state_notif,
+                        |state| WebEvent::BatteryState(state),
+                    ),
+                    process_state_update(
+                        &sender,
+                        &role,
+                        &battery::HISTORY,
+                        battery_history_state_notif,
+                        |history| WebEvent::BatteryHistory(history),
+                    ),
+                ),
+                process_state_update(
+                    &sender,
+                    &role,
+                    &schedule::STATE,
+                    schedule_state_notif,
+                    |state| WebEvent::ScheduleState(state),
+                ),
+                process_state_update(
+                    &sender,
+                    &role,
+                    &budget::STATE,
+                    budget_state_notif,
+                    |state| WebEvent::BudgetState(state),
+                ),
+                select3(
+                    process_state_update(
+                        &sender,
+                        &role,
+                        &alert::STATE,
+                        alerts_state_notif,
+                        |state| WebEvent::AlertsState(state),
+                    ),
+                    process_state_update(
+                        &sender,
+                        &role,
+                        &i18n::LANGUAGE,
+                        language_state_notif,
+                        |language| WebEvent::LanguageState(language),
+                    ),
+                    process_state_update(
+                        &sender,
+                        &role,
+                        &power::STATE,
+                        power_state_notif,
+                        |state| WebEvent::PowerState(state),
+                    ),
+                ),
             ),
         ),
     )
@@ -119,12 +230,32 @@ where
         if let Some(request) = request {
             let new_auth_event = if request.role() <= role.lock(Cell::get) {
                 match request {
-                    WebRequest::ValveCommand(command) => {
-                        valve::COMMAND.signal(command);
+                    WebRequest::ValveCommand(valve, command) => {
+                        valve::command(Some(valve), command);
+                        None
+                    }
+                    WebRequest::WaterMeterCommand(meter, command) => {
+                        wm::command(Some(meter), command);
+                        None
+                    }
+                    WebRequest::ScheduleCommand(command) => {
+                        schedule::COMMAND.signal(command);
+                        None
+                    }
+                    WebRequest::BudgetConfig(config) => {
+                        budget::COMMAND.signal(config);
                         None
                     }
-                    WebRequest::WaterMeterCommand(command) => {
-                        wm::COMMAND.signal(command);
+                    WebRequest::AlertCommand(command) => {
+                        alert::COMMAND.signal(command);
+                        None
+                    }
+                    WebRequest::Language(language) => {
+                        i18n::LANGUAGE.update(language);
+                        None
+                    }
+                    WebRequest::TimeZone(time_zone) => {
+                        clock::TIME_ZONE.update(time_zone);
                         None
                     }
                     WebRequest::Authenticate(username, password) => {
@@ -138,6 +269,17 @@ where
                             Some(AuthEvent::AuthenticationFailed)
                         }
                     }
+                    WebRequest::Pair(code) => {
+                        if pairing::pair(code) {
+                            info!("[WS] Paired");
+
+                            Some(AuthEvent::Authenticated(Role::User))
+                        } else {
+                            info!("[WS] Pairing failed");
+
+                            Some(AuthEvent::AuthenticationFailed)
+                        }
+                    }
                     WebRequest::Logout => Some(AuthEvent::LoggedOut),
                 }
             } else {
@@ -174,23 +316,88 @@ where
 
         send_event(sender, web_event, event.role()).await?;
 
+        for valve in valve::configured() {
+            send_event(
+                sender,
+                WebEvent::ValveState(valve, valve::STATES[valve as usize].get()),
+                event.role(),
+            )
+            .await?;
+        }
+
+        for meter in wm::configured() {
+            send_event(
+                sender,
+                WebEvent::WaterMeterState(meter, wm::STATES[meter as usize].get()),
+                event.role(),
+            )
+            .await?;
+
+            send_event(
+                sender,
+                WebEvent::SensorHealth(meter, wm::HEALTH[meter as usize].get()),
+                event.role(),
+            )
+            .await?;
+        }
+
+        for sensor in 0..leak_sensor::sensors() {
+            send_event(
+                sender,
+                WebEvent::LeakSensorState(
+                    sensor as LeakSensorId,
+                    leak_sensor::STATES[sensor].get(),
+                ),
+                event.role(),
+            )
+            .await?;
+        }
+
         send_event(
             sender,
-            WebEvent::ValveState(valve::STATE.get()),
+            WebEvent::BatteryState(battery::STATE.get()),
             event.role(),
         )
         .await?;
 
         send_event(
             sender,
-            WebEvent::WaterMeterState(wm::STATE.get()),
+            WebEvent::BatteryHistory(battery::HISTORY.get()),
             event.role(),
         )
         .await?;
 
         send_event(
             sender,
-            WebEvent::BatteryState(battery::STATE.get()),
+            WebEvent::PowerState(power::STATE.get()),
+            event.role(),
+        )
+        .await?;
+
+        send_event(
+            sender,
+            WebEvent::ScheduleState(schedule::STATE.get()),
+            event.role(),
+        )
+        .await?;
+
+        send_event(
+            sender,
+            WebEvent::BudgetState(budget::STATE.get()),
+            event.role(),
+        )
+        .await?;
+
+        send_event(
+            sender,
+            WebEvent::AlertsState(alert::STATE.get()),
+            event.role(),
+        )
+        .await?;
+
+        send_event(
+            sender,
+            WebEvent::LanguageState(i18n::LANGUAGE.get()),
             event.role(),
         )
         .await?;
@@ -215,6 +422,43 @@ where
     }
 }
 
+/// Sends only the states which changed, as the meters or valves share one notification
+/// `configured` yields the indexes of the states in use
+async fn process_states_update<'a, S, T, I, const N: usize>(
+    sender: &AsyncMutex<impl RawMutex, S>,
+    role: &Mutex<impl RawMutex, Cell<Role>>,
+    states: &[State<'a, T>; N],
+    configured: fn() -> I,
+    state_notif: &Notification,
+    to_web_event: impl Fn(u8, T) -> WebEvent,
+) -> Result<(), S::Error>
+where
+    S: Sender<Data = WebEvent>,
+    T: Clone + PartialEq,
+    I: Iterator<Item = u8>,
+{
+    let mut sent: [Option<T>; N] = core::array::from_fn(|_| None);
+
+    loop {
+        state_notif.wait().await;
+
+        for index in configured() {
+            let state = states[index as usize].get();
+
+            if sent[index as usize].as_ref() != Some(&state) {
+                send_event(
+                    sender,
+                    to_web_event(index, state.clone()),
+                    role.lock(Cell::get),
+                )
+                .await?;
+
+                sent[index as usize] = Some(state);
+            }
+        }
+    }
+}
+
 async fn send_event<S>(
     sender: &AsyncMutex<impl RawMutex, S>,
     event: WebEvent,
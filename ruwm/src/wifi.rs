@@ -2,27 +2,104 @@ use core::fmt::Debug;
 
 use serde::{Deserialize, Serialize};
 
-use embassy_futures::select::{select, Either};
+use log::info;
+
+use embassy_futures::select::{select3, Either3};
+use embassy_time::{Duration, Instant, Timer};
 
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::signal::Signal;
 
-use embedded_svc::wifi::{Configuration, Wifi as WifiTrait};
+use embedded_svc::wifi::{
+    AccessPointConfiguration, AuthMethod, ClientConfiguration, Configuration, Wifi as WifiTrait,
+};
 
 use channel_bridge::asynch::Receiver;
 
+use crate::keepalive::{self, Lease};
 use crate::state::State;
 
+/// The SSID of the open access point the device starts while provisioning
+pub const PROVISIONING_SSID: &str = "ruwm-setup";
+
+const INFO_POLL: Duration = Duration::from_secs(60);
+const PROVISIONING_TIMEOUT: Duration = Duration::from_secs(60 * 10);
+
 #[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub enum WifiCommand {
     SetConfiguration(Configuration),
+    /// Starts the provisioning access point, while still using the current credentials
+    Provision,
+    /// Starts the provisioning access point and forgets the current credentials
+    Reprovision,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct WifiState {
+    pub connected: Option<bool>,
+    pub ssid: heapless::String<32>,
+    pub ip: Option<[u8; 4]>,
+    pub rssi: Option<i8>,
+    pub provisioning: bool,
+}
+
+impl WifiState {
+    pub const fn new() -> Self {
+        Self {
+            connected: None,
+            ssid: heapless::String::new(),
+            ip: None,
+            rssi: None,
+            provisioning: false,
+        }
+    }
+
+    /// The signal strength in percents: -100 dBm or less is 0%, -50 dBm or more is 100%
+    pub fn strength(&self) -> Option<u8> {
+        (self.connected == Some(true)).then(|| {
+            self.rssi
+                .map(|rssi| ((rssi as i16 + 100) * 2).clamp(0, 100) as u8)
+                .unwrap_or(100)
+        })
+    }
 }
 
-pub static STATE: State<Option<bool>> = State::new(
+/// Link details which are not covered by the `Wifi` trait
+pub trait WifiInfo {
+    fn rssi(&self) -> Option<i8>;
+
+    fn ip(&self) -> Option<[u8; 4]>;
+}
+
+impl<T> WifiInfo for &T
+where
+    T: WifiInfo,
+{
+    fn rssi(&self) -> Option<i8> {
+        (*self).rssi()
+    }
+
+    fn ip(&self) -> Option<[u8; 4]> {
+        (*self).ip()
+    }
+}
+
+impl WifiInfo for () {
+    fn rssi(&self) -> Option<i8> {
+        None
+    }
+
+    fn ip(&self) -> Option<[u8; 4]> {
+        None
+    }
+}
+
+pub static STATE: State<WifiState> = State::new(
     "WIFI",
-    None,
+    WifiState::new(),
     &[
         &crate::keepalive::NOTIF,
+        &crate::alert::WIFI_STATE_NOTIF,
         &crate::screen::WIFI_STATE_NOTIF,
         &crate::mqtt::WIFI_STATE_NOTIF,
         &crate::web::WIFI_STATE_NOTIF,
@@ -31,18 +108,117 @@ pub static STATE: State<Option<bool>> = State::new(
 
 pub(crate) static COMMAND: Signal<CriticalSectionRawMutex, WifiCommand> = Signal::new();
 
+/// Completes the provisioning with the credentials entered by the user
+pub fn provision(ssid: &str, password: &str) {
+    COMMAND.signal(WifiCommand::SetConfiguration(Configuration::Client(
+        ClientConfiguration {
+            ssid: ssid.into(),
+            password: password.into(),
+            auth_method: if password.is_empty() {
+                AuthMethod::None
+            } else {
+                Default::default()
+            },
+            ..Default::default()
+        },
+    )));
+}
+
 pub async fn process<D>(
     mut wifi: impl WifiTrait,
+    info: impl WifiInfo,
     mut state_changed_source: impl Receiver<Data = D>,
 ) {
+    let mut provisioning_started: Option<Instant> = None;
+
     loop {
-        match select(state_changed_source.recv(), COMMAND.wait()).await {
-            Either::First(_) => {
-                STATE.update(Some(wifi.is_connected().unwrap()));
+        match select3(
+            state_changed_source.recv(),
+            COMMAND.wait(),
+            Timer::after(INFO_POLL),
+        )
+        .await
+        {
+            Either3::First(_) | Either3::Third(_) => {
+                if provisioning_started
+                    .map(|started| started.elapsed() >= PROVISIONING_TIMEOUT)
+                    .unwrap_or(false)
+                {
+                    info!("Provisioning timed out");
+
+                    let conf = client_configuration(&wifi);
+                    wifi.set_configuration(&Configuration::Client(conf))
+                        .unwrap();
+
+                    provisioning_started = None;
+                    keepalive::end(Lease::Provisioning);
+                }
             }
-            Either::Second(command) => match command {
-                WifiCommand::SetConfiguration(conf) => wifi.set_configuration(&conf).unwrap(),
+            Either3::Second(command) => match command {
+                WifiCommand::SetConfiguration(conf) => {
+                    wifi.set_configuration(&conf).unwrap();
+
+                    if !matches!(conf, Configuration::AccessPoint(_)) {
+                        wifi.connect().unwrap();
+                    }
+
+                    if provisioning_started.take().is_some() {
+                        keepalive::end(Lease::Provisioning);
+                    }
+                }
+                WifiCommand::Provision | WifiCommand::Reprovision => {
+                    let conf = if command == WifiCommand::Provision {
+                        client_configuration(&wifi)
+                    } else {
+                        wifi.disconnect().unwrap();
+
+                        Default::default()
+                    };
+
+                    info!("Provisioning with access point {}", PROVISIONING_SSID);
+
+                    wifi.set_configuration(&Configuration::Mixed(
+                        conf,
+                        AccessPointConfiguration {
+                            ssid: PROVISIONING_SSID.into(),
+                            auth_method: AuthMethod::None,
+                            ..Default::default()
+                        },
+                    ))
+                    .unwrap();
+
+                    provisioning_started = Some(Instant::now());
+                    keepalive::acquire(Lease::Provisioning, Some(PROVISIONING_TIMEOUT));
+                }
             },
         }
+
+        update_state(&wifi, &info);
+    }
+}
+
+fn client_configuration(wifi: &impl WifiTrait) -> ClientConfiguration {
+    match wifi.get_configuration().unwrap() {
+        Configuration::Client(conf) | Configuration::Mixed(conf, _) => conf,
+        _ => Default::default(),
     }
 }
+
+fn update_state(wifi: &impl WifiTrait, info: &impl WifiInfo) {
+    let connected = wifi.is_connected().unwrap();
+
+    let conf = wifi.get_configuration().unwrap();
+
+    let ssid = match &conf {
+        Configuration::Client(conf) | Configuration::Mixed(conf, _) => conf.ssid.clone(),
+        _ => heapless::String::new(),
+    };
+
+    STATE.update(WifiState {
+        connected: Some(connected),
+        ssid,
+        ip: connected.then(|| info.ip()).flatten(),
+        rssi: connected.then(|| info.rssi()).flatten(),
+        provisioning: matches!(conf, Configuration::Mixed(_, _)),
+    });
+}
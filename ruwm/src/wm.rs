@@ -1,94 +1,571 @@
-use embassy_futures::select::select;
-use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use core::cell::{Cell, RefCell};
+use core::fmt::Write;
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, RawMutex};
+use embassy_sync::blocking_mutex::Mutex;
 use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
+
+use serde::Deserialize;
 
 use channel_bridge::notification::Notification;
 
+use crate::clock::{self, SECS_PER_DAY, SECS_PER_HOUR};
+use crate::error;
 use crate::pulse_counter::{PulseCounter, PulseWakeup};
+use crate::settings;
+use crate::sleep::{self, Activity};
 use crate::state::State;
+use crate::storage::{self, CounterLog, Storage, Versioned};
+use crate::valve::{self, ValveState, MAIN_VALVE};
 
 pub use crate::dto::water_meter::*;
 
-pub const FLASH_WRITE_CYCLE: usize = 20;
+pub const COUNTER_LOG_SLOTS: usize = 8;
+
+const STORAGE_KEY: &str = "wm";
+const LEGACY_STORAGE_KEY: &str = "wm-state";
+const COUNTER_LOG_KEY: &str = "wm-log";
+const HEALTH_STORAGE_KEY: &str = "wm-health";
 
-pub static STATE: State<WaterMeterState> = State::new(
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+const METER_STATE: State<WaterMeterState> = State::new(
     "WM",
     WaterMeterState::new(),
     &[
         &crate::keepalive::NOTIF,
         &crate::emergency::WM_STATE_NOTIF,
+        &crate::alert::WM_STATE_NOTIF,
         &crate::wm_stats::WM_STATE_NOTIF,
         &crate::screen::WM_STATE_NOTIF,
         &crate::mqtt::WM_STATE_NOTIF,
         &crate::web::WM_STATE_NOTIF,
         &STATE_PERSIST_NOTIFY,
-        &STATE_FLASH_NOTIFY,
     ],
 );
 
+const METER_HEALTH: State<SensorHealthState> = State::new(
+    "WM HEALTH",
+    SensorHealthState::new(),
+    &[
+        &crate::alert::WM_STATE_NOTIF,
+        &crate::mqtt::WM_STATE_NOTIF,
+        &crate::web::WM_HEALTH_NOTIF,
+        &STATE_PERSIST_NOTIFY,
+    ],
+);
+
+const METER_COMMAND: Signal<CriticalSectionRawMutex, WaterMeterCommand> = Signal::new();
+
+/// Indexed by `MeterId`
+pub static STATES: [State<WaterMeterState>; MAX_METERS] = [METER_STATE; MAX_METERS];
+pub static HEALTH: [State<SensorHealthState>; MAX_METERS] = [METER_HEALTH; MAX_METERS];
+
 static STATE_PERSIST_NOTIFY: Notification = Notification::new();
-static STATE_FLASH_NOTIFY: Notification = Notification::new();
 
-pub(crate) static COMMAND: Signal<CriticalSectionRawMutex, WaterMeterCommand> = Signal::new();
+static COMMANDS: [Signal<CriticalSectionRawMutex, WaterMeterCommand>; MAX_METERS] =
+    [METER_COMMAND; MAX_METERS];
+
+/// The edges counted by each meter since it was last armed or disarmed
+static ARMED_EDGES: Mutex<CriticalSectionRawMutex, Cell<[u64; MAX_METERS]>> =
+    Mutex::new(Cell::new([0; MAX_METERS]));
+
+/// The configurations of the meters being counted
+static CONFIGS: Mutex<CriticalSectionRawMutex, Cell<[Option<MeterConfig>; MAX_METERS]>> =
+    Mutex::new(Cell::new([None; MAX_METERS]));
+
+/// The plausibility checks of the pulses
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SensorHealthConfig {
+    /// More pulses than that within a minute cannot come from the meter
+    pub max_pulses_per_minute: u32,
+    /// The valve being open for that long without any pulses means the sensor is stuck or silent
+    pub quiet_days: u32,
+}
+
+impl SensorHealthConfig {
+    pub const fn new() -> Self {
+        Self {
+            max_pulses_per_minute: 100,
+            quiet_days: 7,
+        }
+    }
+}
+
+impl Default for SensorHealthConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct MeterConfig {
+    /// Millilitres per counted edge
+    pub edge_volume_ml: u32,
+    /// The edges counted while armed which make a leak; the leak threshold setting if `None`
+    pub leak_threshold: Option<u32>,
+    pub health: SensorHealthConfig,
+}
+
+impl MeterConfig {
+    pub const fn new() -> Self {
+        Self {
+            edge_volume_ml: 1000,
+            leak_threshold: None,
+            health: SensorHealthConfig::new(),
+        }
+    }
+}
+
+impl Default for MeterConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The number of meters being counted
+pub fn meters() -> usize {
+    CONFIGS.lock(|configs| configs.get().iter().flatten().count())
+}
+
+/// The ids of the meters being counted, which need not be consecutive
+pub fn configured() -> impl Iterator<Item = MeterId> {
+    let configs = CONFIGS.lock(Cell::get);
+
+    (0..MAX_METERS)
+        .filter(move |meter| configs[*meter].is_some())
+        .map(|meter| meter as MeterId)
+}
+
+pub fn config(meter: MeterId) -> Option<MeterConfig> {
+    CONFIGS.lock(|configs| configs.get()[meter as usize])
+}
+
+/// Sends the command to the given meter, or to all meters if `None`
+pub fn command(meter: Option<MeterId>, command: WaterMeterCommand) {
+    for (index, signal) in COMMANDS.iter().enumerate() {
+        if meter.map(|meter| meter as usize == index).unwrap_or(true) {
+            signal.signal(command);
+        }
+    }
+}
+
+/// Clears the backflow of all meters, which is otherwise kept until the meter is disarmed
+pub fn acknowledge_backflow() {
+    for state in &STATES {
+        state.update_with(|state| WaterMeterState {
+            backflow: false,
+            ..state
+        });
+    }
+}
+
+pub async fn process(
+    meter: MeterId,
+    pulse_counter: impl PulseCounter,
+    pulse_wakeup: impl PulseWakeup,
+    config: MeterConfig,
+) {
+    CONFIGS.lock(|configs| {
+        let mut all = configs.get();
+        all[meter as usize] = Some(config);
+
+        configs.set(all);
+    });
 
-pub async fn process(pulse_counter: impl PulseCounter, pulse_wakeup: impl PulseWakeup) {
     select(
-        process_pulses(pulse_counter),
-        process_commands(pulse_wakeup),
+        process_pulses(meter, pulse_counter, config),
+        process_commands(meter, pulse_wakeup),
     )
     .await;
 }
 
-async fn process_pulses(mut pulse_counter: impl PulseCounter) {
+async fn process_pulses(meter: MeterId, mut pulse_counter: impl PulseCounter, config: MeterConfig) {
+    let health_config = config.health;
+
+    // The reverse edges not yet made up by forward ones, so that water flowing
+    // back and forth is not counted twice
+    let mut unreturned_edges = 0;
+
+    let mut rate_since = Instant::now();
+    let mut rate_pulses = 0;
+    let mut runaway = false;
+
     loop {
-        let pulses = pulse_counter.take_pulses().await.unwrap();
+        let pulses = match select(
+            pulse_counter.take_pulses(),
+            Timer::after(HEALTH_CHECK_INTERVAL),
+        )
+        .await
+        {
+            Either::First(pulses) => pulses.unwrap(),
+            Either::Second(_) => 0,
+        };
 
-        if pulses > 0 {
-            STATE.update_with(|state| WaterMeterState {
-                edges_count: state.edges_count + pulses,
-                armed: state.armed,
-                leaking: state.armed,
-            });
+        // The rate is judged per minute, so a runaway lasts at least until the end of the minute
+        if Instant::now() - rate_since >= Duration::from_secs(60) {
+            runaway = rate_pulses > health_config.max_pulses_per_minute as u64;
+            rate_since = Instant::now();
+            rate_pulses = 0;
+        }
+
+        rate_pulses += pulses.unsigned_abs();
+        runaway = runaway || rate_pulses > health_config.max_pulses_per_minute as u64;
+
+        check_health(
+            &HEALTH[meter as usize],
+            pulses != 0,
+            runaway,
+            pulse_counter.sensor_active(),
+            &health_config,
+        );
+
+        if pulses != 0 {
+            sleep::mark(Activity::Flow);
+
+            let leak_threshold = config
+                .leak_threshold
+                .unwrap_or_else(|| settings::STATE.get().leak_threshold)
+                as u64;
+
+            count(meter, pulses, &mut unreturned_edges, leak_threshold);
         }
     }
 }
 
-async fn process_commands(mut pulse_wakeup: impl PulseWakeup) {
+fn count(meter: MeterId, pulses: i64, unreturned_edges: &mut u64, leak_threshold: u64) {
+    STATES[meter as usize].update_with(|state| {
+        let reverse_edges = if pulses < 0 { pulses.unsigned_abs() } else { 0 };
+
+        let forward_edges = if pulses > 0 {
+            let returned = (pulses as u64).min(*unreturned_edges);
+            *unreturned_edges -= returned;
+
+            pulses as u64 - returned
+        } else {
+            *unreturned_edges += reverse_edges;
+
+            0
+        };
+
+        let armed_edges = ARMED_EDGES.lock(|armed_edges| {
+            let mut all = armed_edges.get();
+
+            if state.armed {
+                all[meter as usize] += forward_edges + reverse_edges;
+            }
+
+            armed_edges.set(all);
+
+            all[meter as usize]
+        });
+
+        WaterMeterState {
+            edges_count: state.edges_count + forward_edges,
+            reverse_edges_count: state.reverse_edges_count + reverse_edges,
+            armed: state.armed,
+            leaking: state.armed && armed_edges >= leak_threshold,
+            // Latched, as a single reverse edge is easily missed otherwise
+            backflow: state.backflow || pulses < 0,
+        }
+    });
+}
+
+fn check_health(
+    health: &State<SensorHealthState>,
+    pulsed: bool,
+    runaway: bool,
+    sensor_active: Option<bool>,
+    config: &SensorHealthConfig,
+) {
+    let valve_open = valve::STATES[MAIN_VALVE as usize].get() == Some(ValveState::Open);
+
+    // Without a synchronized clock, only a runaway can be told
+    let now = clock::now();
+
+    health.update_with(|mut health| {
+        // Pulses and a closed valve restart the quiet period; an hour
+        // is precise enough and spares updates
+        let restart = health
+            .quiet_since
+            .zip(now)
+            .map(|(since, now)| (pulsed || !valve_open) && now >= since + SECS_PER_HOUR)
+            .unwrap_or(true);
+
+        if restart {
+            health.quiet_since = now;
+        }
+
+        let quiet = health
+            .quiet_since
+            .zip(now)
+            .map(|(since, now)| now >= since + config.quiet_days as u64 * SECS_PER_DAY)
+            .unwrap_or(false);
+
+        health.fault = if runaway {
+            Some(SensorFault::Runaway)
+        } else if quiet && !pulsed {
+            if sensor_active == Some(true) {
+                Some(SensorFault::Stuck)
+            } else {
+                Some(SensorFault::Silent)
+            }
+        } else {
+            None
+        };
+
+        health
+    });
+}
+
+async fn process_commands(meter: MeterId, mut pulse_wakeup: impl PulseWakeup) {
     loop {
-        let armed = COMMAND.wait().await == WaterMeterCommand::Arm;
+        let armed = COMMANDS[meter as usize].wait().await == WaterMeterCommand::Arm;
 
         pulse_wakeup.set_enabled(armed).unwrap();
 
-        STATE.update_with(|state| WaterMeterState {
-            edges_count: state.edges_count,
+        arm(meter, armed);
+    }
+}
+
+/// A leak is only told by the edges counted since the meter was last armed
+fn arm(meter: MeterId, armed: bool) {
+    STATES[meter as usize].update_with(|state| {
+        if state.armed != armed {
+            ARMED_EDGES.lock(|armed_edges| {
+                let mut all = armed_edges.get();
+                all[meter as usize] = 0;
+
+                armed_edges.set(all);
+            });
+        }
+
+        WaterMeterState {
             armed,
+            backflow: state.backflow && armed,
+            ..state
+        }
+    });
+}
+
+#[derive(Deserialize)]
+struct WaterMeterStateV1 {
+    edges_count: u64,
+    armed: bool,
+    leaking: bool,
+}
+
+impl From<WaterMeterStateV1> for WaterMeterState {
+    fn from(state: WaterMeterStateV1) -> Self {
+        Self {
+            edges_count: state.edges_count,
+            armed: state.armed,
             leaking: state.leaking,
-        });
+            ..Self::new()
+        }
     }
 }
 
-pub async fn persist(mut persister: impl FnMut(WaterMeterState)) {
-    loop {
-        STATE_PERSIST_NOTIFY.wait().await;
+impl Versioned for WaterMeterState {
+    const VERSION: u8 = 2;
 
-        persister(STATE.get());
+    fn migrate(version: u8, payload: &[u8]) -> Result<Option<Self>, postcard::Error> {
+        match version {
+            1 => storage::migrate_from::<WaterMeterStateV1, _>(payload),
+            _ => Ok(None),
+        }
     }
 }
 
-pub async fn flash(mut flasher: impl FnMut(WaterMeterState)) {
-    let mut cycle = 0;
+impl Versioned for SensorHealthState {
+    const VERSION: u8 = 1;
+}
 
-    loop {
-        STATE_FLASH_NOTIFY.wait().await;
+/// The storage key of the given meter; the first meter keeps the keys of the single meter devices
+fn key(base: &str, meter: MeterId) -> heapless::String<16> {
+    let mut key = heapless::String::new();
 
-        if cycle == 0 {
-            flasher(STATE.get());
+    if meter == 0 {
+        key.push_str(base).unwrap();
+    } else {
+        write!(&mut key, "{}-{}", base, meter).unwrap();
+    }
+
+    key
+}
+
+pub fn restore(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>) {
+    for meter in 0..MAX_METERS as MeterId {
+        let storage_key = key(STORAGE_KEY, meter);
+
+        let mut state: WaterMeterState = storage::restore(storage, &storage_key)
+            .or_else(|| {
+                (meter == 0)
+                    .then(|| storage::restore_legacy(storage, LEGACY_STORAGE_KEY, &storage_key))
+                    .flatten()
+            })
+            .unwrap_or_else(|| {
+                log::warn!("No WM {} state found in storage, assuming new meter", meter);
+
+                Default::default()
+            });
+
+        // The counter log is written on every pulse, so it is more recent than the state record
+        let counter_log_key = key(COUNTER_LOG_KEY, meter);
+        let mut counter_log = CounterLog::<COUNTER_LOG_SLOTS>::new(&counter_log_key);
+
+        if let Ok(Some(edges_count)) =
+            storage.lock(|storage| error::check!(counter_log.load(&*storage.borrow())))
+        {
+            state.edges_count = edges_count;
         }
 
-        cycle += 1;
+        STATES[meter as usize].set(state);
 
-        if cycle >= FLASH_WRITE_CYCLE {
-            cycle = 0;
+        if let Some(health) = storage::restore(storage, &key(HEALTH_STORAGE_KEY, meter)) {
+            HEALTH[meter as usize].set(health);
         }
     }
 }
+
+pub async fn persist(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>) {
+    let counter_log_keys: [_; MAX_METERS] =
+        core::array::from_fn(|meter| key(COUNTER_LOG_KEY, meter as MeterId));
+    let mut counter_logs: [_; MAX_METERS] = core::array::from_fn(|meter| {
+        CounterLog::<COUNTER_LOG_SLOTS>::new(&counter_log_keys[meter])
+    });
+    let mut persisted_states: [Option<WaterMeterState>; MAX_METERS] = [None; MAX_METERS];
+    let mut persisted_health: [Option<SensorHealthState>; MAX_METERS] = [None; MAX_METERS];
+
+    for counter_log in &mut counter_logs {
+        error::log_err!(storage.lock(|storage| counter_log.load(&*storage.borrow())));
+    }
+
+    loop {
+        STATE_PERSIST_NOTIFY.wait().await;
+
+        for meter in configured() {
+            let meter = meter as usize;
+
+            let state = STATES[meter].get();
+            let health = HEALTH[meter].get();
+
+            let counter_log = &mut counter_logs[meter];
+            let persisted_state = &mut persisted_states[meter];
+            let persisted_health = &mut persisted_health[meter];
+
+            storage.lock(|storage| {
+                let storage = &mut *storage.borrow_mut();
+
+                // Every edge is logged, so that none is lost on a power loss
+                error::log_err!(counter_log.append(storage, state.edges_count));
+
+                if persisted_state
+                    .map(|persisted| {
+                        persisted.armed != state.armed
+                            || persisted.leaking != state.leaking
+                            || persisted.backflow != state.backflow
+                            || persisted.reverse_edges_count != state.reverse_edges_count
+                    })
+                    .unwrap_or(true)
+                    && error::check!(storage::store(
+                        storage,
+                        &key(STORAGE_KEY, meter as MeterId),
+                        &state
+                    ))
+                    .is_ok()
+                {
+                    *persisted_state = Some(state);
+                }
+
+                if *persisted_health != Some(health)
+                    && error::check!(storage::store(
+                        storage,
+                        &key(HEALTH_STORAGE_KEY, meter as MeterId),
+                        &health
+                    ))
+                    .is_ok()
+                {
+                    *persisted_health = Some(health);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use crate::storage::testing::MemoryStorage;
+
+    use super::*;
+
+    const MIGRATED: WaterMeterState = WaterMeterState {
+        edges_count: 1234,
+        armed: true,
+        ..WaterMeterState::new()
+    };
+
+    #[test]
+    fn migrates_v1() {
+        let storage = MemoryStorage::with(STORAGE_KEY, include_bytes!("../fixtures/wm-v1.bin"));
+
+        assert_eq!(
+            storage::load::<_, WaterMeterState>(&storage, STORAGE_KEY).unwrap(),
+            Some(MIGRATED)
+        );
+    }
+
+    #[test]
+    fn migrates_legacy() {
+        let storage = Mutex::<NoopRawMutex, _>::new(RefCell::new(MemoryStorage::with(
+            LEGACY_STORAGE_KEY,
+            include_bytes!("../fixtures/wm-state-legacy.bin"),
+        )));
+
+        assert_eq!(
+            storage::restore_legacy::<WaterMeterState>(&storage, LEGACY_STORAGE_KEY, STORAGE_KEY),
+            Some(MIGRATED)
+        );
+
+        storage.lock(|storage| {
+            let storage = storage.borrow();
+
+            assert!(storage.get(LEGACY_STORAGE_KEY).is_none());
+            assert_eq!(
+                storage::load::<_, WaterMeterState>(&*storage, STORAGE_KEY).unwrap(),
+                Some(MIGRATED)
+            );
+        });
+    }
+
+    #[test]
+    fn rearming_forgets_edges() {
+        let meter = MAX_METERS as MeterId - 1;
+        let mut unreturned_edges = 0;
+
+        arm(meter, true);
+        count(meter, 2, &mut unreturned_edges, 3);
+
+        assert!(!STATES[meter as usize].get().leaking);
+
+        // Without pulses in between, the disarm is only seen by the commands
+        arm(meter, false);
+        arm(meter, true);
+        count(meter, 2, &mut unreturned_edges, 3);
+
+        assert!(!STATES[meter as usize].get().leaking);
+
+        // Arming an armed meter keeps counting
+        arm(meter, true);
+        count(meter, 1, &mut unreturned_edges, 3);
+
+        let state = STATES[meter as usize].get();
+
+        assert!(state.armed && state.leaking);
+        assert_eq!(state.edges_count, 5);
+    }
+}
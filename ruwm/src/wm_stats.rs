@@ -1,52 +1,258 @@
-use embassy_time::{Duration, Instant, Timer};
+use core::cell::RefCell;
+use core::fmt::Write;
+
+use embassy_time::{Duration, Timer};
 
 use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+
+use serde::Deserialize;
 
 use channel_bridge::notification::Notification;
 
-use crate::{state::*, wm};
+use crate::settings;
+use crate::storage::{self, Storage, Versioned};
+use crate::wm::{self, MAX_METERS};
+use crate::{clock, state::*};
 
 pub use crate::dto::water_meter_stats::*;
 
-pub static STATE: State<WaterMeterStatsState> = State::new(
+const STORAGE_KEY: &str = "wm-stats";
+
+const METER_STATE: State<WaterMeterStatsState> = State::new(
     "WM STATS",
     WaterMeterStatsState::new(),
     &[
         &crate::keepalive::NOTIF,
         &crate::screen::WM_STATS_STATE_NOTIF,
         &crate::web::WM_STATS_STATE_NOTIF,
+        &crate::budget::WM_STATS_STATE_NOTIF,
         &STATE_PERSIST_NOTIFY,
     ],
 );
 
+/// Indexed by `MeterId`
+pub static STATES: [State<WaterMeterStatsState>; MAX_METERS] = [METER_STATE; MAX_METERS];
+
 pub(crate) static WM_STATE_NOTIF: Notification = Notification::new();
+pub(crate) static CLOCK_STATE_NOTIF: Notification = Notification::new();
 
 static STATE_PERSIST_NOTIFY: Notification = Notification::new();
 
 pub async fn process() {
     loop {
-        let edges_count = match select(
-            WM_STATE_NOTIF.wait(),
-            Timer::after(Duration::from_secs(10) /*Duration::from_millis(200)*/),
-        )
-        .await
-        {
-            Either::First(_) => wm::STATE.get().edges_count,
-            Either::Second(_) => STATE.get().most_recent.edges_count,
-        };
+        let counted = matches!(
+            select(
+                select(WM_STATE_NOTIF.wait(), CLOCK_STATE_NOTIF.wait()),
+                Timer::after(Duration::from_secs(10) /*Duration::from_millis(200)*/),
+            )
+            .await,
+            Either::First(_)
+        );
+
+        // Statistics are aligned to the local calendar, so wait until the wall clock is known
+        if let Some(now) = clock::now() {
+            let time_zone = clock::TIME_ZONE.get();
+
+            for meter in wm::configured() {
+                let meter = meter as usize;
+                let stats = &STATES[meter];
+
+                let edges_count = if counted {
+                    wm::STATES[meter].get().edges_count
+                } else {
+                    stats.get().most_recent.edges_count
+                };
+
+                stats.update_with(|mut state| {
+                    state.update(edges_count, now, &time_zone);
+
+                    state
+                });
+            }
+        }
+    }
+}
+
+/// The layout of versions 1 and 2, which had no hourly history
+#[derive(Deserialize)]
+struct WaterMeterStatsStateV2 {
+    installation: FlowSnapshot,
+    most_recent: FlowSnapshot,
+    snapshots: [FlowSnapshot; FLOW_STATS_INSTANCES],
+    measurements: [Option<FlowMeasurement>; FLOW_STATS_INSTANCES],
+}
+
+impl From<WaterMeterStatsStateV2> for WaterMeterStatsState {
+    fn from(state: WaterMeterStatsStateV2) -> Self {
+        Self {
+            installation: state.installation,
+            most_recent: state.most_recent,
+            snapshots: state.snapshots,
+            measurements: state.measurements,
+            history: [0; HISTORY_LEN],
+        }
+    }
+}
+
+impl Versioned for WaterMeterStatsState {
+    const VERSION: u8 = 3;
+
+    fn migrate(version: u8, payload: &[u8]) -> Result<Option<Self>, postcard::Error> {
+        match version {
+            // Version 1 snapshots were timestamped with the uptime rather than the wall clock,
+            // so only the edges count at installation is worth keeping
+            1 => storage::migrate_from::<WaterMeterStatsStateV2, Self>(payload).map(|state| {
+                state.map(|state| {
+                    let mut migrated = WaterMeterStatsState::new();
+                    migrated.installation.edges_count = state.installation.edges_count;
+
+                    migrated
+                })
+            }),
+            2 => storage::migrate_from::<WaterMeterStatsStateV2, _>(payload),
+            _ => Ok(None),
+        }
+    }
+}
 
-        STATE.update_with(|mut state| {
-            state.update(edges_count, Instant::now().as_secs());
+/// The first meter keeps the key of the single meter devices
+fn key(meter: usize) -> heapless::String<16> {
+    let mut key = heapless::String::new();
 
-            state
-        });
+    if meter == 0 {
+        key.push_str(STORAGE_KEY).unwrap();
+    } else {
+        write!(&mut key, "{}-{}", STORAGE_KEY, meter).unwrap();
+    }
+
+    key
+}
+
+pub fn restore(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>) {
+    for (meter, stats) in STATES.iter().enumerate() {
+        if let Some(state) = storage::restore(storage, &key(meter)) {
+            stats.set(state);
+        }
     }
 }
 
-pub async fn persist(mut persister: impl FnMut(WaterMeterStatsState)) {
+pub async fn persist(storage: &Mutex<impl RawMutex, RefCell<impl Storage>>) {
+    let mut persisted: [Option<WaterMeterStatsState>; MAX_METERS] = core::array::from_fn(|_| None);
+
     loop {
         STATE_PERSIST_NOTIFY.wait().await;
 
-        persister(STATE.get());
+        let flash_write_cycle = settings::STATE.get().flash_write_cycle as u64;
+
+        for meter in wm::configured() {
+            let meter = meter as usize;
+            let state = STATES[meter].get();
+
+            // The most recent snapshot changes with every edge, so it is written less often
+            // to spare the flash; the edges themselves are safe in the counter log of the meter
+            let due = persisted[meter]
+                .as_ref()
+                .map(|persisted| {
+                    WaterMeterStatsState {
+                        most_recent: persisted.most_recent,
+                        ..state
+                    } != *persisted
+                        || state.most_recent.edges_count
+                            >= persisted.most_recent.edges_count + flash_write_cycle
+                })
+                .unwrap_or(true);
+
+            if due {
+                storage::persist(storage, &key(meter), &state);
+
+                persisted[meter] = Some(state);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::TimeZone;
+    use crate::storage::testing::MemoryStorage;
+
+    use super::*;
+
+    const T: u64 = 1_700_000_000;
+
+    #[test]
+    fn migrates_v1() {
+        let storage =
+            MemoryStorage::with(STORAGE_KEY, include_bytes!("../fixtures/wm-stats-v1.bin"));
+
+        let mut expected = WaterMeterStatsState::new();
+        expected.installation.edges_count = 10;
+
+        assert_eq!(
+            storage::load::<_, WaterMeterStatsState>(&storage, STORAGE_KEY).unwrap(),
+            Some(expected)
+        );
+    }
+
+    #[test]
+    fn keeps_migrated_installation() {
+        let storage =
+            MemoryStorage::with(STORAGE_KEY, include_bytes!("../fixtures/wm-stats-v1.bin"));
+
+        let mut state = storage::load::<_, WaterMeterStatsState>(&storage, STORAGE_KEY)
+            .unwrap()
+            .unwrap();
+        state.update(42, T, &TimeZone::UTC);
+
+        assert_eq!(state.installation, FlowSnapshot::new(T, 10));
+        assert_eq!(state.most_recent, FlowSnapshot::new(T, 42));
+    }
+
+    #[test]
+    fn migrates_v2() {
+        let storage =
+            MemoryStorage::with(STORAGE_KEY, include_bytes!("../fixtures/wm-stats-v2.bin"));
+
+        let mut measurements = [None; FLOW_STATS_INSTANCES];
+        measurements[0] = Some(FlowMeasurement::new(
+            FlowSnapshot::new(T - 300, 38),
+            FlowSnapshot::new(T, 40),
+        ));
+
+        assert_eq!(
+            storage::load::<_, WaterMeterStatsState>(&storage, STORAGE_KEY).unwrap(),
+            Some(WaterMeterStatsState {
+                installation: FlowSnapshot::new(T - DAY, 10),
+                most_recent: FlowSnapshot::new(T + 60, 42),
+                snapshots: [FlowSnapshot::new(T, 40); FLOW_STATS_INSTANCES],
+                measurements,
+                history: [0; HISTORY_LEN],
+            })
+        );
+    }
+
+    #[test]
+    fn skips_hours_without_updates() {
+        const HOUR: u64 = 60 * 60;
+
+        let mut state = WaterMeterStatsState::new();
+
+        state.update(0, T, &TimeZone::UTC);
+        state.update(10, T + HOUR, &TimeZone::UTC);
+
+        assert_eq!(state.history[HISTORY_LEN - 1], 10);
+
+        // Asleep for three hours
+        state.update(25, T + 4 * HOUR, &TimeZone::UTC);
+
+        assert_eq!(state.history[HISTORY_LEN - 4..], [10, 15, 0, 0]);
+        assert_eq!(state.history.iter().sum::<u64>(), 25);
+
+        // Asleep for longer than the history
+        state.update(30, T + 30 * HOUR, &TimeZone::UTC);
+
+        assert_eq!(state.history, [0; HISTORY_LEN]);
     }
 }
@@ -31,14 +31,28 @@ const NOTIF: Notification = Notification::new();
 
 static HANDLERS_VALVE_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] = [NOTIF; WS_MAX_CONNECTIONS];
 static HANDLERS_WM_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] = [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_WM_HEALTH_NOTIF: [Notification; WS_MAX_CONNECTIONS] = [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_LEAK_SENSOR_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
 static HANDLERS_WM_STATS_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
     [NOTIF; WS_MAX_CONNECTIONS];
 static HANDLERS_BATTERY_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
     [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_BATTERY_HISTORY_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
 static HANDLERS_REMAINING_TIME_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
     [NOTIF; WS_MAX_CONNECTIONS];
 static HANDLERS_MQTT_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] = [NOTIF; WS_MAX_CONNECTIONS];
 static HANDLERS_WIFI_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] = [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_SCHEDULE_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_BUDGET_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_ALERTS_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_LANGUAGE_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] =
+    [NOTIF; WS_MAX_CONNECTIONS];
+static HANDLERS_POWER_STATE_NOTIF: [Notification; WS_MAX_CONNECTIONS] = [NOTIF; WS_MAX_CONNECTIONS];
 
 struct WebHandler;
 
@@ -71,7 +85,15 @@ impl ws::AcceptorHandler for WebHandler {
                 receiver,
                 &HANDLERS_VALVE_STATE_NOTIF[index],
                 &HANDLERS_WM_STATE_NOTIF[index],
-                &HANDLERS_WM_STATS_STATE_NOTIF[index],
+                &HANDLERS_WM_HEALTH_NOTIF[index],
+                &HANDLERS_LEAK_SENSOR_STATE_NOTIF[index],
+                &HANDLERS_BATTERY_STATE_NOTIF[index],
+                &HANDLERS_BATTERY_HISTORY_STATE_NOTIF[index],
+                &HANDLERS_SCHEDULE_STATE_NOTIF[index],
+                &HANDLERS_BUDGET_STATE_NOTIF[index],
+                &HANDLERS_ALERTS_STATE_NOTIF[index],
+                &HANDLERS_LANGUAGE_STATE_NOTIF[index],
+                &HANDLERS_POWER_STATE_NOTIF[index],
             )
             .await
         }
@@ -92,6 +114,14 @@ pub async fn broadcast() {
             REMAINING_TIME_STATE_NOTIF.wait(),
             MQTT_STATE_NOTIF.wait(),
             WIFI_STATE_NOTIF.wait(),
+            SCHEDULE_STATE_NOTIF.wait(),
+            BUDGET_STATE_NOTIF.wait(),
+            ALERTS_STATE_NOTIF.wait(),
+            LANGUAGE_STATE_NOTIF.wait(),
+            BATTERY_HISTORY_STATE_NOTIF.wait(),
+            POWER_STATE_NOTIF.wait(),
+            WM_HEALTH_NOTIF.wait(),
+            LEAK_SENSOR_STATE_NOTIF.wait(),
         ])
         .await
         .1
@@ -103,6 +133,14 @@ pub async fn broadcast() {
             4 => &HANDLERS_REMAINING_TIME_STATE_NOTIF,
             5 => &HANDLERS_MQTT_STATE_NOTIF,
             6 => &HANDLERS_WIFI_STATE_NOTIF,
+            7 => &HANDLERS_SCHEDULE_STATE_NOTIF,
+            8 => &HANDLERS_BUDGET_STATE_NOTIF,
+            9 => &HANDLERS_ALERTS_STATE_NOTIF,
+            10 => &HANDLERS_LANGUAGE_STATE_NOTIF,
+            11 => &HANDLERS_BATTERY_HISTORY_STATE_NOTIF,
+            12 => &HANDLERS_POWER_STATE_NOTIF,
+            13 => &HANDLERS_WM_HEALTH_NOTIF,
+            14 => &HANDLERS_LEAK_SENSOR_STATE_NOTIF,
             _ => unreachable!(),
         };
 